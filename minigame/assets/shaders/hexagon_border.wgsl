@@ -3,78 +3,114 @@
 #import bevy_sprite::mesh2d_vertex_output::VertexOutput
 
 struct HexagonBorderMaterial {
-    color: vec4<f32>,
     border_color: vec4<f32>,
+    selected_border_color: vec4<f32>,
     border_width: f32,
+    selected_border_width: f32,
+    hex_size: f32,
+    grid_size: vec2<u32>,
+    flat_top: u32,
+    fog_strength: vec2<f32>, // 已探索和未探明地块的变暗程度
+    tint: vec4<f32>,         // 季节、昼夜和天气的整体色调
 };
 
 @group(2) @binding(0)
 var<uniform> material: HexagonBorderMaterial;
+// 每个像素对应一个地块，rgb为填充色，a通道的bit0表示选中，bit1-2为迷雾等级
+@group(2) @binding(1)
+var cell_states: texture_2d<f32>;
 
-// 尖顶六边形距离函数
+const SQRT3: f32 = 1.7320508; // √3
+
+// 尖顶六边形距离函数，p以外接圆半径归一化
 fn distance_to_edges(p: vec2<f32>) -> f32 {
-    // 尖顶六边形常数
-    let SQRT3 = 1.7320508; // √3
     let k = vec2<f32>(0.5, SQRT3 / 2.0); // 1/2, √3/2
-    
-    // // 坐标变换：将六边形的高度归一化为1（原算法高度为√3 / 2）
-    // let p_scaled = vec2<f32>(p.x * SQRT3 / 2.0, p.y);
-    
+
     // 取绝对值并对称处理
-    // let p_abs = abs(p_scaled);
     let p_abs = abs(p);
-    
+
     // 计算到六边形边的距离
     let d1 = p_abs.x; // 左右边距离
     let d2 = dot(p_abs, k); // 斜线边距离
-    
+
     return max(d1, d2) - SQRT3 / 2.0;
 }
 
+// 立方体坐标取整
+fn cube_round(frac: vec3<f32>) -> vec3<i32> {
+    var rounded = round(frac);
+    let diff = abs(rounded - frac);
+    if (diff.x > diff.y && diff.x > diff.z) {
+        rounded.x = -rounded.y - rounded.z;
+    } else if (diff.y > diff.z) {
+        rounded.y = -rounded.x - rounded.z;
+    }
+    return vec3<i32>(rounded);
+}
+
+// 世界坐标转奇行偏移坐标，与SpatialPartition::grid_to_world互逆
+fn world_to_cell(p: vec2<f32>) -> vec2<i32> {
+    let q = (SQRT3 / 3.0 * p.x - p.y / 3.0) / material.hex_size;
+    let r = (2.0 / 3.0 * p.y) / material.hex_size;
+    let cube = cube_round(vec3<f32>(q, r, -q - r));
+    let row = cube.y;
+    let col = cube.x + (row - (row & 1)) / 2;
+    return vec2<i32>(col, row);
+}
+
+fn cell_center(cell: vec2<i32>) -> vec2<f32> {
+    let x = material.hex_size * SQRT3 * (f32(cell.x) + 0.5 * f32(cell.y & 1));
+    let y = material.hex_size * 1.5 * f32(cell.y);
+    return vec2<f32>(x, y);
+}
+
 @fragment
 fn fragment(
     input: VertexOutput,
 ) -> @location(0) vec4<f32> {
-    // if (input.uv.x < 0.1) {
-    //     return vec4<f32>(0.0, 1.0, 0.0, 0.0);
-    // } else if (input.uv.x <= 0.9) {
-    //     return vec4<f32>(0.0, 0.0, 1.0, 0.0);
-    // } else {
-    //     return vec4<f32>(1.0, 0.0, 0.0, input.uv.y);
-    // }
-    
-    // 将UV从[0,1]映射到[-1,1]
-    let uv = (input.uv - 0.5) * 2.0;
-    
-    // 计算到六边形边的距离
-    let dist = distance_to_edges(uv);
+    // 平顶布局是尖顶布局沿对角线翻转的结果，交换xy后按尖顶布局计算
+    var world = input.world_position.xy;
+    if (material.flat_top != 0u) {
+        world = world.yx;
+    }
+    let cell = world_to_cell(world);
+    let size = vec2<i32>(material.grid_size);
+    if (any(cell < vec2<i32>(0)) || any(cell >= size)) {
+        discard;
+    }
+
+    let state = textureLoad(cell_states, cell, 0);
+    let flags = u32(round(state.a * 255.0));
+    let selected = (flags & 1u) != 0u;
+    let fog = flags >> 1u;
+    let border_width = select(material.border_width, material.selected_border_width, selected);
+    let border_color = select(material.border_color, material.selected_border_color, selected);
+
+    // 计算到所在六边形边的距离
+    let dist = distance_to_edges((world - cell_center(cell)) / material.hex_size);
 
     // 计算边框区域
-    let border_start = -material.border_width;
-    
-    // // 判断颜色
-    // if (dist > border_start && dist <= 0.0) {
-    //     return material.border_color;
-    // } else if (dist <= border_start) {
-    //     return material.color;
-    // }
-    
-    // // 透明背景
-    // return vec4(0.0, 0.0, 0.0, 0.0);
+    let border_start = -border_width;
 
      // 平滑边缘抗锯齿
     let antialias = fwidth(dist);
-    
+
     // 计算内部填充
     let inside = smoothstep(0.0, -antialias, dist);
-    
-    // 防止抗锯齿范围超出内部区域
-    // let safe_antialias = min(antialias, -border_start);
+
     let border = smoothstep(border_start - antialias, antialias, dist) * inside;
-    
+
     // 组合颜色
-    let fill_color = mix(vec4<f32>(0.0), material.color, inside);
-    let final_color = mix(fill_color, material.border_color, border);
-    
+    let fill_color = mix(vec4<f32>(0.0), vec4<f32>(state.rgb, 1.0), inside);
+    var final_color = mix(fill_color, border_color, border);
+
+    // 战争迷雾：已探索的地块稍暗，未探明的地块更暗
+    if (fog > 0u) {
+        let dim = select(material.fog_strength.x, material.fog_strength.y, fog > 1u);
+        final_color = vec4<f32>(final_color.rgb * (1.0 - dim), final_color.a);
+    }
+
+    final_color = vec4<f32>(final_color.rgb * material.tint.rgb, final_color.a);
+
     return final_color;
-}
\ No newline at end of file
+}
@@ -0,0 +1,119 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use minigame::core::grid::{
+    HexCellStates, MAX_MAP_SIZE, TARGET_FPS, render_grid_system, upload_cell_states_system,
+};
+use minigame::core::hex_grid::{
+    HexGridConfig, HexMapPosition, HexagonBorderMaterial, SpatialPartition,
+};
+use minigame::core::interaction::{MapCellColors, click_effect_system, selected_effect_system};
+use minigame::scenes::GameSceneRoot;
+
+use bevy::ecs::system::RunSystemOnce;
+use bevy::prelude::*;
+use std::time::{Duration, Instant};
+
+/// 检查平均帧时间时运行的帧数
+const FRAME_SAMPLES: u32 = 100;
+
+// 最大地图下地块状态的写入和上传开销，需要远小于目标帧率下的单帧时间
+fn bench_cell_states(c: &mut Criterion) {
+    let mut images = Assets::<Image>::default();
+    let mut states = HexCellStates::new(
+        MAX_MAP_SIZE,
+        MAX_MAP_SIZE,
+        Color::srgb(0.1, 0.55, 0.2),
+        Color::WHITE,
+        &mut images,
+    );
+    let size = MAX_MAP_SIZE as i32;
+
+    // 悬停移动到另一格：清除旧高亮并设置新高亮
+    let mut frame = 0;
+    c.bench_function("cell_states_hover_256x256", |b| {
+        b.iter(|| {
+            let old = HexMapPosition::new(frame % size, frame / size % size);
+            frame += 1;
+            let new = HexMapPosition::new(frame % size, frame / size % size);
+            states.set_overlay(&old, None);
+            states.set_overlay(&new, Some(Color::srgb(0.10, 0.80, 0.25)));
+            states.upload(&mut images);
+        })
+    });
+
+    // 整张地图的地形重绘，例如切换关卡或迷雾整体刷新
+    let mut toggle = false;
+    c.bench_function("cell_states_full_repaint_256x256", |b| {
+        b.iter(|| {
+            toggle = !toggle;
+            let color = if toggle { Color::BLACK } else { Color::WHITE };
+            for y in 0..size {
+                for x in 0..size {
+                    states.set_terrain(&HexMapPosition::new(x, y), color);
+                }
+            }
+            states.upload(&mut images);
+            black_box(states.has_changes())
+        })
+    });
+}
+
+// 悬停的地块每帧移动一格
+fn move_hover_system(
+    mut frame: Local<i32>,
+    colors: Res<MapCellColors>,
+    mut states: ResMut<HexCellStates>,
+) {
+    let size = MAX_MAP_SIZE as i32;
+    let old = HexMapPosition::new(*frame % size, *frame / size % size);
+    *frame += 1;
+    let new = HexMapPosition::new(*frame % size, *frame / size % size);
+    states.set_overlay(&old, None);
+    states.set_overlay(&new, Some(colors.hovered));
+}
+
+// 最大地图下一整帧的网格开销：生成网格后运行每帧都会执行的网格系统和变换传播，
+// 平均帧时间超出目标帧率下的单帧时间时失败
+fn bench_grid_frame(c: &mut Criterion) {
+    let mut app = App::new();
+    app.add_plugins((MinimalPlugins, TransformPlugin))
+        .init_resource::<Assets<Mesh>>()
+        .init_resource::<Assets<Image>>()
+        .init_resource::<Assets<HexagonBorderMaterial>>()
+        .init_resource::<MapCellColors>()
+        .insert_resource(SpatialPartition::new(HexGridConfig::new(
+            1.0,
+            MAX_MAP_SIZE,
+            MAX_MAP_SIZE,
+            1.0,
+        )))
+        .add_systems(
+            Update,
+            (
+                move_hover_system,
+                click_effect_system,
+                selected_effect_system,
+                upload_cell_states_system,
+            )
+                .chain(),
+        );
+    app.world_mut().spawn((GameSceneRoot, Transform::default()));
+    app.world_mut().run_system_once(render_grid_system).unwrap();
+    app.update();
+
+    c.bench_function("grid_frame_256x256", |b| b.iter(|| app.update()));
+
+    // 目标帧率下的单帧时间，例如60帧为16.7ms
+    let budget = Duration::from_secs_f64(1.0 / TARGET_FPS);
+    let start = Instant::now();
+    for _ in 0..FRAME_SAMPLES {
+        app.update();
+    }
+    let mean = start.elapsed() / FRAME_SAMPLES;
+    assert!(
+        mean < budget,
+        "grid frame takes {mean:?}, over the {budget:?} budget at {TARGET_FPS} fps"
+    );
+}
+
+criterion_group!(benches, bench_cell_states, bench_grid_frame);
+criterion_main!(benches);
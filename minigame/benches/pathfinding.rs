@@ -0,0 +1,47 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use minigame::core::dstar_lite::DStarLite;
+use minigame::core::hex_grid::HexMapPosition;
+
+const MAP_SIZE: i32 = 256;
+
+// 在地图中间每隔几行放置一道带缺口的墙
+fn is_wall(pos: &HexMapPosition) -> bool {
+    pos.x == MAP_SIZE / 2 && pos.y % 16 != 0
+}
+
+fn bench_dstar_lite(c: &mut Criterion) {
+    let start = HexMapPosition::new(2, 2);
+    let goal = HexMapPosition::new(MAP_SIZE - 3, MAP_SIZE - 3);
+
+    c.bench_function("dstar_lite_initial_plan_256x256", |b| {
+        b.iter(|| {
+            let mut planner = DStarLite::new(MAP_SIZE, MAP_SIZE, start, goal);
+            planner.compute_shortest_path(is_wall);
+            black_box(planner.cost())
+        })
+    });
+
+    // 初始规划完成后，沿路径前进一步并在路径上新增一个障碍，测量增量修复的耗时
+    let mut planned = DStarLite::new(MAP_SIZE, MAP_SIZE, start, goal);
+    planned.compute_shortest_path(is_wall);
+    let path = planned.path(is_wall).unwrap();
+    let next = path[1];
+    let obstacle = path[path.len() / 2];
+    let blocked = |p: &HexMapPosition| is_wall(p) || *p == obstacle;
+
+    c.bench_function("dstar_lite_repair_256x256", |b| {
+        b.iter_batched(
+            || planned.clone(),
+            |mut planner| {
+                planner.move_start(next);
+                planner.update_cells([obstacle].iter(), blocked);
+                planner.compute_shortest_path(blocked);
+                black_box(planner.cost())
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, bench_dstar_lite);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+use minigame::core::components::EntityType;
+use minigame::core::hex_grid::{HexGridConfig, HexMapPosition, SpatialPartition, hex_distance};
+
+use bevy::prelude::Entity;
+
+const VISION_RADIUS: i32 = 10;
+
+// 按固定密度（每7格一棵草）铺满地图，地图越大草的总数越多，局部密度不变
+fn build_partition(size: usize) -> SpatialPartition {
+    let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, size, size, 1.0));
+    let mut id = 0;
+    for y in 0..size as i32 {
+        for x in 0..size as i32 {
+            if (x + y * 3) % 7 == 0 {
+                let pos = HexMapPosition::new(x, y);
+                partition.insert_cache_entity(Entity::from_raw(id), &pos, EntityType::Grass);
+                id += 1;
+            }
+        }
+    }
+    partition
+}
+
+// 觅食目标选择：环形查找的耗时只取决于视野内的密度，全局排序随地图大小增长
+fn bench_forage_target(c: &mut Criterion) {
+    let mut group = c.benchmark_group("forage_target");
+    for size in [32usize, 64, 128, 256] {
+        let partition = build_partition(size);
+        let center = HexMapPosition::new(size as i32 / 2, size as i32 / 2);
+
+        group.bench_with_input(BenchmarkId::new("nearest_ring", size), &size, |b, _| {
+            b.iter(|| {
+                black_box(partition.nearest_of_type(
+                    &center,
+                    VISION_RADIUS,
+                    &EntityType::Grass,
+                    |_| true,
+                ))
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("global_sort", size), &size, |b, _| {
+            b.iter(|| {
+                let mut entities = partition.entities_by_type(&EntityType::Grass);
+                entities.retain(|e| hex_distance(&e.pos, &center) <= VISION_RADIUS);
+                entities.sort_by_key(|e| hex_distance(&e.pos, &center));
+                black_box(entities.first().cloned())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_forage_target);
+criterion_main!(benches);
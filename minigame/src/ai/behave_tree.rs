@@ -1,13 +1,25 @@
-use crate::core::components::EntityType;
+use crate::core::components::{EntityType, FieldOfView, Metabolism, MoveTo};
+use crate::core::economy::EcologyEvent;
 use crate::core::hex_grid::{EntityWithCoord, HexMapPosition, hex_distance};
+use crate::core::lod::{Sleeping, simulation_delta};
+use crate::core::metabolism::EnergyLedger;
+use crate::core::nutrient::CorpseEvent;
+use crate::core::player_action::release_reservation;
 use crate::core::systems::hex_grid::SpatialPartition;
+use crate::level::food_chain::EnergyTransfer;
+use crate::sprite::animation::{CLIP_EAT, SpriteAnimation, spawn_death_animation};
+use crate::sprite::sprite_mgr::SpriteManager;
 use crate::ui::Percentage;
 use bevy::color::palettes::css::*;
 use bevy::prelude::*;
 use bevy_behave::prelude::*;
-use pathfinding::prelude::*;
 use std::cmp::min;
 
+use super::pathfinding::{
+    DStarPlanner, PathAlgorithm, PathRequest, PathfindingQueue, PathfindingTask,
+};
+use super::potential_field::{PotentialField, PotentialFieldConfig, PotentialSources};
+
 // 探索方向（随机移动）偏好组件
 #[derive(Component, Debug, Clone)]
 pub struct MovementPreference {
@@ -39,21 +51,32 @@ pub struct ForageAction {
     pub food_entity_type: EntityType,
 }
 
+#[derive(Component, Debug, Clone, Default)]
+pub struct FleeAction;
+
 #[derive(Component, Debug, Default, Clone)]
 pub struct IdleAction {
     pub preference: MovementPreference,
     pub exploration: Exploration,
 }
 
+/// 物种觅食的对象
+pub fn food_of(entity_type: &EntityType) -> EntityType {
+    match entity_type {
+        EntityType::Fox => EntityType::Rabbit,
+        _ => EntityType::Grass,
+    }
+}
+
 pub fn get_ai_behave_tree(entity_type: EntityType) -> Tree<Behave> {
     let forage_subtree = behave! {
         Behave::Fallback => {
-            Behave::spawn_named("Forage Action", ForageAction { food_entity_type: EntityType::Grass}),
+            Behave::spawn_named("Forage Action", ForageAction { food_entity_type: food_of(&entity_type) }),
         }
     };
     let flee_subtree = behave! {
         Behave::Fallback => {
-            Behave::AlwaysFail,
+            Behave::spawn_named("Flee Action", FleeAction),
         }
     };
     let idle_subtree = behave! {
@@ -109,14 +132,13 @@ pub enum ActorState {
     Flee,
 }
 
-// #[derive(Percentage, Debug, Clone, Default, PartialEq)]
-// #[percentage(max = 10000)]
+/// 饱食度进度条，显示Metabolism中储存的能量占上限的比例
 #[derive(Component, Debug, Clone, Default, PartialEq, TypePath)]
-pub struct Satiety(pub i32);
+pub struct Satiety(pub f32);
 
 impl Percentage for Satiety {
     fn value(&self) -> f32 {
-        self.0 as f32 / 10000.0
+        self.0
     }
 }
 
@@ -129,10 +151,10 @@ pub struct AnimalActorBoard {
     pub path_buffer: Vec<HexMapPosition>,    // 预计算的路径缓冲区
     pub state: ActorState,                   // 当前行为状态
     pub idle_counter: u32,                   // 空闲计数器
-    pub move_cd_timer: Timer,                // 移动CD计时器
-    pub satiety: i32,                        // 饱食度:放大100倍来避免float类型计算
-    pub decay_faction: f32,                  // 饱食度衰减因子，表示每秒衰减的饱食度
+    pub move_cd_timer: Timer,                // 空闲时的行动CD计时器
+    pub move_speed: f32,                     // 移动速度，每秒移动的格子数
     pub path_cost: f32,                      // 路径代价（用于D*Lite）[2](@ref)
+    pub path_algorithm: PathAlgorithm,       // 寻路算法，按物种选择
     pub entity_type: EntityType,
 }
 
@@ -145,44 +167,161 @@ impl AnimalActorBoard {
     pub fn clear_forage_target(&mut self) {
         self.forage_target = None;
         self.move_target = None;
+        self.path_buffer.clear();
     }
 
+    /// 吃掉觅食目标，获得的能量由食物链的能量转化率决定，在觅食系统最后结算
     pub fn do_eat(&mut self) -> EntityWithCoord {
         let result = EntityWithCoord {
             entity: self.forage_target.unwrap(),
             pos: self.move_target.unwrap(),
         };
-        self.satiety += 5000;
         self.clear_forage_target();
         return result;
     }
 }
 
-// // 新增寻路请求队列（异步处理避免卡顿）
-// #[derive(Resource, Default)]
-// pub struct PathfindingQueue {
-//     requests: Vec<(Entity, UVec2, PathAlgorithm)>, // (实体, 目标点, 算法类型)
-// }
-
-// 算法选择策略
-#[derive(Debug, Clone, Copy)]
-pub enum PathAlgorithm {
-    DStarLite, // 长CD动物
-    APF,       // 短CD动物（人工势场法）
-    Hybrid,    // 中CD动物（混合策略）
+// 觅食者决策需要的组件，寻路规划器和势场只有对应算法的动物才会持有
+type ForageActorData = (
+    &'static mut AnimalActorBoard,
+    Option<&'static mut DStarPlanner>,
+    Option<&'static mut PotentialField>,
+    Has<PathfindingTask>,
+    Has<MoveTo>,
+    Option<&'static FieldOfView>,
+    Option<&'static Sleeping>,
+);
+
+// 逃跑和空闲行为需要的组件
+type WanderActorData = (
+    &'static mut AnimalActorBoard,
+    Has<MoveTo>,
+    Option<&'static Metabolism>,
+    Option<&'static FieldOfView>,
+    Option<&'static Sleeping>,
+);
+
+// 视野内的捕食者位置
+fn visible_predators(
+    fov: Option<&FieldOfView>,
+    partition: &SpatialPartition,
+    predators: &[EntityType],
+) -> Vec<HexMapPosition> {
+    let Some(fov) = fov else {
+        return Vec::new();
+    };
+    predators
+        .iter()
+        .flat_map(|predator| fov.entities_of(partition, predator))
+        .map(|e| e.pos)
+        .collect()
 }
 
+/// 视野内出现捕食者时向远离捕食者的方向移动，捕食者都离开视野后失败，交给觅食和空闲行为
+pub fn flee_action_system(
+    mut commands: Commands,
+    query: Query<&BehaveCtx, With<FleeAction>>,
+    mut actor_query: Query<WanderActorData>,
+    mut edible_query: Query<&mut EdibleEntity>,
+    partition: Res<SpatialPartition>,
+    field_config: Res<PotentialFieldConfig>,
+    time: Res<Time>,
+) {
+    for ctx in query.iter() {
+        let this_entity = ctx.target_entity();
+        let Ok((mut actor, moving, energy, fov, sleeping)) = actor_query.get_mut(this_entity)
+        else {
+            continue;
+        };
+        if simulation_delta(sleeping, &time).is_none() {
+            continue;
+        }
+
+        let predators = visible_predators(
+            fov,
+            &partition,
+            field_config.predators_of(&actor.entity_type),
+        );
+        if predators.is_empty() {
+            if actor.state == ActorState::Flee {
+                actor.state = ActorState::Idle;
+            }
+            commands.trigger(ctx.failure());
+            continue;
+        }
+
+        // 逃跑时放弃觅食目标，释放预占
+        actor.state = ActorState::Flee;
+        if let Some(target) = actor.forage_target {
+            if let Ok(mut edible) = edible_query.get_mut(target)
+                && edible.reserved_by == Some(this_entity)
+            {
+                edible.reserved_by = None;
+            }
+            actor.clear_forage_target();
+        }
+
+        if moving || energy.is_some_and(|e| !e.can_move()) {
+            continue;
+        }
+
+        // 选择离最近的捕食者最远的相邻格，相同时看离所有捕食者的总距离
+        let threat = |pos: &HexMapPosition| {
+            let nearest = predators.iter().map(|p| hex_distance(pos, p)).min();
+            let total: i32 = predators.iter().map(|p| hex_distance(pos, p)).sum();
+            (nearest.unwrap_or(i32::MAX), total)
+        };
+        let best = partition
+            .get_valid_neighbours(&actor.current_pos)
+            .into_iter()
+            .filter(|pos| !partition.is_occupied(pos))
+            .max_by_key(|pos| threat(pos));
+        if let Some(next_pos) = best
+            && threat(&next_pos) > threat(&actor.current_pos)
+        {
+            commands
+                .entity(this_entity)
+                .insert(MoveTo::step(next_pos, actor.move_speed));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn forage_action_system(
     mut commands: Commands,
     mut query: Query<(&BehaveCtx, &mut ForageAction)>,
-    mut actor_query: Query<(&mut Transform, &mut AnimalActorBoard)>,
+    mut actor_query: Query<ForageActorData>,
     mut target_query: Query<(Entity, &mut EdibleEntity)>,
     mut partition: ResMut<SpatialPartition>,
+    mut path_queue: ResMut<PathfindingQueue>,
+    field_config: Res<PotentialFieldConfig>,
     time: Res<Time>,
+    mut metabolism_q: Query<&mut Metabolism>,
+    energy_transfer: Res<EnergyTransfer>,
+    mut ledger: ResMut<EnergyLedger>,
+    mut ecology: EventWriter<EcologyEvent>,
+    mut corpses: EventWriter<CorpseEvent>,
+    prey_sprite_q: Query<(&SpriteAnimation, &Sprite, &Transform, &ChildOf)>,
+    sprite_manager: Res<SpriteManager>,
 ) {
+    let mut meals = Vec::new();
     for (ctx, action) in query.iter_mut() {
         let this_entity = ctx.target_entity();
-        if let Ok((mut transform, mut actor)) = actor_query.get_mut(this_entity) {
+        if let Ok((mut actor, mut planner, mut field, path_pending, moving, fov, sleeping)) =
+            actor_query.get_mut(this_entity)
+        {
+            let energy = metabolism_q.get(this_entity).ok();
+            // 休眠的动物只在自己的更新帧决策
+            if simulation_delta(sleeping, &time).is_none() {
+                continue;
+            }
+
+            // 看到捕食者时交给逃跑行为
+            let predators = field_config.predators_of(&actor.entity_type);
+            if !visible_predators(fov, &partition, predators).is_empty() {
+                actor.state = ActorState::Flee;
+            }
+
             match actor.state {
                 ActorState::Flee => {
                     // 检查状态，如果是Flee状态则退出觅食逻辑
@@ -190,7 +329,7 @@ pub fn forage_action_system(
                     continue;
                 }
                 _ => {
-                    if actor.satiety >= 8000 {
+                    if energy.is_some_and(|e| e.is_full()) {
                         actor.state = ActorState::Idle;
                         actor.clear_forage_target();
                         commands.trigger(ctx.failure());
@@ -201,19 +340,40 @@ pub fn forage_action_system(
                 }
             }
 
-            // 移动Cd未冷却时不能行动
-            if !actor.move_cd_timer.tick(time.delta()).finished() {
+            // 正在移动或者能量不足时不做新的决策
+            if moving || energy.is_some_and(|e| !e.can_move()) {
                 continue;
             }
 
-            actor.move_cd_timer.reset();
             // 对于已经有目标的要检查目标的预占对象是否是自己
             if let Some(target) = actor.forage_target {
-                if let Ok((_, edible)) = target_query.get(target) {
+                if let Ok((_, mut edible)) = target_query.get_mut(target) {
                     if let Some(reserved) = edible.reserved_by
                         && reserved != this_entity
                     {
                         actor.clear_forage_target();
+                    } else {
+                        // 猎物可能是会移动的动物，按目标当前所在的地块更新移动目标
+                        let current = match fov {
+                            Some(fov) => fov
+                                .entities_of(&partition, &action.food_entity_type)
+                                .into_iter()
+                                .find(|e| e.entity == target),
+                            None => partition.nearest_of_type(
+                                &actor.current_pos,
+                                i32::MAX,
+                                &action.food_entity_type,
+                                |e| e.entity == target,
+                            ),
+                        };
+                        match current {
+                            Some(found) => actor.move_target = Some(found.pos),
+                            None => {
+                                // 目标离开视野，放弃预占
+                                edible.reserved_by = None;
+                                actor.clear_forage_target();
+                            }
+                        }
                     }
                 }
             }
@@ -226,125 +386,256 @@ pub fn forage_action_system(
                 //     _ => panic!("Unknown forage type"),
                 // };
                 let food_type = action.food_entity_type.clone();
-                // actor.current_pos
-                let mut entities = partition.entities_by_type(&food_type);
-                if entities.is_empty() {
+                // 只能看到视野内的食物，从近到远找第一个没有被预占的
+                let unreserved = |e: &EntityWithCoord| {
+                    target_query
+                        .get(e.entity)
+                        .is_ok_and(|(_, edible)| edible.reserved_by.is_none())
+                };
+                let nearest = match fov {
+                    Some(fov) => fov
+                        .entities_of(&partition, &food_type)
+                        .into_iter()
+                        .find(unreserved),
+                    None => partition.nearest_of_type(
+                        &actor.current_pos,
+                        i32::MAX,
+                        &food_type,
+                        unreserved,
+                    ),
+                };
+                let Some(food) = nearest else {
                     // warn!("forage_action: No {food_type:?} to forage!");
                     actor.clear_forage_target();
                     continue;
-                }
+                };
 
-                // 根据距离排序
-                entities.sort_by(|a, b| {
-                    hex_distance(&a.pos, &actor.current_pos)
-                        .cmp(&hex_distance(&b.pos, &actor.current_pos))
-                });
-                // info!("sorted entities: {entities:?}");
-
-                for e in entities {
-                    if let Ok((_, mut edible)) = target_query.get_mut(e.entity) {
-                        if edible.reserved_by.is_none() {
-                            // warn!("forage_action: get forage {e:?}");
-                            edible.reserved_by = Some(this_entity.clone());
-                            actor.set_forage_target(e);
-                            break;
-                        }
-                    }
+                if let Ok((_, mut edible)) = target_query.get_mut(food.entity) {
+                    edible.reserved_by = Some(this_entity);
+                    actor.set_forage_target(food);
                 }
             }
 
             // 有觅食目标的时候，向目标移动
             if let Some(move_target) = actor.move_target {
-                let pref = partition.as_ref();
                 // 首先处理觅食者就站在食物上的情况
                 if actor.current_pos.eq(&move_target) {
-                    do_eat_and_despawn_food_entity(
+                    // 被吃掉的猎物在原位置播放死亡动画
+                    if let Some(target) = actor.forage_target
+                        && let Ok((animation, sprite, transform, parent)) =
+                            prey_sprite_q.get(target)
+                    {
+                        spawn_death_animation(
+                            &mut commands,
+                            &sprite_manager,
+                            animation,
+                            sprite,
+                            transform,
+                            parent,
+                        );
+                    }
+                    let food = do_eat_and_despawn_food_entity(
                         &mut commands,
-                        &target_query,
+                        &mut target_query,
                         &mut partition,
                         action.food_entity_type.clone(),
                         &mut actor,
+                        &mut ecology,
                     );
+                    meals.push((
+                        this_entity,
+                        actor.entity_type.clone(),
+                        food,
+                        action.food_entity_type.clone(),
+                    ));
+                    commands
+                        .entity(this_entity)
+                        .entry::<SpriteAnimation>()
+                        .and_modify(|mut animation| animation.play_now(CLIP_EAT));
                     commands.trigger(ctx.failure());
                     continue;
                 }
 
-                if let Some((path, _)) = astar(
-                    &actor.current_pos,
-                    |p| {
-                        pref.get_valid_neighbours(p)
-                            .into_iter()
-                            .map(|p| (p, 1))
-                            .collect::<Vec<_>>()
-                    },
-                    |p| hex_distance(p, &move_target),
-                    |p| *p == move_target,
-                ) {
-                    // info!("forage action: Path to forage target: {path:?}");
-                    if path.len() > 1 {
-                        //move to
-                        let next_pos = path[1];
-                        move_actor_to_next_pos(
-                            &mut actor,
-                            &mut transform,
-                            &next_pos,
-                            &mut partition,
-                        );
-                        if next_pos == move_target {
-                            do_eat_and_despawn_food_entity(
-                                &mut commands,
-                                &target_query,
-                                &mut partition,
-                                action.food_entity_type.clone(),
-                                &mut actor,
-                            );
-                            commands.trigger(ctx.failure());
+                let weights = field_config.weights(&actor.entity_type);
+                let mut path = None;
+
+                // 势场法只看相邻格，开销很低：APF动物总是优先使用，混合策略的动物在目标进入作用半径后使用
+                if let Some(field) = field.as_mut()
+                    && (actor.path_algorithm == PathAlgorithm::APF
+                        || hex_distance(&actor.current_pos, &move_target)
+                            <= weights.influence_radius)
+                {
+                    let mut sources = PotentialSources::gather(
+                        &partition,
+                        &actor.current_pos,
+                        weights.influence_radius,
+                        &action.food_entity_type,
+                        &actor.entity_type,
+                        field_config.predators_of(&actor.entity_type),
+                        this_entity,
+                        fov,
+                    );
+                    // 觅食目标不在作用半径内时同样产生引力
+                    if !sources.food.contains(&move_target) {
+                        sources.food.push(move_target);
+                    }
+                    path = field
+                        .steer(
+                            &actor.current_pos,
+                            &move_target,
+                            &sources,
+                            &weights,
+                            &partition,
+                        )
+                        .map(|next_pos| vec![next_pos]);
+                    if path.is_some() {
+                        actor.path_buffer.clear();
+                    }
+                }
+
+                // 持有增量规划器的动物每步修复路径，不走异步队列
+                if path.is_none()
+                    && let Some(planner) = planner.as_mut()
+                {
+                    let Some(next_pos) =
+                        planner.next_step(actor.current_pos, move_target, &partition)
+                    else {
+                        continue;
+                    };
+                    actor.path_cost = planner.cost().unwrap_or_default() as f32;
+                    path = Some(vec![next_pos]);
+                }
+
+                let path = match path {
+                    Some(path) => path,
+                    None => {
+                        // 缓存的路径不是通往当前目标的，或者下一步被阻挡时，路径失效
+                        let path_valid = actor.path_buffer.last() == Some(&move_target)
+                            && actor.path_buffer.first().is_some_and(|p| {
+                                partition.is_valid_position(p) && !partition.is_obstacle(p)
+                            });
+                        if !path_valid {
+                            actor.path_buffer.clear();
+                            if !path_pending
+                                && !path_queue.is_pending(this_entity)
+                                && !path_queue.in_cooldown(this_entity, &move_target)
+                            {
+                                path_queue.request(PathRequest {
+                                    entity: this_entity,
+                                    start: actor.current_pos,
+                                    goal: move_target,
+                                    algorithm: actor.path_algorithm,
+                                });
+                            }
+                            // 等待寻路结果
                             continue;
                         }
+                        // 整条路径交给移动系统，走完或者中途被阻挡后重新使用势场法
+                        if let Some(field) = field.as_mut() {
+                            field.finish_fallback();
+                        }
+                        std::mem::take(&mut actor.path_buffer)
                     }
+                };
+
+                // 交给移动系统沿路径移动，到达目标后的下一次决策中吃掉食物
+                if let Some(move_to) = MoveTo::new(path, actor.move_speed) {
+                    commands.entity(this_entity).insert(move_to);
                 }
             }
         }
     }
+
+    // 遍历结束后才结算进食：猎物的能量按食物链的转化率转移给捕食者，其余的损失掉
+    for (predator, predator_type, food, food_type) in meals {
+        // 被吃掉的猎物如果是动物，释放它预占的食物
+        release_reservation(
+            &mut actor_query
+                .transmute_lens::<&mut AnimalActorBoard>()
+                .query(),
+            &mut target_query.transmute_lens::<&mut EdibleEntity>().query(),
+            food.entity,
+        );
+        let prey_energy = metabolism_q
+            .get_mut(food.entity)
+            .map_or(0.0, |mut prey| prey.take());
+        let meal = energy_transfer.meal(&food_type, &predator_type, prey_energy);
+        let absorbed = metabolism_q
+            .get_mut(predator)
+            .map_or(0.0, |mut metabolism| metabolism.gain(meal));
+        ledger.transfer_loss += (prey_energy - absorbed) as f64;
+        // 没有被捕食者吸收的能量作为残骸留在原地
+        corpses.write(CorpseEvent {
+            pos: food.pos,
+            energy: prey_energy - absorbed,
+        });
+    }
 }
 
 // 执行吃掉食物并清理食物实体的逻辑
 fn do_eat_and_despawn_food_entity(
     commands: &mut Commands,
-    target_query: &Query<(Entity, &mut EdibleEntity)>,
+    target_query: &mut Query<(Entity, &mut EdibleEntity)>,
     partition: &mut SpatialPartition,
     food_type: EntityType,
     actor: &mut AnimalActorBoard,
-) {
+    ecology: &mut EventWriter<EcologyEvent>,
+) -> EntityWithCoord {
     // 修正AnimalActorBoard的数据
     let food = actor.do_eat();
     // 从SpatialPartition移除食物，移除实体的时候要先把数据从SpatialPartition中移除，才能移除实体。
-    partition.remove_entity(food.entity, &food.pos, food_type);
+    partition.remove_entity(food.entity, &food.pos, food_type.clone());
     // 销毁食物对应的实体
     if let Ok((entity, _)) = target_query.get(food.entity) {
         commands.entity(entity).despawn();
+        ecology.write(EcologyEvent::Predation {
+            predator: actor.entity_type.clone(),
+            prey: food_type.clone(),
+        });
+        ecology.write(EcologyEvent::Death(food_type));
     }
+    food
 }
 
 pub fn idle_action_system(
     mut commands: Commands,
     mut query: Query<(&BehaveCtx, &mut IdleAction)>,
-    mut board_query: Query<(&mut Transform, &mut AnimalActorBoard)>,
-    mut partition: ResMut<SpatialPartition>,
+    mut board_query: Query<WanderActorData>,
+    partition: Res<SpatialPartition>,
+    field_config: Res<PotentialFieldConfig>,
     time: Res<Time>,
 ) {
     for (ctx, mut action) in query.iter_mut() {
-        if let Ok((mut transform, mut actor)) = board_query.get_mut(ctx.target_entity()) {
-            // TODO 如果进入食物链捕食者视野范围，则进入逃离模式
+        if let Ok((mut actor, moving, energy, fov, sleeping)) =
+            board_query.get_mut(ctx.target_entity())
+        {
+            // 休眠的动物只在自己的更新帧决策，计时器按累计时间追帧
+            let Some(delta) = simulation_delta(sleeping, &time) else {
+                continue;
+            };
+
+            // 视野内出现捕食者，进入逃离模式
+            let predators = field_config.predators_of(&actor.entity_type);
+            if !visible_predators(fov, &partition, predators).is_empty() {
+                actor.state = ActorState::Flee;
+                commands.trigger(ctx.failure());
+                continue;
+            }
+
             // 如果进入饥饿临界值，进入觅食状态
-            if actor.satiety <= 5000 {
+            if energy.is_some_and(|e| e.is_hungry()) {
                 actor.state = ActorState::Foraging;
                 commands.trigger(ctx.failure());
                 continue;
             }
 
+            // 正在移动或者能量不足时原地等待
+            if moving || energy.is_some_and(|e| !e.can_move()) {
+                continue;
+            }
+
             // 移动cd未结束时不进行行动
-            if !actor.move_cd_timer.tick(time.delta()).finished() {
+            if !actor.move_cd_timer.tick(delta).finished() {
                 continue;
             }
 
@@ -407,8 +698,9 @@ pub fn idle_action_system(
                     base_position: actor.current_pos,
                 };
 
-                // partition.entity_move()
-                move_actor_to_next_pos(&mut actor, &mut transform, &target, &mut partition);
+                commands
+                    .entity(ctx.target_entity())
+                    .insert(MoveTo::step(target, actor.move_speed));
             } else {
                 // 对于有配置的，则进行随机偏移
                 action.exploration.steps_remaining -= 1;
@@ -444,24 +736,15 @@ pub fn idle_action_system(
                 }
                 if let Some(target_pos) = weighted_random_choice(&candidates) {
                     // info!("Next Move To: {:?}", &target_pos);
-                    move_actor_to_next_pos(&mut actor, &mut transform, &target_pos, &mut partition);
+                    commands
+                        .entity(ctx.target_entity())
+                        .insert(MoveTo::step(target_pos, actor.move_speed));
                 }
             }
         }
     }
 }
 
-fn move_actor_to_next_pos(
-    actor: &mut AnimalActorBoard,
-    transform: &mut Transform,
-    target_pos: &HexMapPosition,
-    partition: &mut SpatialPartition,
-) {
-    //TODO entity移动了，需要修改partition中的entities的数据
-    actor.current_pos = target_pos.clone();
-    transform.translation = partition.grid_to_world(&target_pos.to_vec2()) + Vec3::Z * 3.0;
-}
-
 pub fn onadd_idle_action(
     _trigger: Trigger<OnAdd, IdleAction>,
     _q: Query<&BehaveCtx, With<IdleAction>>,
@@ -525,18 +808,18 @@ fn weighted_random_choice(candidates: &[(HexMapPosition, f32)]) -> Option<HexMap
 pub fn render_gizmos(
     mut gizmos: Gizmos,
     query: Query<(&BehaveCtx, &mut IdleAction)>,
-    board_query: Query<(&Transform, &AnimalActorBoard)>,
+    board_query: Query<(&Transform, &AnimalActorBoard, Option<&Metabolism>)>,
     partition: Res<SpatialPartition>,
 ) {
     for (ctx, action) in query {
-        if let Ok((trans, _)) = board_query.get(ctx.target_entity()) {
+        if let Ok((trans, ..)) = board_query.get(ctx.target_entity()) {
             let end = trans.translation + action.preference.direction * 50.0;
             gizmos.arrow_2d(trans.translation.xy(), end.xy(), RED);
         }
     }
-    for (transform, board) in board_query {
+    for (transform, board, energy) in board_query {
         let location = transform.translation.xy();
-        if board.satiety <= 5000 {
+        if energy.is_some_and(|e| e.is_hungry()) {
             gizmos.circle_2d(location.clone(), 30.0, RED);
         }
 
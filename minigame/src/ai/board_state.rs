@@ -3,8 +3,9 @@ use crate::{
     ui::{Percentage, ProgressBarMaterial},
 };
 
-use super::behave_tree::AnimalActorBoard;
-use bevy::{math::ops::floor, prelude::*};
+use crate::core::components::Metabolism;
+use crate::core::lod::Sleeping;
+use bevy::prelude::*;
 
 #[derive(Resource, Default)]
 pub struct FrameCounter {
@@ -20,7 +21,7 @@ impl FrameCounter {
 }
 
 pub fn udpate_board_state_system(
-    mut query: Query<(&mut AnimalActorBoard, &Children)>,
+    query: Query<(&Metabolism, &Children, Option<&Sleeping>)>,
     mut f_counter: ResMut<FrameCounter>,
     mut pbar_q: Query<(&mut Satiety, &MeshMaterial2d<ProgressBarMaterial>)>,
     mut materials: ResMut<Assets<ProgressBarMaterial>>,
@@ -29,22 +30,21 @@ pub fn udpate_board_state_system(
     f_counter.elpased += time.delta_secs();
     f_counter.counter += 1;
 
+    // 能量由metabolism_system结算，这里每10帧同步一次进度条，休眠的动物不更新
     if f_counter.counter % 10 == 0 {
-        for (mut board, children) in query.iter_mut() {
-            // 修正饱食度数据
-            board.satiety -= floor(f_counter.elpased * board.decay_faction * 100f32) as i32;
-            board.satiety = i32::clamp(board.satiety, 0, 10000);
-
+        for (metabolism, children, sleeping) in query.iter() {
+            if sleeping.is_some() {
+                continue;
+            }
             // 修改动物们的饱食度进度条
             for child in children {
                 if let Ok((mut satiety, material)) = pbar_q.get_mut(*child) {
-                    satiety.0 = board.satiety;
+                    satiety.0 = metabolism.fraction();
                     materials.get_mut(material.id()).map(|m| {
                         m.value_and_dimensions.x = satiety.value();
                     });
                 }
             }
-            // info!("satiety:{}", board.satiety);
         }
 
         f_counter.reset();
@@ -1,5 +1,9 @@
 mod behave_tree;
 mod board_state;
+mod pathfinding;
+mod potential_field;
 
 pub use behave_tree::*;
 pub use board_state::*;
+pub use pathfinding::*;
+pub use potential_field::*;
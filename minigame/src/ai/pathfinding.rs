@@ -0,0 +1,406 @@
+//! 异步寻路请求队列
+//!
+//! 行为系统只负责提交寻路请求，请求按帧预算批量派发到AsyncComputeTaskPool上计算，
+//! 计算结果写回AnimalActorBoard::path_buffer，路径被阻挡时才重新请求。
+//! 地图快照只在地形的通行状态变化时重建，找不到路径的目标在冷却时间内不再重复请求。
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use bevy::tasks::{AsyncComputeTaskPool, Task, block_on, poll_once};
+use pathfinding::prelude::astar;
+
+use crate::ai::AnimalActorBoard;
+use crate::core::components::EntityType;
+use crate::core::dstar_lite::DStarLite;
+use crate::core::hex_grid::{CUBE_DIRECTIONS, HexMapPosition, SpatialPartition, hex_distance};
+
+/// 每帧默认最多派发的寻路请求数
+const DEFAULT_BUDGET_PER_FRAME: usize = 8;
+/// 寻路失败后，同一目标默认的重试冷却时间（秒）
+const DEFAULT_RETRY_COOLDOWN: f32 = 2.0;
+
+// 算法选择策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathAlgorithm {
+    #[default]
+    AStar,
+    DStarLite, // 长CD动物
+    APF,       // 短CD动物（人工势场法）
+    Hybrid,    // 中CD动物（混合策略）
+}
+
+impl PathAlgorithm {
+    /// 根据物种选择寻路算法
+    pub fn by_species(entity_type: &EntityType) -> Self {
+        match entity_type {
+            EntityType::Rabbit => PathAlgorithm::Hybrid,
+            EntityType::Fox => PathAlgorithm::APF,
+            _ => PathAlgorithm::AStar,
+        }
+    }
+
+    /// 是否需要持有增量寻路规划器
+    pub fn is_incremental(&self) -> bool {
+        matches!(self, PathAlgorithm::DStarLite | PathAlgorithm::Hybrid)
+    }
+
+    /// 是否在短距离内使用势场法移动
+    pub fn uses_potential_field(&self) -> bool {
+        matches!(self, PathAlgorithm::APF | PathAlgorithm::Hybrid)
+    }
+}
+
+/// 寻路请求
+#[derive(Debug, Clone)]
+pub struct PathRequest {
+    pub entity: Entity,
+    pub start: HexMapPosition,
+    pub goal: HexMapPosition,
+    pub algorithm: PathAlgorithm,
+}
+
+// 新增寻路请求队列（异步处理避免卡顿）
+#[derive(Resource)]
+pub struct PathfindingQueue {
+    requests: VecDeque<PathRequest>,
+    pub budget_per_frame: usize,
+    pub retry_cooldown: f32,
+    failed: HashMap<Entity, (HexMapPosition, f32)>, // 寻路失败的目标及剩余的冷却时间
+    grid: Option<(u32, Arc<PathGrid>)>,             // 地图快照及其对应的地形版本
+}
+
+impl Default for PathfindingQueue {
+    fn default() -> Self {
+        Self {
+            requests: VecDeque::new(),
+            budget_per_frame: DEFAULT_BUDGET_PER_FRAME,
+            retry_cooldown: DEFAULT_RETRY_COOLDOWN,
+            failed: HashMap::new(),
+            grid: None,
+        }
+    }
+}
+
+impl PathfindingQueue {
+    /// 提交寻路请求，同一实体只保留最新的一个请求
+    pub fn request(&mut self, request: PathRequest) {
+        if let Some(existing) = self
+            .requests
+            .iter_mut()
+            .find(|r| r.entity == request.entity)
+        {
+            *existing = request;
+        } else {
+            self.requests.push_back(request);
+        }
+    }
+
+    pub fn is_pending(&self, entity: Entity) -> bool {
+        self.requests.iter().any(|r| r.entity == entity)
+    }
+
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// 记录一次寻路失败，冷却时间内不再为同一目标发起请求
+    pub fn mark_failed(&mut self, entity: Entity, goal: HexMapPosition) {
+        self.failed.insert(entity, (goal, self.retry_cooldown));
+    }
+
+    pub fn in_cooldown(&self, entity: Entity, goal: &HexMapPosition) -> bool {
+        self.failed.get(&entity).is_some_and(|(g, _)| g == goal)
+    }
+
+    pub fn tick_cooldowns(&mut self, secs: f32) {
+        self.failed.retain(|_, (_, remaining)| {
+            *remaining -= secs;
+            *remaining > 0.0
+        });
+    }
+
+    /// 当前地形的地图快照，地形的通行状态变化后才重建
+    pub fn grid(&mut self, partition: &SpatialPartition) -> Arc<PathGrid> {
+        let version = partition.terrain_version();
+        match &self.grid {
+            Some((v, grid)) if *v == version => grid.clone(),
+            _ => {
+                let grid = Arc::new(PathGrid::from_partition(partition));
+                self.grid = Some((version, grid.clone()));
+                grid
+            }
+        }
+    }
+}
+
+/// 正在后台计算的寻路任务，挂在发起请求的动物实体上
+#[derive(Component)]
+pub struct PathfindingTask {
+    pub goal: HexMapPosition,
+    task: Task<Option<Vec<HexMapPosition>>>,
+}
+
+/// 使用D* Lite增量寻路的动物持有的规划器，目标不变时跨帧复用搜索结果
+#[derive(Component, Default)]
+pub struct DStarPlanner(pub Option<DStarLite>);
+
+impl DStarPlanner {
+    /// 从start出发前往goal的下一步，目标改变时重新建立规划器
+    pub fn next_step(
+        &mut self,
+        start: HexMapPosition,
+        goal: HexMapPosition,
+        partition: &SpatialPartition,
+    ) -> Option<HexMapPosition> {
+        let is_blocked = |p: &HexMapPosition| is_path_blocked(partition, p);
+        let planner = match &mut self.0 {
+            Some(planner) if planner.goal() == goal => {
+                planner.move_start(start);
+                planner
+            }
+            planner => planner.insert(DStarLite::new(
+                partition.config.width as i32,
+                partition.config.height as i32,
+                start,
+                goal,
+            )),
+        };
+        planner.compute_shortest_path(is_blocked);
+        planner.next_step(is_blocked)
+    }
+
+    pub fn cost(&self) -> Option<i32> {
+        self.0.as_ref().and_then(|p| p.cost())
+    }
+}
+
+/// 增量寻路中地块是否不可进入：障碍物或者被其他动物占据
+pub fn is_path_blocked(partition: &SpatialPartition, pos: &HexMapPosition) -> bool {
+    partition.is_obstacle(pos) || partition.is_occupied(pos)
+}
+
+/// 将SpatialPartition中占用状态发生变化的地块同步给所有D* Lite规划器
+pub fn sync_dstar_planners_system(
+    mut partition: ResMut<SpatialPartition>,
+    mut query: Query<&mut DStarPlanner>,
+) {
+    let changed = partition.take_changed_cells();
+    if changed.is_empty() {
+        return;
+    }
+
+    for mut planner in query.iter_mut() {
+        if let Some(planner) = &mut planner.0 {
+            planner.update_cells(changed.iter(), |p| is_path_blocked(&partition, p));
+        }
+    }
+}
+
+/// 提供给后台任务使用的只读地图快照
+#[derive(Debug, Clone)]
+pub struct PathGrid {
+    pub width: i32,
+    pub height: i32,
+    blocked: Vec<bool>,
+}
+
+impl PathGrid {
+    pub fn new(width: i32, height: i32) -> Self {
+        Self {
+            width,
+            height,
+            blocked: vec![false; (width * height).max(0) as usize],
+        }
+    }
+
+    pub fn from_partition(partition: &SpatialPartition) -> Self {
+        let mut grid = Self::new(
+            partition.config.width as i32,
+            partition.config.height as i32,
+        );
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let pos = HexMapPosition::new(x, y);
+                if partition.is_obstacle(&pos) {
+                    grid.set_blocked(&pos, true);
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn is_valid_position(&self, pos: &HexMapPosition) -> bool {
+        pos.x >= 0 && pos.x < self.width && pos.y >= 0 && pos.y < self.height
+    }
+
+    pub fn is_blocked(&self, pos: &HexMapPosition) -> bool {
+        !self.is_valid_position(pos) || self.blocked[self.get_index(pos)]
+    }
+
+    pub fn set_blocked(&mut self, pos: &HexMapPosition, blocked: bool) {
+        if self.is_valid_position(pos) {
+            let index = self.get_index(pos);
+            self.blocked[index] = blocked;
+        }
+    }
+
+    pub fn neighbours(&self, pos: &HexMapPosition) -> Vec<HexMapPosition> {
+        CUBE_DIRECTIONS
+            .iter()
+            .map(|dir| pos.clone().add_cube_coord(dir))
+            .filter(|p| !self.is_blocked(p))
+            .collect()
+    }
+
+    fn get_index(&self, pos: &HexMapPosition) -> usize {
+        (pos.y * self.width + pos.x) as usize
+    }
+}
+
+/// 计算从start到goal的路径，结果包含起点和终点
+pub fn find_path(
+    grid: &PathGrid,
+    start: &HexMapPosition,
+    goal: &HexMapPosition,
+    algorithm: PathAlgorithm,
+) -> Option<Vec<HexMapPosition>> {
+    match algorithm {
+        PathAlgorithm::DStarLite | PathAlgorithm::Hybrid => {
+            let mut planner = DStarLite::new(grid.width, grid.height, *start, *goal);
+            planner.compute_shortest_path(|p| grid.is_blocked(p));
+            planner.path(|p| grid.is_blocked(p))
+        }
+        // 势场法只负责短距离移动，陷入局部极小值后由A*给出完整路径
+        PathAlgorithm::AStar | PathAlgorithm::APF => astar_path(grid, start, goal),
+    }
+}
+
+pub fn astar_path(
+    grid: &PathGrid,
+    start: &HexMapPosition,
+    goal: &HexMapPosition,
+) -> Option<Vec<HexMapPosition>> {
+    astar(
+        start,
+        |p| grid.neighbours(p).into_iter().map(|p| (p, 1)),
+        |p| hex_distance(p, goal),
+        |p| p == goal,
+    )
+    .map(|(path, _)| path)
+}
+
+/// 按帧预算将队列中的请求派发到异步线程池
+pub fn dispatch_path_requests_system(
+    mut commands: Commands,
+    mut queue: ResMut<PathfindingQueue>,
+    partition: Res<SpatialPartition>,
+    time: Res<Time>,
+) {
+    queue.tick_cooldowns(time.delta_secs());
+    if queue.is_empty() {
+        return;
+    }
+
+    let grid = queue.grid(&partition);
+    let pool = AsyncComputeTaskPool::get();
+    let budget = queue.budget_per_frame;
+
+    for _ in 0..budget {
+        let Some(request) = queue.requests.pop_front() else {
+            break;
+        };
+
+        let grid = grid.clone();
+        let goal = request.goal;
+        let task = pool.spawn(async move {
+            find_path(&grid, &request.start, &request.goal, request.algorithm)
+        });
+
+        // 实体可能在请求排队期间已经被销毁
+        if let Ok(mut entity) = commands.get_entity(request.entity) {
+            entity.insert(PathfindingTask { goal, task });
+        }
+    }
+}
+
+/// 轮询寻路任务，完成后写入path_buffer，找不到路径时进入重试冷却
+pub fn poll_path_tasks_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut PathfindingTask, &mut AnimalActorBoard)>,
+    mut queue: ResMut<PathfindingQueue>,
+) {
+    for (entity, mut task, mut board) in query.iter_mut() {
+        let Some(result) = block_on(poll_once(&mut task.task)) else {
+            continue;
+        };
+
+        commands.entity(entity).remove::<PathfindingTask>();
+        // 计算期间目标已经变化，丢弃结果
+        if board.move_target != Some(task.goal) {
+            continue;
+        }
+
+        board.path_buffer = match result {
+            // 路径的第一个节点是起点，不需要缓存
+            Some(path) => path.into_iter().skip(1).collect(),
+            None => {
+                queue.mark_failed(entity, task.goal);
+                Vec::new()
+            }
+        };
+        board.path_cost = board.path_buffer.len() as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::hex_grid::HexGridConfig;
+
+    #[test]
+    fn test_astar_path_avoids_blocked_cells() {
+        let mut grid = PathGrid::new(5, 5);
+        let start = HexMapPosition::new(0, 2);
+        let goal = HexMapPosition::new(4, 2);
+        let direct = astar_path(&grid, &start, &goal).unwrap();
+        assert_eq!(direct.len() as i32 - 1, hex_distance(&start, &goal));
+
+        for y in 0..4 {
+            grid.set_blocked(&HexMapPosition::new(2, y), true);
+        }
+        let detour = astar_path(&grid, &start, &goal).unwrap();
+        assert!(detour.iter().all(|p| !grid.is_blocked(p)));
+        assert!(detour.len() > direct.len());
+    }
+
+    #[test]
+    fn test_grid_snapshot_and_retry_cooldown() {
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 5, 5, 1.0));
+        let mut queue = PathfindingQueue::default();
+        let grid = queue.grid(&partition);
+        // 地形没有变化时复用快照
+        assert!(Arc::ptr_eq(&grid, &queue.grid(&partition)));
+
+        let rock = HexMapPosition::new(2, 2);
+        partition.set_obstacle(&rock, true);
+        let rebuilt = queue.grid(&partition);
+        assert!(!Arc::ptr_eq(&grid, &rebuilt));
+        assert!(rebuilt.is_blocked(&rock));
+
+        let entity = Entity::from_raw(1);
+        let goal = HexMapPosition::new(4, 4);
+        queue.mark_failed(entity, goal);
+        assert!(queue.in_cooldown(entity, &goal));
+        // 换一个目标可以立即请求
+        assert!(!queue.in_cooldown(entity, &HexMapPosition::new(0, 4)));
+        queue.tick_cooldowns(queue.retry_cooldown);
+        assert!(!queue.in_cooldown(entity, &goal));
+    }
+}
@@ -0,0 +1,385 @@
+//! 人工势场法（APF）短距离移动
+//!
+//! 只在当前格的六个相邻格中选择势能最低的一格作为下一步：食物和同类产生引力，
+//! 捕食者、被占据的格子和地图边缘产生斥力。陷入局部极小值时通过回访惩罚和随机扰动脱困，
+//! 多次脱困失败后交由A*/D* Lite接管。
+
+use std::collections::VecDeque;
+
+use bevy::platform::collections::HashMap;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::core::components::{EntityType, FieldOfView};
+use crate::core::hex_grid::{HexMapPosition, SpatialPartition, hex_distance};
+use crate::level::config::LevelConfigAsset;
+
+/// 记录最近走过的格子数量，用于检测来回震荡
+const HISTORY_LEN: usize = 8;
+
+/// 势场权重，可以在关卡配置中按物种覆盖
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct PotentialFieldWeights {
+    pub food: f32,             // 食物引力
+    pub mate: f32,             // 同类引力
+    pub predator: f32,         // 捕食者斥力
+    pub occupied: f32,         // 被占据格子的斥力
+    pub edge: f32,             // 地图边缘斥力
+    pub edge_margin: i32,      // 距离边缘多少格以内开始受到斥力
+    pub influence_radius: i32, // 势场源的作用半径
+    pub revisit_penalty: f32,  // 回到最近走过的格子的惩罚
+    pub jitter: f32,           // 脱困时的随机扰动幅度
+    pub max_stuck_steps: u32,  // 连续陷入局部极小值的最大步数，超过后交由全局寻路
+}
+
+impl Default for PotentialFieldWeights {
+    fn default() -> Self {
+        Self {
+            food: 10.0,
+            mate: 1.0,
+            predator: 20.0,
+            occupied: 50.0,
+            edge: 2.0,
+            edge_margin: 1,
+            influence_radius: 6,
+            revisit_penalty: 3.0,
+            jitter: 0.5,
+            max_stuck_steps: 3,
+        }
+    }
+}
+
+impl PotentialFieldWeights {
+    pub fn by_species(entity_type: &EntityType) -> Self {
+        match entity_type {
+            EntityType::Rabbit => Self {
+                predator: 30.0,
+                ..Default::default()
+            },
+            EntityType::Fox => Self {
+                food: 15.0,
+                predator: 0.0,
+                influence_radius: 8,
+                ..Default::default()
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// 当前关卡的势场配置：各物种的权重以及捕食者列表
+#[derive(Resource, Debug, Clone, Default)]
+pub struct PotentialFieldConfig {
+    pub weights: HashMap<EntityType, PotentialFieldWeights>,
+    pub predators: HashMap<EntityType, Vec<EntityType>>,
+}
+
+impl PotentialFieldConfig {
+    pub fn from_level(level: &LevelConfigAsset) -> Self {
+        Self {
+            weights: level.potential_fields.clone(),
+            predators: level
+                .food_chains
+                .iter()
+                .map(|(t, r)| (t.clone(), r.predators_of.iter().cloned().collect()))
+                .collect(),
+        }
+    }
+
+    pub fn weights(&self, entity_type: &EntityType) -> PotentialFieldWeights {
+        self.weights
+            .get(entity_type)
+            .cloned()
+            .unwrap_or_else(|| PotentialFieldWeights::by_species(entity_type))
+    }
+
+    pub fn predators_of(&self, entity_type: &EntityType) -> &[EntityType] {
+        self.predators
+            .get(entity_type)
+            .map_or(&[], |p| p.as_slice())
+    }
+}
+
+/// 势场的来源
+#[derive(Debug, Clone, Default)]
+pub struct PotentialSources {
+    pub food: Vec<HexMapPosition>,
+    pub mates: Vec<HexMapPosition>,
+    pub predators: Vec<HexMapPosition>,
+}
+
+impl PotentialSources {
+    /// 从SpatialPartition中收集center附近radius范围内的势场源，有视野时只收集看得到的
+    #[allow(clippy::too_many_arguments)]
+    pub fn gather<'a>(
+        partition: &SpatialPartition,
+        center: &HexMapPosition,
+        radius: i32,
+        food_type: &EntityType,
+        species: &EntityType,
+        predators: impl IntoIterator<Item = &'a EntityType>,
+        exclude: Entity,
+        fov: Option<&FieldOfView>,
+    ) -> Self {
+        let nearby = |entity_type: &EntityType| {
+            partition
+                .entities_of_type_within(center, radius, entity_type)
+                .into_iter()
+                .filter(|e| e.entity != exclude)
+                .filter(|e| fov.is_none_or(|fov| fov.can_see(&e.pos)))
+                .map(|e| e.pos)
+                .collect::<Vec<_>>()
+        };
+
+        Self {
+            food: nearby(food_type),
+            mates: nearby(species),
+            predators: predators.into_iter().flat_map(nearby).collect(),
+        }
+    }
+}
+
+/// 使用势场法移动的动物持有的状态
+#[derive(Component, Debug, Clone, Default)]
+pub struct PotentialField {
+    pub history: VecDeque<HexMapPosition>,
+    pub stuck_steps: u32,
+    pub fallback: Option<HexMapPosition>, // 势场法放弃后交由全局寻路的目标
+}
+
+impl PotentialField {
+    /// 计算pos处的势能，势能越低越倾向于移动过去
+    pub fn potential_at(
+        pos: &HexMapPosition,
+        sources: &PotentialSources,
+        weights: &PotentialFieldWeights,
+        partition: &SpatialPartition,
+    ) -> f32 {
+        let mut potential = 0.0;
+
+        for food in sources.food.iter() {
+            potential -= weights.food / (hex_distance(pos, food) as f32 + 1.0);
+        }
+        for mate in sources.mates.iter() {
+            potential -= weights.mate / (hex_distance(pos, mate) as f32 + 1.0);
+        }
+        for predator in sources.predators.iter() {
+            let d = hex_distance(pos, predator) as f32 + 1.0;
+            potential += weights.predator / (d * d);
+        }
+
+        // 食物本身也可能是动物（例如狐狸捕食兔子），不能排斥
+        if partition.is_occupied(pos) && !sources.food.contains(pos) {
+            potential += weights.occupied;
+        }
+
+        let to_edge = pos
+            .x
+            .min(pos.y)
+            .min(partition.config.width as i32 - 1 - pos.x)
+            .min(partition.config.height as i32 - 1 - pos.y);
+        if to_edge < weights.edge_margin {
+            potential += weights.edge * (weights.edge_margin - to_edge) as f32;
+        }
+
+        potential
+    }
+
+    /// 选择下一步移动的格子，返回None表示陷入局部极小值无法脱困，需要交由全局寻路
+    pub fn select_move(
+        &mut self,
+        current: &HexMapPosition,
+        sources: &PotentialSources,
+        weights: &PotentialFieldWeights,
+        partition: &SpatialPartition,
+    ) -> Option<HexMapPosition> {
+        let here = Self::potential_at(current, sources, weights, partition);
+        let stuck = self.stuck_steps > 0;
+
+        let best = partition
+            .get_valid_neighbours(current)
+            .into_iter()
+            .map(|neighbour| {
+                let mut potential = Self::potential_at(&neighbour, sources, weights, partition);
+                // 回访惩罚，走得越近惩罚越大，避免在两格之间来回震荡
+                if let Some(age) = self.history.iter().rev().position(|p| *p == neighbour) {
+                    potential +=
+                        weights.revisit_penalty * (HISTORY_LEN - age) as f32 / HISTORY_LEN as f32;
+                }
+                // 陷入局部极小值时加入随机扰动
+                if stuck && weights.jitter > 0.0 {
+                    potential += rand::random_range(0.0..weights.jitter);
+                }
+                (neighbour, potential)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+
+        let (next, potential) = best?;
+        if potential >= here {
+            self.stuck_steps += 1;
+            if self.stuck_steps > weights.max_stuck_steps {
+                self.stuck_steps = 0;
+                return None;
+            }
+        } else {
+            self.stuck_steps = 0;
+        }
+
+        self.history.push_back(*current);
+        if self.history.len() > HISTORY_LEN {
+            self.history.pop_front();
+        }
+        Some(next)
+    }
+
+    /// 向goal移动时的下一步。势场法放弃后返回None，直到全局路径走完或者失效（finish_fallback）
+    /// 或者目标改变，才重新使用势场，避免刚算好的路径被势场法的下一步覆盖
+    pub fn steer(
+        &mut self,
+        current: &HexMapPosition,
+        goal: &HexMapPosition,
+        sources: &PotentialSources,
+        weights: &PotentialFieldWeights,
+        partition: &SpatialPartition,
+    ) -> Option<HexMapPosition> {
+        if self.fallback == Some(*goal) {
+            return None;
+        }
+        let next = self.select_move(current, sources, weights, partition);
+        self.fallback = next.is_none().then_some(*goal);
+        next
+    }
+
+    pub fn finish_fallback(&mut self) {
+        self.fallback = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{PathGrid, astar_path};
+    use crate::core::hex_grid::HexGridConfig;
+
+    fn partition(size: usize) -> SpatialPartition {
+        SpatialPartition::new(HexGridConfig::new(1.0, size, size, 1.0))
+    }
+
+    #[test]
+    fn test_apf_moves_towards_food_and_away_from_predator() {
+        let partition = partition(9);
+        let weights = PotentialFieldWeights::default();
+        let current = HexMapPosition::new(4, 4);
+
+        let mut field = PotentialField::default();
+        let towards_food = PotentialSources {
+            food: vec![HexMapPosition::new(8, 4)],
+            ..Default::default()
+        };
+        let next = field
+            .select_move(&current, &towards_food, &weights, &partition)
+            .unwrap();
+        assert!(
+            hex_distance(&next, &towards_food.food[0])
+                < hex_distance(&current, &towards_food.food[0])
+        );
+
+        let mut field = PotentialField::default();
+        let flee = PotentialSources {
+            predators: vec![HexMapPosition::new(3, 4)],
+            ..Default::default()
+        };
+        let next = field
+            .select_move(&current, &flee, &weights, &partition)
+            .unwrap();
+        assert!(
+            hex_distance(&next, &flee.predators[0]) > hex_distance(&current, &flee.predators[0])
+        );
+    }
+
+    #[test]
+    fn test_apf_gives_up_when_stuck() {
+        let partition = partition(9);
+        let weights = PotentialFieldWeights {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        // 四周都是对称的食物，当前格就是势能最低点
+        let current = HexMapPosition::new(4, 4);
+        let sources = PotentialSources {
+            food: vec![current],
+            ..Default::default()
+        };
+
+        let mut field = PotentialField::default();
+        let mut gave_up = false;
+        for _ in 0..=weights.max_stuck_steps {
+            if field
+                .select_move(&current, &sources, &weights, &partition)
+                .is_none()
+            {
+                gave_up = true;
+                break;
+            }
+        }
+        assert!(gave_up);
+    }
+
+    #[test]
+    fn test_apf_falls_back_to_global_path_out_of_u_trap() {
+        let mut partition = partition(15);
+        // 开口朝西的U形障碍，食物在障碍的东侧
+        for x in 3..=6 {
+            partition.set_obstacle(&HexMapPosition::new(x, 4), true);
+            partition.set_obstacle(&HexMapPosition::new(x, 10), true);
+        }
+        for y in 4..=10 {
+            partition.set_obstacle(&HexMapPosition::new(6, y), true);
+        }
+        let weights = PotentialFieldWeights {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        let food = HexMapPosition::new(12, 7);
+        let sources = PotentialSources {
+            food: vec![food],
+            ..Default::default()
+        };
+
+        // 模拟觅食系统：势场法放弃后提交寻路请求，结果在下一次决策时到达
+        let mut current = HexMapPosition::new(5, 7);
+        let mut field = PotentialField::default();
+        let mut path_buffer: Vec<HexMapPosition> = Vec::new();
+        let mut pending = false;
+        let mut fell_back = false;
+        for _ in 0..50 {
+            if current == food {
+                break;
+            }
+            if pending {
+                let grid = PathGrid::from_partition(&partition);
+                path_buffer = astar_path(&grid, &current, &food).unwrap();
+                path_buffer.remove(0);
+                pending = false;
+            }
+            match field.steer(&current, &food, &sources, &weights, &partition) {
+                Some(next) => {
+                    path_buffer.clear();
+                    current = next;
+                }
+                None if path_buffer.last() == Some(&food) => {
+                    fell_back = true;
+                    for step in path_buffer.drain(..) {
+                        assert!(!partition.is_obstacle(&step));
+                        current = step;
+                    }
+                    field.finish_fallback();
+                }
+                None => pending = true,
+            }
+        }
+        assert!(fell_back);
+        assert_eq!(current, food);
+    }
+}
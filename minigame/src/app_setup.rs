@@ -23,7 +23,8 @@ use minigame::sprite::sprite_mgr::SpriteManagerPlugin;
 use minigame::ui::cards::EntityCardsPlugin;
 use minigame::ui::hud::HudPlugin;
 use minigame::ui::{
-    ErrorTipsPlugin, ForegroundColor, PBarColorScheme, ProgressBarPlugin, show_error_tips,
+    BehaveDebuggerPlugin, ErrorTipsPlugin, FoodWebPlugin, ForegroundColor, PBarColorScheme,
+    ProgressBarPlugin, show_error_tips,
 };
 
 fn close_window_on_esc(
@@ -71,7 +72,7 @@ pub fn create_app() -> App {
         .add_plugins(BehavePlugin::default())
         // .add_plugins(VisibilityPlugin)  //提示已经加载这个插件了，目前还不知道是哪个插件包含了这个
         .insert_resource(LevelLoader::default())
-        .add_plugins((SpriteManagerPlugin, SceneSelectorPlugin))
+        .add_plugins((SpriteManagerPlugin::default(), SceneSelectorPlugin))
         .add_plugins(ProgressBarPlugin::<Satiety>::default())
         .insert_resource(PBarColorScheme::<Satiety>::new().foreground_color(
             ForegroundColor::TriSpectrum {
@@ -86,6 +87,8 @@ pub fn create_app() -> App {
             HudPlugin,
             MapInteractionPlugin,
             EntityCardsPlugin,
+            FoodWebPlugin,
+            BehaveDebuggerPlugin,
         ))
         .add_systems(
             Update,
@@ -2,10 +2,11 @@
 
 use std::fmt;
 
+use bevy::platform::collections::HashSet;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
 
-use crate::core::systems::hex_grid::HexMapPosition;
+use crate::core::systems::hex_grid::{EntityWithCoord, HexMapPosition, SpatialPartition};
 
 /// 渲染信息
 #[derive(Component)]
@@ -24,6 +25,8 @@ pub enum EntityType {
     Grass,
     Rabbit,
     Fox,
+    Fungus, // 分解者，分解尸体把养分还给土壤
+    Corpse, // 尸体，留在地块上的养分
 }
 
 impl fmt::Display for EntityType {
@@ -33,24 +36,149 @@ impl fmt::Display for EntityType {
             EntityType::Grass => "Grass_normal",
             EntityType::Rabbit => "Rabbit",
             EntityType::Fox => "Fox",
+            EntityType::Fungus => "Fungus",
+            EntityType::Corpse => "Corpse",
         };
         write!(f, "{}", s)
     }
 }
 
-/// 能量存储
-#[derive(Component, Debug)]
-pub struct EnergyStore {
-    pub value: f32,
-    pub max: f32,
+impl EntityType {
+    /// 界面上显示的名称
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            EntityType::Cell => "地块",
+            EntityType::Grass => "青草",
+            EntityType::Rabbit => "兔子",
+            EntityType::Fox => "狐狸",
+            EntityType::Fungus => "真菌",
+            EntityType::Corpse => "尸体",
+        }
+    }
+
+    /// 地表实体和动物分层存放，不占用地块，也不阻挡移动
+    pub fn is_ground(&self) -> bool {
+        matches!(
+            self,
+            EntityType::Grass | EntityType::Fungus | EntityType::Corpse
+        )
+    }
+
+    /// 按生长阶段生长、会播种繁殖的植物
+    pub fn is_plant(&self) -> bool {
+        matches!(self, EntityType::Grass)
+    }
+
+    /// 尸体和地块不是生物，不计入物种
+    pub fn is_living(&self) -> bool {
+        !matches!(self, EntityType::Cell | EntityType::Corpse)
+    }
 }
 
-/// 饥饿状态
-#[derive(Component, Debug)]
-pub struct Hunger {
-    pub value: f32,
+/// 开始觅食的能量比例
+pub const HUNGRY_FRACTION: f32 = 0.5;
+/// 停止觅食的能量比例
+pub const FULL_FRACTION: f32 = 0.8;
+
+/// 能量代谢，生物体内储存的能量
+///
+/// 动物的能量来自进食，按基础代谢、移动和繁殖消耗；植物的能量来自光合作用，同样支付基础代谢。
+/// 修改能量的地方都要把实际的变化量记到EnergyLedger中。
+#[derive(Component, Debug, Clone, Default)]
+pub struct Metabolism {
+    pub energy: f32,
     pub max: f32,
-    pub is_searching: bool,
+    pub basal: f32,      // 每秒的基础代谢消耗
+    pub move_cost: f32,  // 每移动一格的消耗
+    pub birth_cost: f32, // 繁殖一次交给后代的能量
+    pub production: f32, // 每秒光合作用产生的能量，只有植物有
+}
+
+impl Metabolism {
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 {
+            self.energy / self.max
+        } else {
+            0.0
+        }
+    }
+
+    pub fn is_hungry(&self) -> bool {
+        self.fraction() <= HUNGRY_FRACTION
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.fraction() >= FULL_FRACTION
+    }
+
+    /// 吃饱并且能量足够支付后代出生时的能量
+    pub fn can_reproduce(&self) -> bool {
+        self.birth_cost > 0.0 && self.energy >= self.birth_cost && self.is_full()
+    }
+
+    /// 剩余能量是否还够移动一格
+    pub fn can_move(&self) -> bool {
+        self.energy >= self.move_cost
+    }
+
+    /// 能量耗尽的动物会饿死，植物另外按is_withered判断
+    pub fn is_starved(&self) -> bool {
+        self.production <= 0.0 && self.energy <= 0.0
+    }
+
+    /// 光合作用抵不上基础代谢的植物，能量耗尽后枯萎
+    pub fn is_withered(&self) -> bool {
+        self.production > 0.0 && self.energy <= 0.0
+    }
+
+    /// 消耗能量，返回实际消耗的量
+    pub fn spend(&mut self, amount: f32) -> f32 {
+        let spent = amount.clamp(0.0, self.energy);
+        self.energy -= spent;
+        spent
+    }
+
+    /// 获得能量，超出上限的部分不能吸收，返回实际获得的量
+    pub fn gain(&mut self, amount: f32) -> f32 {
+        let gained = amount.clamp(0.0, (self.max - self.energy).max(0.0));
+        self.energy += gained;
+        gained
+    }
+
+    /// 取出全部能量，被吃掉时使用
+    pub fn take(&mut self) -> f32 {
+        std::mem::take(&mut self.energy)
+    }
+}
+
+/// 植物生长阶段，从0开始，到max_stage时成熟
+#[derive(Component, Debug, Clone)]
+pub struct GrowthStage {
+    pub stage: usize,
+    pub max_stage: usize,
+    pub timer: Timer, // 每个阶段持续的时间
+}
+
+impl GrowthStage {
+    pub fn new(max_stage: usize, secs_per_stage: f32) -> Self {
+        Self {
+            stage: 0,
+            max_stage,
+            timer: Timer::from_seconds(secs_per_stage, TimerMode::Repeating),
+        }
+    }
+
+    /// 已经成熟的植物
+    pub fn mature(max_stage: usize) -> Self {
+        Self {
+            stage: max_stage,
+            ..Self::new(max_stage, 1.0)
+        }
+    }
+
+    pub fn is_mature(&self) -> bool {
+        self.stage >= self.max_stage
+    }
 }
 
 /// 生命值组件
@@ -59,20 +187,86 @@ pub struct Health {
     pub value: Option<f32>,
 }
 
+/// 尸体中还没有分解的能量，分解时按比例转化为地块肥力
+#[derive(Component, Debug, Clone)]
+pub struct Corpse {
+    pub energy: f32,
+}
+
+/// 分解者，每秒从附近的尸体中分解rate的能量
+#[derive(Component, Debug, Clone)]
+pub struct Decomposer {
+    pub rate: f32,
+    pub radius: i32,
+}
+
+/// 已绝育的植物，不再播种
+#[derive(Component, Debug)]
+pub struct Sterile;
+
 /// 玩家标记
 #[derive(Component, Debug)]
 pub struct Player;
 
-/// 移动目标
-#[derive(Component, Debug)]
+/// 移动目标，由AI行为挂到实体上，movement_system沿path逐格移动，走完后自动移除
+#[derive(Component, Debug, Clone)]
 pub struct MoveTo {
     pub target: HexMapPosition,
-    pub path: Vec<HexMapPosition>,
-    pub speed: f32,
+    pub path: Vec<HexMapPosition>, // 剩余路径，不包含当前位置
+    pub speed: f32,                // 每秒移动的格子数
+    pub progress: f32,             // 当前这一步已经完成的比例
+}
+
+impl MoveTo {
+    /// 沿path移动，path的最后一格即为目标
+    pub fn new(path: Vec<HexMapPosition>, speed: f32) -> Option<Self> {
+        Some(Self {
+            target: *path.last()?,
+            path,
+            speed,
+            progress: 0.0,
+        })
+    }
+
+    /// 只移动到相邻的一格
+    pub fn step(next: HexMapPosition, speed: f32) -> Self {
+        Self {
+            target: next,
+            path: vec![next],
+            speed,
+            progress: 0.0,
+        }
+    }
 }
 
 /// 视野范围
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Clone)]
+#[require(FieldOfView)]
 pub struct VisionRange {
     pub radius: i32,
 }
+
+/// 当前能看到的地块，每个固定帧最多计算一次，位置和障碍都没有变化时沿用上次结果
+#[derive(Component, Debug, Clone, Default)]
+pub struct FieldOfView {
+    pub cells: Vec<HexMapPosition>, // 可见地块，由近到远排列
+    pub visible: HashSet<HexMapPosition>,
+    pub origin: Option<HexMapPosition>, // 计算时所在的地块，None表示尚未计算
+    pub radius: i32,
+    pub obstacle_version: u32,
+}
+
+impl FieldOfView {
+    pub fn can_see(&self, pos: &HexMapPosition) -> bool {
+        self.visible.contains(pos)
+    }
+
+    /// 视野内的某类实体，由近到远排列
+    pub fn entities_of(
+        &self,
+        partition: &SpatialPartition,
+        entity_type: &EntityType,
+    ) -> Vec<EntityWithCoord> {
+        partition.visible_entities_of(&self.cells, entity_type)
+    }
+}
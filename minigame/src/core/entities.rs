@@ -1,17 +1,26 @@
 use std::{collections::HashMap, time::Duration};
 
 use crate::{
-    ai::{AnimalActorBoard, EdibleEntity, FrameCounter, Satiety, get_ai_behave_tree},
+    ai::{
+        AnimalActorBoard, DStarPlanner, EdibleEntity, FrameCounter, PathAlgorithm,
+        PathfindingQueue, PotentialField, PotentialFieldConfig, Satiety, get_ai_behave_tree,
+    },
     core::{
-        components::EntityType,
+        components::{Decomposer, EntityType, GrowthStage, Metabolism, Sterile, VisionRange},
+        economy::EcologyEvent,
+        fog::{FogOfWar, FogRevealer},
         hex_grid::{HexMapPosition, SpatialPartition},
+        lod::SimulationLod,
+        metabolism::EnergyLedger,
+        move_animation::MoveAnimation,
     },
     level::{
         config::{EntityConfig, LevelConfigAsset},
+        food_chain::EnergyTransfer,
         loader::LevelLoader,
     },
     scenes::GameSceneRoot,
-    sprite::sprite_mgr::SpriteManager,
+    sprite::{animation::SpriteAnimation, sprite_mgr::SpriteManager},
     ui::{
         BarBorder, BarHeight, BarOrientation, BarSettings, ForegroundColor, PBarColorScheme,
         Percentage, ProgressBarMaterial,
@@ -20,6 +29,7 @@ use crate::{
 use bevy::prelude::*;
 use bevy_behave::prelude::BehaveTree;
 use bevy_egui::egui::emath::OrderedFloat;
+use serde::{Deserialize, Serialize};
 
 #[derive(Component)]
 #[require(Visibility::default())]
@@ -46,6 +56,119 @@ pub struct Reproduction {
     pub cooldown: f32,
 }
 
+/// 在地图上生成实体
+#[derive(Event, Debug, Clone)]
+pub struct SpawnEntityEvent {
+    pub config: EntityConfig,
+    pub by_player: bool,        // 玩家投放的实体会持续揭示战争迷雾
+    pub parent: Option<Entity>, // 繁殖产生的实体的亲代，由亲代支付出生时的能量
+}
+
+/// 植物储存能量的上限
+pub const PLANT_MAX_ENERGY: f32 = 100.0;
+/// 植物每秒光合作用产生的能量
+pub const PLANT_PRODUCTION: f32 = 2.0;
+/// 植物每秒的基础代谢消耗，冬季和干旱时光合作用入不敷出
+pub const PLANT_BASAL: f32 = 0.5;
+/// 还在生长的植物生成时的能量
+pub const PLANT_SEED_ENERGY: f32 = 10.0;
+/// 植物从幼苗到成熟经历的阶段数，显示时按比例对应到grow片段的帧
+pub const PLANT_GROWTH_STAGES: usize = 4;
+/// 真菌储存能量的上限
+pub const FUNGUS_MAX_ENERGY: f32 = 50.0;
+/// 真菌每秒的基础代谢消耗
+pub const FUNGUS_BASAL: f32 = 0.5;
+/// 真菌每秒分解的尸体能量
+pub const FUNGUS_DECOMPOSE_RATE: f32 = 10.0;
+
+/// 物种的基础属性，生成实体和卡片提示共用，可以在关卡配置中按物种覆盖
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpeciesStats {
+    pub move_speed: f32,    // 每秒移动的格子数
+    pub vision_radius: i32, // 视野半径（格）
+    pub max_energy: f32,    // 能量上限
+    pub basal: f32,         // 每秒的基础代谢消耗
+    pub move_cost: f32,     // 每移动一格消耗的能量
+    pub birth_energy: f32,  // 出生时的能量，繁殖时由亲代支付
+}
+
+impl SpeciesStats {
+    /// 只有带AI的动物才有这些属性
+    pub fn of(entity_type: &EntityType) -> Option<Self> {
+        match entity_type {
+            EntityType::Rabbit => Some(Self {
+                move_speed: 1.0,
+                vision_radius: 10,
+                max_energy: 100.0,
+                basal: 1.1,
+                move_cost: 0.5,
+                birth_energy: 55.0,
+            }),
+            EntityType::Fox => Some(Self {
+                move_speed: 1.5,
+                vision_radius: 12,
+                max_energy: 200.0,
+                basal: 1.0,
+                move_cost: 0.5,
+                birth_energy: 110.0,
+            }),
+            _ => None,
+        }
+    }
+
+    /// 实体配置中的速度、视野和饥饿速度覆盖物种的默认值
+    pub fn with_overrides(mut self, config: &EntityConfig) -> Self {
+        if let Some(speed) = config.speed {
+            self.move_speed = speed;
+        }
+        if let Some(vision_range) = config.vision_range {
+            self.vision_radius = vision_range;
+        }
+        // 饥饿速度即每秒的基础代谢消耗
+        if let Some(hunger_rate) = config.hunger_rate {
+            self.basal = hunger_rate;
+        }
+        self
+    }
+
+    pub fn metabolism(&self) -> Metabolism {
+        Metabolism {
+            energy: self.birth_energy,
+            max: self.max_energy,
+            basal: self.basal,
+            move_cost: self.move_cost,
+            birth_cost: self.birth_energy,
+            production: 0.0,
+        }
+    }
+}
+
+/// 当前关卡的物种属性
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SpeciesConfig {
+    stats: HashMap<EntityType, SpeciesStats>, // 关卡中覆盖的物种属性
+}
+
+impl SpeciesConfig {
+    pub fn from_level(level: &LevelConfigAsset) -> Self {
+        Self {
+            stats: level
+                .species
+                .iter()
+                .map(|(entity_type, stats)| (entity_type.clone(), *stats))
+                .collect(),
+        }
+    }
+
+    /// 关卡没有覆盖的物种使用默认属性
+    pub fn stats(&self, entity_type: &EntityType) -> Option<SpeciesStats> {
+        self.stats
+            .get(entity_type)
+            .copied()
+            .or_else(|| SpeciesStats::of(entity_type))
+    }
+}
+
 #[derive(Bundle)]
 pub struct EntityHeaderBarUI {
     pub sprite: Sprite,
@@ -57,9 +180,10 @@ pub fn spawn_entity(
     commands: &mut Commands,
     config: &EntityConfig,
     sprite_manager: &ResMut<SpriteManager>,
+    species: &SpeciesConfig,
     partition: &mut ResMut<SpatialPartition>,
     parent: &Entity,
-) {
+) -> Entity {
     let mut center = partition.grid_to_world(&config.pos);
     center.z = 2.0;
 
@@ -68,32 +192,74 @@ pub fn spawn_entity(
         &config.entity_type, config.pos, center
     );
 
+    let sprite_name = config.entity_type.to_string().to_lowercase();
     let mut cmd = commands.spawn((
-        sprite_manager.get_sprite_by_name(config.entity_type.to_string().to_lowercase().as_str()),
+        sprite_manager.get_sprite_by_name(sprite_name.as_str()),
         Transform::from_translation(center),
         EdibleEntity::default(),
+        SpriteAnimation::new(sprite_name.as_str()),
     ));
 
     match config.entity_type {
-        EntityType::Rabbit => {
+        EntityType::Grass => {
+            // 配置了生长速度的植物从幼苗开始生长，否则生成时已经成熟
+            let (growth, energy) = match config.growth_rate {
+                Some(secs_per_stage) => (
+                    GrowthStage::new(PLANT_GROWTH_STAGES, secs_per_stage),
+                    PLANT_SEED_ENERGY,
+                ),
+                None => (GrowthStage::mature(PLANT_GROWTH_STAGES), PLANT_MAX_ENERGY),
+            };
+            cmd.insert(growth);
+            cmd.insert(Metabolism {
+                energy,
+                max: PLANT_MAX_ENERGY,
+                basal: PLANT_BASAL,
+                production: PLANT_PRODUCTION,
+                birth_cost: PLANT_SEED_ENERGY,
+                ..Default::default()
+            });
+        }
+        EntityType::Rabbit | EntityType::Fox => {
             // info!("spawn rabbit behave tree");
+            let entity_type = config.entity_type.clone();
+            let stats = species.stats(&entity_type).unwrap().with_overrides(config);
             let mut timer = Timer::from_seconds(1.0, TimerMode::Repeating);
             timer.tick(Duration::from_millis(1500));
+            let path_algorithm = PathAlgorithm::by_species(&entity_type);
+            if path_algorithm.is_incremental() {
+                cmd.insert(DStarPlanner::default());
+            }
+            if path_algorithm.uses_potential_field() {
+                cmd.insert(PotentialField::default());
+            }
+            let metabolism = stats.metabolism();
+            let satiety = Satiety(metabolism.fraction());
+            cmd.insert((
+                metabolism,
+                VisionRange {
+                    radius: stats.vision_radius,
+                },
+                MoveAnimation::hopping(6.0)
+                    .with_faces_left(sprite_manager.faces_left(&sprite_name)),
+            ));
             cmd.insert((
                 AnimalActorBoard {
                     current_pos: HexMapPosition::from(config.pos),
                     move_cd_timer: timer,
-                    entity_type: EntityType::Rabbit,
-                    satiety: 5500,
-                    decay_faction: 1.1, // TODO根据不同的动物类型配置不同的饱食度衰减
+                    move_speed: stats.move_speed,
+                    entity_type: entity_type.clone(),
+                    path_algorithm,
                     ..Default::default()
                 },
                 children![(
-                    Name::new("rabbit behave_tree"),
-                    BehaveTree::new(get_ai_behave_tree(EntityType::Rabbit)).with_logging(false),
+                    Name::new(format!("{sprite_name} behave_tree")),
+                    BehaveTree::new(get_ai_behave_tree(entity_type)).with_logging(false),
                 )],
             ))
             .with_children(|parent| {
+                // 按地块外接矩形摆放，平顶布局的地块更矮
+                let half = partition.config.cell_half_extents();
                 // stomach icon
                 parent.spawn((
                     Sprite {
@@ -101,18 +267,14 @@ pub fn spawn_entity(
                         custom_size: Some(Vec2::splat(20.)),
                         ..Default::default()
                     },
-                    Transform::from_translation(Vec3::new(
-                        -partition.config.size / 2.,
-                        -partition.config.size / 2.,
-                        3.0,
-                    )),
+                    Transform::from_translation((-half / 2.).extend(3.0)),
                 ));
 
                 parent.spawn((
-                    Satiety(5500),
+                    satiety,
                     BarSettings::<Satiety> {
-                        width: partition.config.size * 0.7,
-                        offset: Vec2::new(-partition.config.size / 2., partition.config.size / 10.),
+                        width: half.y * 0.7,
+                        offset: Vec2::new(-half.x / 2., half.y / 10.),
                         height: BarHeight::Static(10.),
                         orientation: BarOrientation::Vertical,
                         border: BarBorder::new(2.0),
@@ -122,15 +284,79 @@ pub fn spawn_entity(
                 ));
             });
         }
-        EntityType::Fox => {
-            // cmd.insert(Fox);
+        EntityType::Fungus => {
+            // 真菌不会移动，只分解周围一圈地块上的尸体
+            cmd.insert((
+                Metabolism {
+                    energy: FUNGUS_MAX_ENERGY,
+                    max: FUNGUS_MAX_ENERGY,
+                    basal: FUNGUS_BASAL,
+                    ..Default::default()
+                },
+                Decomposer {
+                    rate: FUNGUS_DECOMPOSE_RATE,
+                    radius: 1,
+                },
+            ));
         }
         _ => {}
     };
 
     cmd.insert(ChildOf(*parent));
 
-    partition.insert_cache_entity(cmd.id(), &config.pos.into(), config.entity_type.clone());
+    let entity = cmd.id();
+    partition.insert_cache_entity(entity, &config.pos.into(), config.entity_type.clone());
+    entity
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_entity_event_system(
+    mut commands: Commands,
+    mut events: EventReader<SpawnEntityEvent>,
+    sprite_manager: ResMut<SpriteManager>,
+    species: Res<SpeciesConfig>,
+    mut partition: ResMut<SpatialPartition>,
+    root: Query<Entity, With<OnMapEntitiesRoot>>,
+    fog: Option<Res<FogOfWar>>,
+    mut ecology: EventWriter<EcologyEvent>,
+    mut parent_q: Query<(&mut Metabolism, Has<Sterile>)>,
+    mut ledger: ResMut<EnergyLedger>,
+) {
+    let Ok(parent) = root.single() else {
+        return;
+    };
+    for event in events.read() {
+        // 亲代支付后代出生时的能量，绝育或者能量不足时不能繁殖
+        if let Some(entity) = event.parent {
+            let Ok((mut metabolism, sterile)) = parent_q.get_mut(entity) else {
+                continue;
+            };
+            if sterile || metabolism.energy < metabolism.birth_cost {
+                continue;
+            }
+            let cost = metabolism.birth_cost;
+            ledger.reproduction += metabolism.spend(cost) as f64;
+        }
+        let entity = spawn_entity(
+            &mut commands,
+            &event.config,
+            &sprite_manager,
+            &species,
+            &mut partition,
+            &parent,
+        );
+        if !event.by_player {
+            ecology.write(EcologyEvent::Birth(event.config.entity_type.clone()));
+        }
+        if let Some(fog) = fog.as_ref()
+            && event.by_player
+        {
+            commands.entity(entity).insert(FogRevealer::new(
+                event.config.pos.into(),
+                fog.config.reveal_radius,
+            ));
+        }
+    }
 }
 
 pub fn spawn_satiety_pbar_onadd(
@@ -227,7 +453,12 @@ pub fn spawn_entities_system(
     root: Query<Entity, With<GameSceneRoot>>,
 ) {
     commands.insert_resource(FrameCounter::default());
+    commands.insert_resource(PathfindingQueue::default());
+    commands.insert_resource(SimulationLod::default());
     let level_config = level_data.get(&level_loader.level_data).unwrap();
+    commands.insert_resource(PotentialFieldConfig::from_level(level_config));
+    commands.insert_resource(EnergyTransfer::from_level(level_config));
+    let species = SpeciesConfig::from_level(level_config);
 
     let root_parent = root.single().unwrap();
 
@@ -237,6 +468,66 @@ pub fn spawn_entities_system(
         .id();
 
     for cfg in level_config.entities.iter() {
-        spawn_entity(&mut commands, cfg, &sprite_manager, &mut partition, &parent);
+        // 关卡中的坐标按地图朝向转换为网格坐标
+        let cfg = EntityConfig {
+            pos: partition.config.level_to_grid(cfg.pos),
+            ..cfg.clone()
+        };
+        // 超出MAX_MAP_SIZE的关卡被裁剪过，裁掉部分的实体跳过
+        if !partition.is_valid_position(&cfg.pos.into()) {
+            warn!(
+                "skip {:?} at {:?}: outside the {}x{} map",
+                cfg.entity_type, cfg.pos, partition.config.width, partition.config.height
+            );
+            continue;
+        }
+        spawn_entity(
+            &mut commands,
+            &cfg,
+            &sprite_manager,
+            &species,
+            &mut partition,
+            &parent,
+        );
+    }
+    commands.insert_resource(species);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_species_stats_overrides() {
+        let defaults = SpeciesStats::of(&EntityType::Rabbit).unwrap();
+        let config = EntityConfig {
+            entity_type: EntityType::Rabbit,
+            speed: Some(2.5),
+            vision_range: Some(4),
+            ..Default::default()
+        };
+        let stats = defaults.with_overrides(&config);
+        assert_eq!(stats.move_speed, 2.5);
+        assert_eq!(stats.vision_radius, 4);
+        // 没有配置的属性使用物种的默认值
+        assert_eq!(stats.basal, defaults.basal);
+        assert_eq!(stats.max_energy, defaults.max_energy);
+    }
+
+    #[test]
+    fn test_species_config_from_level() {
+        let mut level = LevelConfigAsset::default();
+        let fox = SpeciesStats {
+            move_speed: 2.0,
+            ..SpeciesStats::of(&EntityType::Fox).unwrap()
+        };
+        level.species.insert(EntityType::Fox, fox);
+        let species = SpeciesConfig::from_level(&level);
+        assert_eq!(species.stats(&EntityType::Fox), Some(fox));
+        assert_eq!(
+            species.stats(&EntityType::Rabbit),
+            SpeciesStats::of(&EntityType::Rabbit)
+        );
+        assert_eq!(species.stats(&EntityType::Grass), None);
     }
 }
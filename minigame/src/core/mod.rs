@@ -4,7 +4,7 @@ pub mod state;
 pub mod systems;
 
 pub use bevy::prelude::State;
-pub use components::{EnergyStore, MoveTo};
+pub use components::{FieldOfView, Metabolism, MoveTo, VisionRange};
 pub use hex_grid::HexGridConfig;
 pub use state::*;
 pub use systems::hex_grid::CUBE_DIRECTIONS;
@@ -0,0 +1,357 @@
+//! 六边形网格上的D* Lite增量寻路
+//!
+//! 从目标点向起点反向搜索，起点移动或地块通行状态变化时只修复受影响的节点，
+//! 而不是每一步都从头规划。g/rhs使用稀疏存储，大地图上只占用被搜索过的节点。
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use bevy::platform::collections::HashMap;
+
+use super::hex_grid::{CUBE_DIRECTIONS, HexMapPosition};
+
+const INF: i32 = i32::MAX / 4;
+
+type Key = (i32, i32);
+
+/// 立方体坐标距离，作为启发函数
+fn heuristic(a: &HexMapPosition, b: &HexMapPosition) -> i32 {
+    (a.cube_coord() - b.cube_coord()).abs().max_element()
+}
+
+#[derive(Debug, Clone)]
+pub struct DStarLite {
+    width: i32,
+    height: i32,
+    start: HexMapPosition,
+    goal: HexMapPosition,
+    last_start: HexMapPosition,
+    km: i32,
+    g: HashMap<HexMapPosition, i32>,
+    rhs: HashMap<HexMapPosition, i32>,
+    // 堆中节点当前有效的key，用于惰性删除过期的堆元素
+    open_keys: HashMap<HexMapPosition, Key>,
+    open: BinaryHeap<Reverse<(Key, (i32, i32))>>,
+    /// 最近一次compute_shortest_path展开的节点数
+    pub expanded: usize,
+}
+
+impl DStarLite {
+    pub fn new(width: i32, height: i32, start: HexMapPosition, goal: HexMapPosition) -> Self {
+        let mut planner = Self {
+            width,
+            height,
+            start,
+            goal,
+            last_start: start,
+            km: 0,
+            g: HashMap::new(),
+            rhs: HashMap::new(),
+            open_keys: HashMap::new(),
+            open: BinaryHeap::new(),
+            expanded: 0,
+        };
+        planner.rhs.insert(goal, 0);
+        let key = planner.calculate_key(&goal);
+        planner.push(goal, key);
+        planner
+    }
+
+    pub fn start(&self) -> HexMapPosition {
+        self.start
+    }
+
+    pub fn goal(&self) -> HexMapPosition {
+        self.goal
+    }
+
+    /// 起点到目标的路径代价，不可达时为None
+    pub fn cost(&self) -> Option<i32> {
+        let g = self.g(&self.start);
+        (g < INF).then_some(g)
+    }
+
+    /// 起点移动到新的位置（通常是沿路径走了一步）
+    pub fn move_start(&mut self, start: HexMapPosition) {
+        if start == self.start {
+            return;
+        }
+        self.start = start;
+        self.km += heuristic(&self.last_start, &self.start);
+        self.last_start = self.start;
+    }
+
+    /// 通知规划器这些地块的通行状态发生了变化，is_blocked为变化之后的状态
+    pub fn update_cells<'a>(
+        &mut self,
+        cells: impl IntoIterator<Item = &'a HexMapPosition>,
+        is_blocked: impl Fn(&HexMapPosition) -> bool,
+    ) {
+        for cell in cells {
+            if !self.is_valid_position(cell) {
+                continue;
+            }
+            // 进入cell的代价变化了，受影响的是cell的所有前驱节点
+            self.update_vertex(cell, &is_blocked);
+            for neighbour in self.neighbours(cell) {
+                self.update_vertex(&neighbour, &is_blocked);
+            }
+        }
+    }
+
+    /// 计算/修复最短路径
+    pub fn compute_shortest_path(&mut self, is_blocked: impl Fn(&HexMapPosition) -> bool) {
+        self.expanded = 0;
+        while let Some(Reverse((k_old, (x, y)))) = self.open.peek().copied() {
+            let u = HexMapPosition::new(x, y);
+            // 过期的堆元素直接丢弃
+            if self.open_keys.get(&u) != Some(&k_old) {
+                self.open.pop();
+                continue;
+            }
+
+            let start_key = self.calculate_key(&self.start);
+            if k_old >= start_key && self.rhs(&self.start) == self.g(&self.start) {
+                break;
+            }
+
+            self.open.pop();
+            self.expanded += 1;
+            let k_new = self.calculate_key(&u);
+            let (g_u, rhs_u) = (self.g(&u), self.rhs(&u));
+            if k_old < k_new {
+                self.push(u, k_new);
+            } else if g_u > rhs_u {
+                self.open_keys.remove(&u);
+                self.g.insert(u, rhs_u);
+                for pred in self.neighbours(&u) {
+                    self.update_vertex(&pred, &is_blocked);
+                }
+            } else {
+                self.open_keys.remove(&u);
+                self.g.insert(u, INF);
+                self.update_vertex(&u, &is_blocked);
+                for pred in self.neighbours(&u) {
+                    self.update_vertex(&pred, &is_blocked);
+                }
+            }
+        }
+    }
+
+    /// 当前起点的下一步
+    pub fn next_step(
+        &self,
+        is_blocked: impl Fn(&HexMapPosition) -> bool,
+    ) -> Option<HexMapPosition> {
+        if self.start == self.goal {
+            return None;
+        }
+        self.best_successor(&self.start, &is_blocked)
+    }
+
+    /// 沿g值下降方向提取完整路径，结果包含起点和终点
+    pub fn path(
+        &self,
+        is_blocked: impl Fn(&HexMapPosition) -> bool,
+    ) -> Option<Vec<HexMapPosition>> {
+        self.cost()?;
+        let mut path = vec![self.start];
+        let mut current = self.start;
+        while current != self.goal {
+            current = self.best_successor(&current, &is_blocked)?;
+            path.push(current);
+            // 防止数据不一致时死循环
+            if path.len() > (self.width * self.height) as usize {
+                return None;
+            }
+        }
+        Some(path)
+    }
+
+    fn best_successor(
+        &self,
+        pos: &HexMapPosition,
+        is_blocked: &impl Fn(&HexMapPosition) -> bool,
+    ) -> Option<HexMapPosition> {
+        self.neighbours(pos)
+            .into_iter()
+            .map(|s| (self.edge_cost(&s, is_blocked).saturating_add(self.g(&s)), s))
+            .filter(|(cost, _)| *cost < INF)
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, s)| s)
+    }
+
+    fn update_vertex(&mut self, u: &HexMapPosition, is_blocked: &impl Fn(&HexMapPosition) -> bool) {
+        if *u != self.goal {
+            let rhs = self
+                .neighbours(u)
+                .into_iter()
+                .map(|s| self.edge_cost(&s, is_blocked).saturating_add(self.g(&s)))
+                .min()
+                .unwrap_or(INF)
+                .min(INF);
+            self.rhs.insert(*u, rhs);
+        }
+
+        self.open_keys.remove(u);
+        if self.g(u) != self.rhs(u) {
+            let key = self.calculate_key(u);
+            self.push(*u, key);
+        }
+    }
+
+    // 进入to格的代价，目标格总是可以进入的
+    fn edge_cost(&self, to: &HexMapPosition, is_blocked: &impl Fn(&HexMapPosition) -> bool) -> i32 {
+        if *to != self.goal && is_blocked(to) {
+            INF
+        } else {
+            1
+        }
+    }
+
+    fn calculate_key(&self, s: &HexMapPosition) -> Key {
+        let m = self.g(s).min(self.rhs(s));
+        (
+            m.saturating_add(heuristic(&self.start, s))
+                .saturating_add(self.km),
+            m,
+        )
+    }
+
+    fn push(&mut self, s: HexMapPosition, key: Key) {
+        self.open_keys.insert(s, key);
+        self.open.push(Reverse((key, (s.x, s.y))));
+    }
+
+    fn g(&self, s: &HexMapPosition) -> i32 {
+        *self.g.get(s).unwrap_or(&INF)
+    }
+
+    fn rhs(&self, s: &HexMapPosition) -> i32 {
+        *self.rhs.get(s).unwrap_or(&INF)
+    }
+
+    fn is_valid_position(&self, pos: &HexMapPosition) -> bool {
+        pos.x >= 0 && pos.x < self.width && pos.y >= 0 && pos.y < self.height
+    }
+
+    fn neighbours(&self, pos: &HexMapPosition) -> Vec<HexMapPosition> {
+        CUBE_DIRECTIONS
+            .iter()
+            .map(|dir| pos.clone().add_cube_coord(dir))
+            .filter(|p| self.is_valid_position(p))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::platform::collections::HashSet;
+    use pathfinding::prelude::astar;
+
+    fn astar_cost(
+        width: i32,
+        height: i32,
+        start: &HexMapPosition,
+        goal: &HexMapPosition,
+        blocked: &HashSet<HexMapPosition>,
+    ) -> Option<i32> {
+        astar(
+            start,
+            |p| {
+                CUBE_DIRECTIONS
+                    .iter()
+                    .map(|dir| p.clone().add_cube_coord(dir))
+                    .filter(|n| {
+                        n.x >= 0
+                            && n.x < width
+                            && n.y >= 0
+                            && n.y < height
+                            && (n == goal || !blocked.contains(n))
+                    })
+                    .map(|n| (n, 1))
+                    .collect::<Vec<_>>()
+            },
+            |p| heuristic(p, goal),
+            |p| p == goal,
+        )
+        .map(|(_, cost)| cost)
+    }
+
+    fn assert_path_consistent(planner: &DStarLite, blocked: &HashSet<HexMapPosition>) {
+        let path = planner.path(|p| blocked.contains(p)).unwrap();
+        assert_eq!(path.first(), Some(&planner.start()));
+        assert_eq!(path.last(), Some(&planner.goal()));
+        assert_eq!(path.len() as i32 - 1, planner.cost().unwrap());
+        for step in path.windows(2) {
+            assert_eq!(heuristic(&step[0], &step[1]), 1);
+        }
+    }
+
+    #[test]
+    fn test_dstar_matches_astar_on_open_grid() {
+        let blocked = HashSet::new();
+        for (sx, sy, gx, gy) in [(0, 0, 9, 9), (3, 7, 8, 1), (5, 5, 5, 5), (0, 9, 9, 0)] {
+            let start = HexMapPosition::new(sx, sy);
+            let goal = HexMapPosition::new(gx, gy);
+            let mut planner = DStarLite::new(10, 10, start, goal);
+            planner.compute_shortest_path(|p| blocked.contains(p));
+
+            assert_eq!(planner.cost(), astar_cost(10, 10, &start, &goal, &blocked));
+            assert_path_consistent(&planner, &blocked);
+        }
+    }
+
+    #[test]
+    fn test_dstar_repairs_after_obstacle_changes() {
+        let (width, height) = (12, 12);
+        let start = HexMapPosition::new(1, 6);
+        let goal = HexMapPosition::new(10, 6);
+        let mut blocked = HashSet::new();
+        let mut planner = DStarLite::new(width, height, start, goal);
+        planner.compute_shortest_path(|p| blocked.contains(p));
+
+        // 在中间竖起一道墙，只留下顶部的缺口
+        let wall: Vec<_> = (0..height - 1).map(|y| HexMapPosition::new(5, y)).collect();
+        blocked.extend(wall.iter().copied());
+        planner.update_cells(wall.iter(), |p| blocked.contains(p));
+        planner.compute_shortest_path(|p| blocked.contains(p));
+        assert_eq!(
+            planner.cost(),
+            astar_cost(width, height, &start, &goal, &blocked)
+        );
+        assert_path_consistent(&planner, &blocked);
+
+        // 沿路径走几步后再移除部分墙体
+        for _ in 0..3 {
+            let next = planner.next_step(|p| blocked.contains(p)).unwrap();
+            planner.move_start(next);
+            planner.compute_shortest_path(|p| blocked.contains(p));
+        }
+        let opened: Vec<_> = (4..8).map(|y| HexMapPosition::new(5, y)).collect();
+        for cell in opened.iter() {
+            blocked.remove(cell);
+        }
+        planner.update_cells(opened.iter(), |p| blocked.contains(p));
+        planner.compute_shortest_path(|p| blocked.contains(p));
+        assert_eq!(
+            planner.cost(),
+            astar_cost(width, height, &planner.start(), &goal, &blocked)
+        );
+        assert_path_consistent(&planner, &blocked);
+    }
+
+    #[test]
+    fn test_dstar_unreachable_goal() {
+        let start = HexMapPosition::new(0, 0);
+        let goal = HexMapPosition::new(6, 6);
+        let blocked: HashSet<_> = (0..8).map(|y| HexMapPosition::new(3, y)).collect();
+        let mut planner = DStarLite::new(8, 8, start, goal);
+        planner.compute_shortest_path(|p| blocked.contains(p));
+
+        assert_eq!(planner.cost(), None);
+        assert!(planner.path(|p| blocked.contains(p)).is_none());
+        assert_eq!(astar_cost(8, 8, &start, &goal, &blocked), None);
+    }
+}
@@ -0,0 +1,206 @@
+//! 金币收入
+//!
+//! 关卡可以配置被动收入、生物多样性奖励，以及出生、死亡、灭绝的奖惩。生态事件由AI和实体
+//! 生成系统发出，这里按关卡规则结算到LevelGold，并统计收入速率供HUD显示。
+
+use std::collections::VecDeque;
+
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::hex_grid::SpatialPartition;
+use crate::core::components::EntityType;
+use crate::scenes::LevelGold;
+
+/// 统计事件收入速率的时间窗口（秒）
+const RATE_WINDOW: f32 = 10.0;
+
+/// 生态事件
+#[derive(Event, Debug, Clone, PartialEq)]
+pub enum EcologyEvent {
+    Birth(EntityType),      // 自然出生，不包括玩家投放
+    Death(EntityType),      // 被捕食或者被玩家移除
+    Extinction(EntityType), // 地图上最后一个该物种的实体消失
+    // 捕食，同时会发出猎物的Death事件
+    Predation {
+        predator: EntityType,
+        prey: EntityType,
+    },
+}
+
+/// 存活物种数达到阈值时的奖励
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BiodiversityReward {
+    pub species: usize, // 存活物种数阈值
+    #[serde(default)]
+    pub reward: u32, // 首次达到时的一次性奖励
+    #[serde(default)]
+    pub income: f32, // 保持在阈值以上时每秒的额外收入
+}
+
+/// 关卡中的收入规则，奖励为负数时表示惩罚
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+#[serde(default)]
+pub struct EconomyConfig {
+    pub passive_income: f32,                         // 每秒的被动收入
+    pub biodiversity: Vec<BiodiversityReward>,       // 生物多样性奖励
+    pub birth_bounty: HashMap<EntityType, i32>,      // 每出生一个实体的奖励
+    pub death_bounty: HashMap<EntityType, i32>,      // 每死亡一个实体的奖励
+    pub extinction_bounty: HashMap<EntityType, i32>, // 物种灭绝时的奖励
+}
+
+/// 当前关卡的收入状态
+#[derive(Resource, Debug, Default)]
+pub struct Economy {
+    pub config: EconomyConfig,
+    pub rate: f32,                 // 最近的收入速率（每秒）
+    alive: HashSet<EntityType>,    // 上一次检查时存活的物种
+    claimed: Vec<bool>,            // 已经发放过的多样性奖励
+    fraction: f32,                 // 按秒累计、还不满1金币的收入
+    elapsed: f32,                  // 关卡进行的时间
+    history: VecDeque<(f32, i32)>, // 时间窗口内事件带来的收入
+}
+
+impl Economy {
+    pub fn new(config: EconomyConfig) -> Self {
+        Self {
+            claimed: vec![false; config.biodiversity.len()],
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// 结算dt时间内的收入，返回金币的变化量
+    pub fn settle(&mut self, dt: f32, events: &[EcologyEvent], species: usize) -> i32 {
+        self.elapsed += dt;
+
+        // 被动收入和多样性加成按秒累计，满1金币才发放
+        let bonus: f32 = self
+            .config
+            .biodiversity
+            .iter()
+            .filter(|reward| species >= reward.species)
+            .map(|reward| reward.income)
+            .sum();
+        let per_sec = self.config.passive_income + bonus;
+        self.fraction += per_sec * dt;
+        let whole = self.fraction.floor();
+        self.fraction -= whole;
+        let mut gain = whole as i32;
+
+        let mut record = |amount: i32| {
+            if amount != 0 {
+                gain += amount;
+                self.history.push_back((self.elapsed, amount));
+            }
+        };
+        for event in events {
+            let (bounties, entity_type) = match event {
+                EcologyEvent::Birth(t) => (&self.config.birth_bounty, t),
+                EcologyEvent::Death(t) => (&self.config.death_bounty, t),
+                EcologyEvent::Extinction(t) => (&self.config.extinction_bounty, t),
+                EcologyEvent::Predation { .. } => continue,
+            };
+            record(bounties.get(entity_type).copied().unwrap_or(0));
+        }
+        for (reward, claimed) in self.config.biodiversity.iter().zip(self.claimed.iter_mut()) {
+            if !*claimed && species >= reward.species {
+                *claimed = true;
+                record(reward.reward as i32);
+            }
+        }
+
+        while self
+            .history
+            .front()
+            .is_some_and(|(time, _)| *time < self.elapsed - RATE_WINDOW)
+        {
+            self.history.pop_front();
+        }
+        let recent: i32 = self.history.iter().map(|(_, amount)| amount).sum();
+        self.rate = per_sec + recent as f32 / RATE_WINDOW;
+        gain
+    }
+}
+
+/// 检查物种灭绝，地图上曾经存在的物种数量降为0时发出事件
+pub fn detect_extinction_system(
+    partition: Res<SpatialPartition>,
+    mut economy: ResMut<Economy>,
+    mut events: EventWriter<EcologyEvent>,
+) {
+    let alive = partition.living_species();
+    for entity_type in economy.alive.iter() {
+        if !alive.contains(entity_type) {
+            events.write(EcologyEvent::Extinction(entity_type.clone()));
+        }
+    }
+    if economy.alive != alive {
+        economy.alive = alive;
+    }
+}
+
+/// 按关卡规则结算收入
+pub fn economy_system(
+    time: Res<Time>,
+    mut events: EventReader<EcologyEvent>,
+    mut economy: ResMut<Economy>,
+    mut gold: ResMut<LevelGold>,
+) {
+    let events: Vec<_> = events.read().cloned().collect();
+    let species = economy.alive.len();
+    let gain = economy.settle(time.delta_secs(), &events, species);
+    // 只在金币确实变化时修改，避免每帧触发依赖金币变化的系统
+    if gain != 0 {
+        gold.0 = (gold.0 as i64 + gain as i64).max(0) as u32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_economy_config_ron() {
+        let config = ron::de::from_str::<EconomyConfig>(
+            "(passive_income:0.5,death_bounty:{(type:rabbit):-2},biodiversity:[(species:3,reward:20)])",
+        )
+        .unwrap();
+        assert_eq!(config.passive_income, 0.5);
+        assert_eq!(config.death_bounty.get(&EntityType::Rabbit), Some(&-2));
+        assert_eq!(config.biodiversity[0].income, 0.0);
+    }
+
+    #[test]
+    fn test_economy_settle() {
+        let mut config = EconomyConfig {
+            passive_income: 0.5,
+            biodiversity: vec![BiodiversityReward {
+                species: 2,
+                reward: 10,
+                income: 0.5,
+            }],
+            ..Default::default()
+        };
+        config.death_bounty.insert(EntityType::Grass, -1);
+        config.extinction_bounty.insert(EntityType::Rabbit, -20);
+        let mut economy = Economy::new(config);
+
+        // 被动收入满1金币才发放
+        assert_eq!(economy.settle(1.0, &[], 1), 0);
+        assert_eq!(economy.settle(1.0, &[], 1), 1);
+
+        // 多样性奖励只发放一次，加成收入持续
+        assert_eq!(economy.settle(1.0, &[], 2), 11);
+        assert_eq!(economy.settle(1.0, &[], 2), 1);
+
+        let events = [
+            EcologyEvent::Death(EntityType::Grass),
+            EcologyEvent::Extinction(EntityType::Rabbit),
+            EcologyEvent::Birth(EntityType::Rabbit),
+        ];
+        assert_eq!(economy.settle(1.0, &events, 1), -21);
+        assert_eq!(economy.rate, 0.5 + (10 - 21) as f32 / RATE_WINDOW);
+    }
+}
@@ -0,0 +1,530 @@
+//! 环境扰动
+//!
+//! 关卡可以选择开启环境系统：季节按配置循环，影响植物生长和动物代谢；昼夜交替，
+//! 夜间动物的视野缩小、移动变慢；干旱、洪水、寒潮等天气按时间表或随机发生，
+//! 洪水淹没的地块不可通行。环境状态决定地图的整体色调，并显示在HUD上。
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::grid::{HexCellStates, MapGridRoot};
+use super::hex_grid::{HexMapPosition, HexagonBorderMaterial, SpatialPartition, hex_spiral};
+use super::interaction::MapCellColors;
+
+/// 黄昏和黎明的过渡时长，占一个昼夜的比例
+const TWILIGHT: f32 = 0.05;
+/// 深夜时地图的亮度
+const NIGHT_BRIGHTNESS: f32 = 0.45;
+/// 洪水淹没的半径（格）
+const FLOOD_RADIUS: i32 = 2;
+/// 洪水淹没的地块颜色
+const FLOOD_COLOR: Color = Color::srgb(0.2, 0.4, 0.75);
+/// 色调变化小于这个值时不更新材质
+const TINT_EPSILON: f32 = 0.005;
+
+/// 季节
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Season::Spring => "春",
+            Season::Summer => "夏",
+            Season::Autumn => "秋",
+            Season::Winter => "冬",
+        }
+    }
+
+    /// 没有在关卡中覆盖时的植物生长系数
+    pub fn growth(&self) -> f32 {
+        match self {
+            Season::Spring => 1.2,
+            Season::Summer => 1.0,
+            Season::Autumn => 0.7,
+            Season::Winter => 0.3,
+        }
+    }
+
+    /// 没有在关卡中覆盖时的基础代谢系数
+    pub fn metabolism(&self) -> f32 {
+        match self {
+            Season::Spring | Season::Autumn => 1.0,
+            Season::Summer => 1.1,
+            Season::Winter => 1.3,
+        }
+    }
+
+    fn tint(&self) -> Vec3 {
+        match self {
+            Season::Spring => Vec3::ONE,
+            Season::Summer => Vec3::new(1.05, 1.0, 0.9),
+            Season::Autumn => Vec3::new(1.05, 0.88, 0.7),
+            Season::Winter => Vec3::new(0.85, 0.92, 1.1),
+        }
+    }
+}
+
+/// 天气事件
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Weather {
+    Drought,  // 干旱，植物几乎停止生长
+    Flood,    // 洪水，淹没一片地块
+    ColdSnap, // 寒潮，植物生长变慢，动物代谢加快
+}
+
+impl Weather {
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Weather::Drought => "干旱",
+            Weather::Flood => "洪水",
+            Weather::ColdSnap => "寒潮",
+        }
+    }
+
+    pub fn growth(&self) -> f32 {
+        match self {
+            Weather::Drought => 0.2,
+            Weather::Flood => 1.0,
+            Weather::ColdSnap => 0.3,
+        }
+    }
+
+    pub fn metabolism(&self) -> f32 {
+        match self {
+            Weather::Drought | Weather::Flood => 1.0,
+            Weather::ColdSnap => 1.5,
+        }
+    }
+
+    fn tint(&self) -> Vec3 {
+        match self {
+            Weather::Drought => Vec3::new(1.1, 0.95, 0.75),
+            Weather::Flood => Vec3::new(0.85, 0.9, 1.05),
+            Weather::ColdSnap => Vec3::new(0.8, 0.9, 1.15),
+        }
+    }
+}
+
+/// 一个季节，生长和代谢系数不配置时使用季节的默认值
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeasonConfig {
+    pub season: Season,
+    pub duration: f32, // 持续时间（秒）
+    #[serde(default)]
+    pub growth: Option<f32>,
+    #[serde(default)]
+    pub metabolism: Option<f32>,
+}
+
+impl SeasonConfig {
+    pub fn growth(&self) -> f32 {
+        self.growth.unwrap_or(self.season.growth())
+    }
+
+    pub fn metabolism(&self) -> f32 {
+        self.metabolism.unwrap_or(self.season.metabolism())
+    }
+}
+
+/// 按时间表发生的天气
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WeatherEventConfig {
+    pub weather: Weather,
+    pub start: f32,    // 关卡开始后多少秒发生
+    pub duration: f32, // 持续时间（秒）
+}
+
+/// 随机发生的天气，每隔interval秒以chance的概率从kinds中选一种
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct RandomWeatherConfig {
+    pub interval: f32,
+    pub chance: f32,
+    pub duration: f32,
+    pub kinds: Vec<Weather>,
+}
+
+impl Default for RandomWeatherConfig {
+    fn default() -> Self {
+        Self {
+            interval: 60.0,
+            chance: 0.3,
+            duration: 20.0,
+            kinds: vec![Weather::Drought, Weather::Flood, Weather::ColdSnap],
+        }
+    }
+}
+
+/// 关卡中的环境配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct EnvironmentConfig {
+    pub seasons: Vec<SeasonConfig>,       // 按顺序循环，为空则没有季节变化
+    pub day_length: f32,                  // 一个昼夜的时长（秒），0表示没有昼夜
+    pub night_fraction: f32,              // 夜晚占一个昼夜的比例
+    pub night_vision: f32,                // 夜间的视野系数
+    pub night_activity: f32,              // 夜间的移动速度系数
+    pub weather: Vec<WeatherEventConfig>, // 按时间表发生的天气
+    pub random_weather: Option<RandomWeatherConfig>, // 随机天气，不配置则不发生
+}
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self {
+            seasons: Vec::new(),
+            day_length: 120.0,
+            night_fraction: 0.4,
+            night_vision: 0.5,
+            night_activity: 0.6,
+            weather: Vec::new(),
+            random_weather: None,
+        }
+    }
+}
+
+/// 正在发生的天气
+#[derive(Debug, Clone)]
+pub struct ActiveWeather {
+    pub weather: Weather,
+    pub remaining: f32,
+    pub flooded: Vec<HexMapPosition>, // 洪水淹没的地块，天气结束时恢复
+}
+
+/// 当前关卡开启了环境系统
+#[derive(Resource, Debug, Clone)]
+pub struct Environment {
+    pub config: EnvironmentConfig,
+    pub elapsed: f32, // 关卡开始以来的时间
+    pub weather: Option<ActiveWeather>,
+    next_event: usize,                    // 时间表中下一个天气
+    random_elapsed: f32,                  // 上次随机天气判定以来的时间
+    terrain_changes: Vec<HexMapPosition>, // 淹没状态发生变化的地块，供渲染使用
+}
+
+impl Environment {
+    pub fn new(mut config: EnvironmentConfig) -> Self {
+        config.weather.sort_by(|a, b| a.start.total_cmp(&b.start));
+        Self {
+            config,
+            elapsed: 0.0,
+            weather: None,
+            next_event: 0,
+            random_elapsed: 0.0,
+            terrain_changes: Vec::new(),
+        }
+    }
+
+    /// 当前季节，没有配置季节时为None
+    pub fn season(&self) -> Option<&SeasonConfig> {
+        let cycle: f32 = self
+            .config
+            .seasons
+            .iter()
+            .map(|s| s.duration.max(0.0))
+            .sum();
+        if cycle <= 0.0 {
+            return self.config.seasons.first();
+        }
+        let mut t = self.elapsed % cycle;
+        for season in self.config.seasons.iter() {
+            if t < season.duration {
+                return Some(season);
+            }
+            t -= season.duration.max(0.0);
+        }
+        self.config.seasons.last()
+    }
+
+    /// 一个昼夜中的进度，0为清晨
+    fn day_phase(&self) -> Option<f32> {
+        (self.config.day_length > 0.0)
+            .then(|| (self.elapsed % self.config.day_length) / self.config.day_length)
+    }
+
+    /// 白天为1，夜晚为0，黄昏和黎明时渐变
+    pub fn daylight(&self) -> f32 {
+        let Some(phase) = self.day_phase() else {
+            return 1.0;
+        };
+        let night_start = 1.0 - self.config.night_fraction.clamp(0.0, 1.0);
+        let dusk = ((night_start - phase) / TWILIGHT).clamp(0.0, 1.0);
+        let dawn = ((phase - (1.0 - TWILIGHT)) / TWILIGHT).clamp(0.0, 1.0);
+        dusk.max(dawn)
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.daylight() < 0.5
+    }
+
+    /// 植物生长速度系数
+    pub fn growth_factor(&self) -> f32 {
+        self.season().map_or(1.0, |s| s.growth())
+            * self.weather.as_ref().map_or(1.0, |w| w.weather.growth())
+    }
+
+    /// 基础代谢系数
+    pub fn metabolism_factor(&self) -> f32 {
+        self.season().map_or(1.0, |s| s.metabolism())
+            * self
+                .weather
+                .as_ref()
+                .map_or(1.0, |w| w.weather.metabolism())
+    }
+
+    /// 视野半径，夜间缩小但至少能看到相邻的地块
+    pub fn vision_radius(&self, radius: i32) -> i32 {
+        let factor = lerp(self.config.night_vision, 1.0, self.daylight());
+        ((radius as f32 * factor).round() as i32).max(radius.min(1))
+    }
+
+    /// 移动速度系数
+    pub fn activity_factor(&self) -> f32 {
+        lerp(self.config.night_activity, 1.0, self.daylight())
+    }
+
+    /// 地图的整体色调，各通道与地块颜色相乘
+    pub fn tint(&self) -> Vec3 {
+        let season = self.season().map_or(Vec3::ONE, |s| s.season.tint());
+        let weather = self
+            .weather
+            .as_ref()
+            .map_or(Vec3::ONE, |w| w.weather.tint());
+        // 夜间整体变暗，蓝色保留得多一些
+        let brightness = lerp(NIGHT_BRIGHTNESS, 1.0, self.daylight());
+        let night = Vec3::new(brightness, brightness, brightness.sqrt());
+        season * weather * night
+    }
+
+    /// 推进环境时间，天气开始或结束时修改地块的通行状态
+    pub fn advance(&mut self, secs: f32, partition: &mut SpatialPartition) {
+        self.elapsed += secs;
+
+        if let Some(active) = self.weather.as_mut() {
+            active.remaining -= secs;
+            if active.remaining <= 0.0 {
+                self.end_weather(partition);
+            }
+        }
+
+        // 时间表中已经到期的天气，正在发生其它天气时顺延
+        if self.weather.is_none()
+            && let Some(event) = self.config.weather.get(self.next_event).cloned()
+            && event.start <= self.elapsed
+        {
+            self.next_event += 1;
+            self.start_weather(event.weather, event.duration, partition);
+        }
+
+        let Some(random) = self.config.random_weather.clone() else {
+            return;
+        };
+        self.random_elapsed += secs;
+        if self.random_elapsed < random.interval {
+            return;
+        }
+        self.random_elapsed = 0.0;
+        if self.weather.is_none()
+            && !random.kinds.is_empty()
+            && rand::random_bool(random.chance.clamp(0.0, 1.0) as f64)
+        {
+            let weather = random.kinds[rand::random_range(0..random.kinds.len())];
+            self.start_weather(weather, random.duration, partition);
+        }
+    }
+
+    fn start_weather(&mut self, weather: Weather, duration: f32, partition: &mut SpatialPartition) {
+        info!("weather {:?} starts for {}s", weather, duration);
+        let mut flooded = Vec::new();
+        if weather == Weather::Flood {
+            // 随机选一片地块，已经有动物的地块不会被淹没
+            let (width, height) = (partition.config.width, partition.config.height);
+            let center = HexMapPosition::new(
+                rand::random_range(0..width.max(1)) as i32,
+                rand::random_range(0..height.max(1)) as i32,
+            );
+            for pos in hex_spiral(center, FLOOD_RADIUS) {
+                if partition.is_valid_position(&pos)
+                    && !partition.is_occupied(&pos)
+                    && !partition.is_obstacle(&pos)
+                {
+                    partition.set_flooded(&pos, true);
+                    flooded.push(pos);
+                }
+            }
+            self.terrain_changes.extend(flooded.iter().copied());
+        }
+        self.weather = Some(ActiveWeather {
+            weather,
+            remaining: duration,
+            flooded,
+        });
+    }
+
+    fn end_weather(&mut self, partition: &mut SpatialPartition) {
+        let Some(active) = self.weather.take() else {
+            return;
+        };
+        info!("weather {:?} ends", active.weather);
+        for pos in active.flooded.iter() {
+            partition.set_flooded(pos, false);
+        }
+        self.terrain_changes.extend(active.flooded);
+    }
+
+    /// 取出自上次调用以来淹没状态发生变化的地块
+    pub fn take_terrain_changes(&mut self) -> Vec<HexMapPosition> {
+        std::mem::take(&mut self.terrain_changes)
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+/// 推进环境时间
+pub fn environment_system(
+    mut environment: ResMut<Environment>,
+    mut partition: ResMut<SpatialPartition>,
+    time: Res<Time>,
+) {
+    environment.advance(time.delta_secs(), &mut partition);
+}
+
+/// 同步地图色调和被淹没地块的颜色
+pub fn sync_environment_render_system(
+    mut environment: ResMut<Environment>,
+    partition: Res<SpatialPartition>,
+    mut states: ResMut<HexCellStates>,
+    colors: Res<MapCellColors>,
+    grid_q: Query<&Children, With<MapGridRoot>>,
+    material_q: Query<&MeshMaterial2d<HexagonBorderMaterial>>,
+    mut materials: ResMut<Assets<HexagonBorderMaterial>>,
+) {
+    for pos in environment.take_terrain_changes() {
+        let color = if partition.is_flooded(&pos) {
+            FLOOD_COLOR
+        } else if partition.is_obstacle(&pos) {
+            colors.obstacle
+        } else {
+            colors.normal
+        };
+        states.set_terrain(&pos, color);
+    }
+
+    let tint = environment.tint();
+    let handles = grid_q
+        .iter()
+        .flat_map(|children| children.iter())
+        .filter_map(|child| material_q.get(child).ok());
+    for handle in handles {
+        // 只读访问不会触发材质重新上传
+        let unchanged = materials.get(&handle.0).is_none_or(|material| {
+            let current = Vec3::new(material.tint.red, material.tint.green, material.tint.blue);
+            current.distance(tint) < TINT_EPSILON
+        });
+        if !unchanged && let Some(material) = materials.get_mut(&handle.0) {
+            material.tint = LinearRgba::rgb(tint.x, tint.y, tint.z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::HexGridConfig;
+
+    #[test]
+    fn test_environment_config_ron() {
+        let config = ron::de::from_str::<EnvironmentConfig>(
+            "(seasons:[(season:winter,duration:60.0)],weather:[(weather:cold_snap,start:10.0,duration:5.0)])",
+        )
+        .unwrap();
+        assert_eq!(config.seasons[0].growth(), 0.3);
+        assert_eq!(config.weather[0].weather, Weather::ColdSnap);
+        assert_eq!(config.day_length, 120.0);
+    }
+
+    #[test]
+    fn test_environment_cycle() {
+        let config = EnvironmentConfig {
+            seasons: vec![
+                SeasonConfig {
+                    season: Season::Summer,
+                    duration: 30.0,
+                    growth: None,
+                    metabolism: None,
+                },
+                SeasonConfig {
+                    season: Season::Winter,
+                    duration: 30.0,
+                    growth: Some(0.5),
+                    metabolism: None,
+                },
+            ],
+            day_length: 20.0,
+            weather: vec![
+                WeatherEventConfig {
+                    weather: Weather::ColdSnap,
+                    start: 40.0,
+                    duration: 5.0,
+                },
+                WeatherEventConfig {
+                    weather: Weather::Flood,
+                    start: 50.0,
+                    duration: 5.0,
+                },
+            ],
+            ..Default::default()
+        };
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        let mut environment = Environment::new(config);
+
+        // 清晨的夏季：正常视野，生长按季节默认值
+        environment.advance(1.0, &mut partition);
+        assert_eq!(environment.season().unwrap().season, Season::Summer);
+        assert!(!environment.is_night());
+        assert_eq!(environment.vision_radius(10), 10);
+        assert_eq!(environment.growth_factor(), 1.0);
+
+        // 夜晚视野减半，移动变慢
+        environment.advance(15.0, &mut partition);
+        assert!(environment.is_night());
+        assert_eq!(environment.vision_radius(10), 5);
+        assert!(environment.activity_factor() < 1.0);
+
+        // 冬季覆盖了生长系数，寒潮叠加在季节之上
+        environment.advance(25.0, &mut partition);
+        assert_eq!(environment.season().unwrap().season, Season::Winter);
+        assert_eq!(
+            environment.weather.as_ref().unwrap().weather,
+            Weather::ColdSnap
+        );
+        assert_eq!(environment.growth_factor(), 0.5 * 0.3);
+        assert_eq!(environment.metabolism_factor(), 1.3 * 1.5);
+
+        // 寒潮结束后洪水淹没地块，地块不可通行但不遮挡视线
+        environment.advance(5.0, &mut partition);
+        assert!(environment.weather.is_none());
+        environment.advance(5.0, &mut partition);
+        let flooded = environment.weather.as_ref().unwrap().flooded.clone();
+        assert!(!flooded.is_empty());
+        assert!(flooded.iter().all(|pos| partition.is_obstacle(pos)));
+        assert_eq!(partition.obstacle_version(), 0);
+        assert_eq!(environment.take_terrain_changes(), flooded);
+
+        environment.advance(5.0, &mut partition);
+        assert!(environment.weather.is_none());
+        assert!(flooded.iter().all(|pos| !partition.is_obstacle(pos)));
+        // 季节循环回到夏季
+        environment.advance(10.0, &mut partition);
+        assert_eq!(environment.season().unwrap().season, Season::Summer);
+    }
+}
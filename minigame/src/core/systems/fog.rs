@@ -0,0 +1,126 @@
+//! 战争迷雾
+//!
+//! 关卡可以选择开启战争迷雾。玩家放置的实体会持续揭示周围的地块，购买侦察可以永久揭示一片区域。
+//! 观察状态记录在SpatialPartition中，这里负责跟随实体移动更新揭示范围、同步到地块状态纹理，
+//! 以及隐藏看不到的地块中的动物。
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::grid::HexCellStates;
+use super::hex_grid::{HexMapPosition, SpatialPartition};
+use crate::ai::AnimalActorBoard;
+
+/// 关卡中的战争迷雾配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct FogOfWarConfig {
+    pub start_radius: i32,  // 初始相机位置周围永久揭示的半径
+    pub reveal_radius: i32, // 玩家放置的实体揭示的半径
+    pub scout_radius: i32,  // 一次侦察揭示的半径
+    pub scout_cost: u32,    // 一次侦察消耗的金币
+}
+
+impl Default for FogOfWarConfig {
+    fn default() -> Self {
+        Self {
+            start_radius: 4,
+            reveal_radius: 3,
+            scout_radius: 4,
+            scout_cost: 5,
+        }
+    }
+}
+
+/// 当前关卡开启了战争迷雾
+#[derive(Resource, Debug, Clone)]
+pub struct FogOfWar {
+    pub config: FogOfWarConfig,
+    pub scouting: bool, // 已选择侦察，下一次点击地图时执行
+}
+
+impl FogOfWar {
+    pub fn new(config: FogOfWarConfig) -> Self {
+        Self {
+            config,
+            scouting: false,
+        }
+    }
+}
+
+/// 持续揭示周围地块的实体，动物移动后揭示范围跟随移动
+#[derive(Component, Debug, Clone)]
+pub struct FogRevealer {
+    pub radius: i32,
+    pub pos: HexMapPosition,          // 应当揭示的中心
+    revealed: Option<HexMapPosition>, // 当前已经揭示的中心
+}
+
+impl FogRevealer {
+    pub fn new(pos: HexMapPosition, radius: i32) -> Self {
+        Self {
+            radius,
+            pos,
+            revealed: None,
+        }
+    }
+}
+
+/// 揭示中心变化时先撤销旧的揭示再揭示新的位置
+pub fn update_fog_revealers_system(
+    mut query: Query<(&mut FogRevealer, Option<&AnimalActorBoard>)>,
+    mut partition: ResMut<SpatialPartition>,
+) {
+    for (mut revealer, board) in query.iter_mut() {
+        if let Some(board) = board {
+            revealer.pos = board.current_pos;
+        }
+        if revealer.revealed == Some(revealer.pos) {
+            continue;
+        }
+        if let Some(old) = revealer.revealed {
+            partition.conceal(&old, revealer.radius);
+        }
+        partition.reveal(&revealer.pos, revealer.radius);
+        revealer.revealed = Some(revealer.pos);
+    }
+}
+
+/// 揭示实体被移除（例如被吃掉）时撤销它的揭示
+pub fn on_remove_fog_revealer(
+    trigger: Trigger<OnRemove, FogRevealer>,
+    query: Query<&FogRevealer>,
+    partition: Option<ResMut<SpatialPartition>>,
+) {
+    let (Ok(revealer), Some(mut partition)) = (query.get(trigger.target()), partition) else {
+        return;
+    };
+    if let Some(pos) = revealer.revealed {
+        partition.conceal(&pos, revealer.radius);
+    }
+}
+
+/// 把迷雾等级的变化写入地块状态纹理
+pub fn sync_fog_cells_system(
+    mut partition: ResMut<SpatialPartition>,
+    mut cell_states: ResMut<HexCellStates>,
+) {
+    for pos in partition.take_fog_changes() {
+        cell_states.set_fog(&pos, partition.fog_level(&pos));
+    }
+}
+
+/// 隐藏看不到的地块中的动物
+pub fn hide_fogged_animals_system(
+    mut query: Query<(&AnimalActorBoard, &mut Visibility)>,
+    partition: Res<SpatialPartition>,
+) {
+    for (board, mut visibility) in query.iter_mut() {
+        let target = if partition.is_revealed(&board.current_pos) {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        visibility.set_if_neq(target);
+    }
+}
@@ -1,69 +1,296 @@
 //! 网格系统实现
-use crate::core::components::EntityType;
+//!
+//! 整张地图只用一个矩形网格和一个共享的HexagonBorderMaterial绘制，着色器根据世界坐标
+//! 反算所在的地块，再从地块状态纹理中读取颜色和选中标记。地形、悬停、选中等效果
+//! 都只是对HexCellStates的写入，每帧最多上传一次纹理。
 use crate::scenes::GameSceneRoot;
 
 use super::hex_grid::*;
+use super::hex_math::HexOrientation;
+use super::interaction::MapCellColors;
+use bevy::asset::RenderAssetUsages;
 use bevy::color::palettes::css::*;
+use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
 use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+
+/// 设计文档中的最大地图尺寸
+pub const MAX_MAP_SIZE: usize = 256;
+/// 最大地图尺寸下的目标帧率
+pub const TARGET_FPS: f64 = 60.0;
 
 #[derive(Component)]
 pub struct MapGridRoot;
 
+/// 单个地块的显示状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HexCellState {
+    pub terrain: LinearRgba,           // 地形底色
+    pub overlay: Option<LinearRgba>,   // 悬停、选中等高亮颜色，覆盖地形底色
+    pub highlight: Option<LinearRgba>, // 可投放区域等提示颜色，优先级低于overlay
+    pub flash: f32,                    // 点击闪光强度，0-1
+    pub selected: bool,                // 选中时使用加粗的高亮边框
+    pub fog: FogLevel,                 // 战争迷雾等级，由着色器变暗
+}
+
+/// 所有地块的显示状态，对应着色器读取的状态纹理
 #[derive(Resource)]
-pub struct SharedHexMesh(Handle<Mesh>);
+pub struct HexCellStates {
+    pub image: Handle<Image>,
+    width: usize,
+    height: usize,
+    cells: Vec<HexCellState>,
+    flash_color: LinearRgba,
+    dirty: Vec<usize>, // 自上次上传以来修改过的地块
+}
 
-/// 初始化网格和共享资源
-pub fn setup_grid(
-    mut commands: Commands,
-    config: Res<HexGridConfig>,
-    meshes: ResMut<Assets<Mesh>>,
-) {
-    let size = config.size;
-    commands.insert_resource(SharedHexMesh(create_hex_mesh(meshes, size)));
+impl HexCellStates {
+    pub fn new(
+        width: usize,
+        height: usize,
+        terrain: Color,
+        flash_color: Color,
+        images: &mut Assets<Image>,
+    ) -> Self {
+        let state = HexCellState {
+            terrain: terrain.to_linear(),
+            overlay: None,
+            highlight: None,
+            flash: 0.0,
+            selected: false,
+            fog: FogLevel::Visible,
+        };
+        let mut states = Self {
+            image: Handle::default(),
+            width,
+            height,
+            cells: vec![state; width * height],
+            flash_color: flash_color.to_linear(),
+            dirty: Vec::new(),
+        };
+
+        // 主世界保留一份数据，后续只修改变化的像素
+        let image = Image::new_fill(
+            Extent3d {
+                width: width as u32,
+                height: height as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &Self::encode_with(&state, states.flash_color),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+        states.image = images.add(image);
+        states
+    }
+
+    pub fn get(&self, pos: &HexMapPosition) -> Option<&HexCellState> {
+        self.index(pos).map(|index| &self.cells[index])
+    }
+
+    /// 修改地块状态，状态确实变化时才需要重新上传
+    pub fn update(&mut self, pos: &HexMapPosition, f: impl FnOnce(&mut HexCellState)) {
+        let Some(index) = self.index(pos) else {
+            return;
+        };
+        let old = self.cells[index];
+        f(&mut self.cells[index]);
+        if self.cells[index] != old {
+            self.dirty.push(index);
+        }
+    }
+
+    pub fn set_terrain(&mut self, pos: &HexMapPosition, color: Color) {
+        self.update(pos, |cell| cell.terrain = color.to_linear());
+    }
+
+    pub fn set_overlay(&mut self, pos: &HexMapPosition, color: Option<Color>) {
+        self.update(pos, |cell| cell.overlay = color.map(|c| c.to_linear()));
+    }
+
+    pub fn set_highlight(&mut self, pos: &HexMapPosition, color: Option<Color>) {
+        self.update(pos, |cell| cell.highlight = color.map(|c| c.to_linear()));
+    }
+
+    pub fn set_flash(&mut self, pos: &HexMapPosition, flash: f32) {
+        self.update(pos, |cell| cell.flash = flash.clamp(0.0, 1.0));
+    }
+
+    pub fn set_selected(&mut self, pos: &HexMapPosition, selected: bool) {
+        self.update(pos, |cell| cell.selected = selected);
+    }
+
+    pub fn set_fog(&mut self, pos: &HexMapPosition, fog: FogLevel) {
+        self.update(pos, |cell| cell.fog = fog);
+    }
+
+    pub fn has_changes(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// 把修改过的地块写入状态纹理
+    pub fn upload(&mut self, images: &mut Assets<Image>) {
+        if self.dirty.is_empty() {
+            return;
+        }
+        let Some(data) = images.get_mut(&self.image).and_then(|i| i.data.as_mut()) else {
+            return;
+        };
+        for index in self.dirty.drain(..) {
+            let pixel = Self::encode_with(&self.cells[index], self.flash_color);
+            data[index * 4..index * 4 + 4].copy_from_slice(&pixel);
+        }
+    }
+
+    // rgb为最终填充色，a通道的bit0标记是否选中，bit1-2为迷雾等级
+    fn encode_with(state: &HexCellState, flash_color: LinearRgba) -> [u8; 4] {
+        let base = state.overlay.or(state.highlight).unwrap_or(state.terrain);
+        let color = base.mix(&flash_color, state.flash);
+        let mut pixel = Color::from(color).to_srgba().to_u8_array();
+        let fog = match state.fog {
+            FogLevel::Visible => 0,
+            FogLevel::Explored => 1,
+            FogLevel::Hidden => 2,
+        };
+        pixel[3] = state.selected as u8 | fog << 1;
+        pixel
+    }
+
+    fn index(&self, pos: &HexMapPosition) -> Option<usize> {
+        (pos.x >= 0
+            && pos.y >= 0
+            && (pos.x as usize) < self.width
+            && (pos.y as usize) < self.height)
+            .then(|| pos.y as usize * self.width + pos.x as usize)
+    }
 }
 
 /// 渲染网格系统
 pub fn render_grid_system(
     mut commands: Commands,
     mut partition: ResMut<SpatialPartition>,
-    shared_mesh: Res<SharedHexMesh>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<HexagonBorderMaterial>>,
+    colors: Res<MapCellColors>,
     root: Query<Entity, With<GameSceneRoot>>,
 ) {
     info!("render hex grid {:?}", partition.config.clone());
-    // let mut grids = vec![];
 
+    let config = partition.config.clone();
     let parent = commands
         .spawn((MapGridRoot, Transform::from_xyz(0.0, 0.0, 1.0)))
         .insert(ChildOf(root.single().unwrap()))
         .id();
 
-    // 构建所有地图格数组
-    for x in 0..partition.config.width as i32 {
-        for y in 0..partition.config.height as i32 {
+    // 地块的颜色或者纹理后面再处理吧，这里先暂时用绿色
+    let mut states = HexCellStates::new(
+        config.width,
+        config.height,
+        colors.normal,
+        colors.click_effect,
+        &mut images,
+    );
+
+    // 覆盖整张地图的矩形
+    let bounds = config.bounds();
+    commands.spawn((
+        Mesh2d(meshes.add(Rectangle::from_size(bounds.size()))),
+        MeshMaterial2d(materials.add(HexagonBorderMaterial {
+            border_color: WHITE.into(),
+            selected_border_color: Color::srgb(0.00, 1.00, 1.00).into(),
+            border_width: 0.05,
+            selected_border_width: 0.1,
+            hex_size: config.size,
+            grid_size: UVec2::new(config.width as u32, config.height as u32),
+            flat_top: (config.orientation == HexOrientation::Flat) as u32,
+            fog_strength: Vec2::new(0.45, 0.85),
+            tint: LinearRgba::WHITE,
+            cell_states: states.image.clone(),
+        })),
+        Transform::from_translation(bounds.center().extend(0.0)),
+        ChildOf(parent),
+    ));
+
+    // 地块没有对应的实体，交互状态都写在状态纹理中，悬停和点击直接换算地块坐标
+    for x in 0..config.width as i32 {
+        for y in 0..config.height as i32 {
             let pos = HexMapPosition::new(x, y);
-            let center = partition.grid_to_world(&pos.to_vec2());
-
-            // grids.push((
-            let cell = commands
-                .spawn((
-                    Mesh2d(shared_mesh.0.clone()),
-                    Transform::from_translation(center),
-                    MeshMaterial2d(materials.add(HexagonBorderMaterial {
-                        // 地块的颜色或者纹理后面再处理吧，这里先暂时用绿色
-                        // color: Color::srgb(0.58, 0.38, 0.00).into(),
-                        color: Color::srgb(0.1, 0.55, 0.2).into(),
-                        border_color: WHITE.into(),
-                        border_width: 0.05,
-                    })),
-                    pos,
-                ))
-                .insert(ChildOf(parent))
-                .id();
-            // 将cell存入partition对应坐标下数组的第一个元素
-            partition.insert_cache_entity(cell, &pos, EntityType::Cell);
+            states.set_fog(&pos, partition.fog_level(&pos));
+            if partition.is_obstacle(&pos) {
+                states.set_terrain(&pos, colors.obstacle);
+            }
         }
     }
+    // 初始的迷雾状态已经写入
+    partition.take_fog_changes();
+    states.upload(&mut images);
+    commands.insert_resource(states);
+}
 
-    // commands.spawn_batch(grids);
+/// 地块状态有变化时上传状态纹理
+pub fn upload_cell_states_system(
+    mut states: ResMut<HexCellStates>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if states.has_changes() {
+        states.upload(&mut images);
+    }
+}
+
+/// 定期检查平均帧率是否达到目标
+pub fn check_frame_rate_system(
+    diagnostics: Res<DiagnosticsStore>,
+    config: Res<HexGridConfig>,
+    time: Res<Time>,
+    mut timer: Local<Option<Timer>>,
+) {
+    let timer = timer.get_or_insert_with(|| Timer::from_seconds(5.0, TimerMode::Repeating));
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let Some(fps) = diagnostics
+        .get(&FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+    else {
+        return;
+    };
+    if fps < TARGET_FPS {
+        warn!(
+            "frame rate {:.1} below target {} on {}x{} map",
+            fps, TARGET_FPS, config.width, config.height
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_state_writes_only_changed_pixels() {
+        let mut images = Assets::<Image>::default();
+        let mut states = HexCellStates::new(4, 3, Color::BLACK, Color::WHITE, &mut images);
+        let pos = HexMapPosition::new(2, 1);
+
+        states.set_overlay(&HexMapPosition::new(9, 9), Some(Color::WHITE));
+        states.set_selected(&pos, false);
+        assert!(!states.has_changes());
+
+        states.set_overlay(&pos, Some(Color::srgb(1.0, 0.0, 0.0)));
+        states.set_selected(&pos, true);
+        states.upload(&mut images);
+        assert!(!states.has_changes());
+
+        let data = images.get(&states.image).unwrap().data.as_ref().unwrap();
+        let index = (pos.y as usize * 4 + pos.x as usize) * 4;
+        assert_eq!(&data[index..index + 4], &[255, 0, 0, 1]);
+        assert_eq!(&data[0..4], &[0, 0, 0, 0]);
+
+        states.set_fog(&pos, FogLevel::Hidden);
+        states.upload(&mut images);
+        let data = images.get(&states.image).unwrap().data.as_ref().unwrap();
+        assert_eq!(data[index + 3], 1 | 2 << 1);
+    }
 }
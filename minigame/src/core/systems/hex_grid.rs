@@ -1,10 +1,10 @@
-use bevy::math::ops::floor;
 use bevy::platform::collections::HashSet;
 use bevy::prelude::*;
 use bevy::render::render_resource::{AsBindGroup, ShaderRef};
 use bevy::sprite::Material2d;
 use bevy_egui::egui::ahash::{HashMap, HashMapExt};
 
+use super::hex_math::*;
 use crate::core::components::EntityType;
 
 /// 六边形网格坐标, x,y为奇行偏移坐标，q,r,s为立方体坐标
@@ -19,14 +19,24 @@ pub struct HexMapPosition {
 
 impl HexMapPosition {
     pub fn new(x: i32, y: i32) -> Self {
-        let q = x - (y - (y & 1)) / 2; // 奇行偏移修正
-        let r = y;
+        let cube = oddr_to_cube(IVec2::new(x, y));
         Self {
             x,
             y,
-            q,
-            r,
-            s: -q - r,
+            q: cube.x,
+            r: cube.y,
+            s: cube.z,
+        }
+    }
+
+    pub fn from_cube(cube: IVec3) -> Self {
+        let offset = cube_to_oddr(cube);
+        Self {
+            x: offset.x,
+            y: offset.y,
+            q: cube.x,
+            r: cube.y,
+            s: cube.z,
         }
     }
 
@@ -39,45 +49,35 @@ impl HexMapPosition {
     }
 
     pub fn add_cube_coord(&mut self, coord: &IVec3) -> HexMapPosition {
-        self.q += coord.x;
-        self.r += coord.y;
-        self.s += coord.z;
-        self.x = self.q + (self.r - (self.r & 1)) / 2;
-        self.y = self.r;
+        *self = Self::from_cube(self.cube_coord() + *coord);
         *self
     }
 
+    /// 沿直线朝target前进speed格，不会越过target
     pub fn move_towards(&mut self, target: &HexMapPosition, speed: f32, _config: &HexGridConfig) {
-        // 基于六边形网格的移动逻辑
-        let dx = (target.x - self.x).clamp(-1, 1);
-        let dy = (target.y - self.y).clamp(-1, 1);
-
-        self.x += dx * speed as i32;
-        self.y += dy * speed as i32;
+        let line = cube_line(self.cube_coord(), target.cube_coord());
+        let steps = (speed.max(0.0) as usize).min(line.len() - 1);
+        *self = Self::from_cube(line[steps]);
     }
 }
 
 impl From<IVec2> for HexMapPosition {
     fn from(pos: IVec2) -> Self {
-        let q = pos.x - (pos.y - (pos.y & 1)) / 2; // 奇行偏移修正
-        let r = pos.y;
-        Self {
-            x: pos.x as i32,
-            y: pos.y as i32,
-            q,
-            r,
-            s: -q - r,
-        }
+        Self::new(pos.x, pos.y)
     }
 }
 
 /// 六边形网格配置
+///
+/// 网格内部统一使用奇行偏移坐标。平顶布局相当于把尖顶布局沿对角线翻转（立方体坐标的q、r互换），
+/// 翻转不改变相邻关系和距离，只影响网格坐标和世界坐标之间的转换。
 #[derive(Debug, Resource, Clone)]
 pub struct HexGridConfig {
-    pub size: f32,       // 六边形边长
-    pub width: usize,    // 网格宽度(列数)
-    pub height: usize,   // 网格高度(行数)
-    pub move_speed: f32, // 默认移动速度
+    pub size: f32,                   // 六边形边长
+    pub width: usize,                // 网格宽度(列数)
+    pub height: usize,               // 网格高度(行数)
+    pub move_speed: f32,             // 默认移动速度
+    pub orientation: HexOrientation, // 六边形朝向
 }
 
 impl HexGridConfig {
@@ -87,30 +87,95 @@ impl HexGridConfig {
             width,
             height,
             move_speed,
+            orientation: HexOrientation::Pointy,
+        }
+    }
+
+    /// 按关卡中的朝向和地图尺寸创建，平顶地图的(列数, 行数)对应网格的(行数, 列数)
+    pub fn from_level(size: f32, orientation: HexOrientation, level_size: UVec2) -> Self {
+        let grid_size = level_to_grid(orientation, level_size.as_ivec2());
+        Self {
+            orientation,
+            ..Self::new(size, grid_size.x as usize, grid_size.y as usize, 0.0)
+        }
+    }
+
+    pub fn layout(&self) -> HexLayout {
+        HexLayout::new(self.orientation, self.size)
+    }
+
+    /// 关卡中的偏移坐标转网格坐标
+    pub fn level_to_grid(&self, pos: IVec2) -> IVec2 {
+        level_to_grid(self.orientation, pos)
+    }
+
+    /// 网格坐标转世界坐标（地图原点位于世界原点）
+    pub fn grid_to_world(&self, pos: &HexMapPosition) -> Vec2 {
+        self.layout()
+            .hex_to_pixel(self.layout_cube(pos.cube_coord()))
+    }
+
+    pub fn world_to_grid(&self, pos: Vec2) -> HexMapPosition {
+        HexMapPosition::from_cube(self.layout_cube(self.layout().pixel_to_hex(pos)))
+    }
+
+    // 网格的立方体坐标和布局的立方体坐标互相转换，平顶布局q、r互换，这个变换是自身的逆
+    fn layout_cube(&self, cube: IVec3) -> IVec3 {
+        match self.orientation {
+            HexOrientation::Pointy => cube,
+            HexOrientation::Flat => IVec3::new(cube.y, cube.x, cube.z),
+        }
+    }
+
+    /// 单个地块外接矩形的半宽和半高
+    pub fn cell_half_extents(&self) -> Vec2 {
+        let extents = Vec2::new(self.size * SQRT3 / 2.0, self.size);
+        match self.orientation {
+            HexOrientation::Pointy => extents,
+            HexOrientation::Flat => extents.yx(),
+        }
+    }
+
+    /// 整张地图覆盖的世界坐标范围
+    pub fn bounds(&self) -> Rect {
+        let (width, height) = (self.width as f32, self.height as f32);
+        // 按尖顶布局计算，奇数行向右错开半格
+        let min = Vec2::new(-self.size * SQRT3 / 2.0, -self.size);
+        let max = Vec2::new(
+            self.size * SQRT3 * width,
+            self.size * 1.5 * (height - 1.0) + self.size,
+        );
+        match self.orientation {
+            HexOrientation::Pointy => Rect::from_corners(min, max),
+            HexOrientation::Flat => Rect::from_corners(min.yx(), max.yx()),
         }
     }
 }
 
-pub fn world_to_grid(_pos: &Vec3, _hex_size: f32) -> HexMapPosition {
-    HexMapPosition::default()
+const SQRT3: f32 = 1.7320508;
+
+/// 关卡中的偏移坐标转网格坐标
+///
+/// 尖顶地图使用奇行偏移，与网格坐标相同；平顶地图使用奇列偏移(列, 行)，翻转后对应网格坐标(行, 列)
+pub fn level_to_grid(orientation: HexOrientation, pos: IVec2) -> IVec2 {
+    match orientation {
+        HexOrientation::Pointy => pos,
+        HexOrientation::Flat => pos.yx(),
+    }
+}
+
+/// 世界坐标转网格坐标（尖顶布局，地图原点位于世界原点）
+pub fn world_to_grid(pos: &Vec3, hex_size: f32) -> HexMapPosition {
+    let layout = HexLayout::new(HexOrientation::Pointy, hex_size);
+    HexMapPosition::from_cube(layout.pixel_to_hex(pos.xy()))
 }
 
 /// 计算两个六边形之间的距离
 pub fn hex_distance(a: &HexMapPosition, b: &HexMapPosition) -> i32 {
-    let dx = b.x - a.x;
-    let dy = b.y - a.y;
-    (dx.abs() + (dx + dy).abs() + dy.abs()) / 2
+    cube_distance(a.cube_coord(), b.cube_coord())
 }
 
-// 立方体坐标的6个方向向量 [2,4](@ref)
-pub const CUBE_DIRECTIONS: [IVec3; 6] = [
-    IVec3::new(1, -1, 0), // 右 → 东北
-    IVec3::new(1, 0, -1), // 右上 → 东
-    IVec3::new(0, 1, -1), // 左上 → 西北
-    IVec3::new(-1, 1, 0), // 左 → 西南
-    IVec3::new(-1, 0, 1), // 左下 → 西
-    IVec3::new(0, -1, 1), // 右下 → 东南
-];
+pub use super::hex_math::CUBE_DIRECTIONS;
 
 // pub fn get_neighbours(pos: &HexMapPosition) -> Vec<HexMapPosition> {
 //     CUBE_DIRECTIONS
@@ -119,35 +184,109 @@ pub const CUBE_DIRECTIONS: [IVec3; 6] = [
 //         .collect()
 // }
 
-#[derive(Debug, Resource, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Resource, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct EntityWithCoord {
     pub entity: Entity,
     pub pos: HexMapPosition,
 }
 
+/// 遍历以center为中心、半径为radius的环上的所有坐标（可能超出地图范围）
+pub fn hex_ring(center: HexMapPosition, radius: i32) -> Vec<HexMapPosition> {
+    cube_ring(center.cube_coord(), radius)
+        .into_iter()
+        .map(HexMapPosition::from_cube)
+        .collect()
+}
+
+/// 由内向外遍历以center为中心、半径不超过radius的所有坐标（可能超出地图范围）
+pub fn hex_spiral(center: HexMapPosition, radius: i32) -> Vec<HexMapPosition> {
+    cube_spiral(center.cube_coord(), radius)
+        .into_iter()
+        .map(HexMapPosition::from_cube)
+        .collect()
+}
+
+/// 分区块的边长（格）
+pub const PARTITION_CHUNK_SIZE: usize = 16;
+/// 未开启养分循环时的地块肥力，植物按正常速度生长
+pub const NEUTRAL_FERTILITY: f32 = 1.0;
+
+// 一个地块上的实体及其类型
+#[derive(Debug, Default, Clone)]
+struct CellEntities {
+    ground: Vec<(Entity, EntityType)>, // 地表实体
+    others: Vec<(Entity, EntityType)>, // 动物等其它实体
+}
+
+/// 分区块，统计块内各类型的实体数量，查询时可以跳过没有目标类型的块
+#[derive(Debug, Default, Clone)]
+struct PartitionChunk {
+    cells: Vec<CellEntities>,
+    counts: HashMap<EntityType, usize>,
+}
+
+impl PartitionChunk {
+    fn contains_type(&self, entity_type: &EntityType) -> bool {
+        self.counts.get(entity_type).is_some_and(|count| *count > 0)
+    }
+}
+
+/// 战争迷雾等级
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FogLevel {
+    #[default]
+    Visible, // 正在被观察
+    Explored, // 曾经看到过，看不到其中的动物
+    Hidden,   // 从未探明
+}
+
+// 开启战争迷雾时每个地块的观察状态
+#[derive(Debug, Clone)]
+struct FogCells {
+    observers: Vec<u16>, // 正在观察该地块的来源数量，永久揭示的地块至少为1
+    explored: Vec<bool>,
+}
+
 /// 空间分区系统
 #[derive(Debug, Resource)]
 pub struct SpatialPartition {
-    pub cell_entity: Vec<Entity>,
-    pub ground_entities: Vec<HashSet<Entity>>, //在此格内的地表实体
-    pub other_entities: Vec<HashSet<Entity>>,  //在此格内的实体
+    chunks: Vec<PartitionChunk>, // 按PARTITION_CHUNK_SIZE划分的分区块
+    chunks_x: usize,             // 横向的分区块数量
     pub entities_map: HashMap<EntityType, HashSet<EntityWithCoord>>,
+    pub changed_cells: Vec<HexMapPosition>, // 占用状态发生变化的地块，供增量寻路使用
+    obstacles: HashSet<HexMapPosition>,     // 不可通行且遮挡视线的地块
+    flooded: HashSet<HexMapPosition>,       // 被洪水淹没的地块，不可通行但不遮挡视线
+    obstacle_version: u32,                  // 障碍变化时递增，视野缓存据此失效
+    terrain_version: u32,                   // 通行状态变化时递增，寻路快照据此重建
+    fog: Option<FogCells>,                  // 战争迷雾，None表示关卡未开启
+    fertility: Option<Vec<f32>>,            // 地块肥力，None表示关卡未开启养分循环
+    fog_changes: Vec<HexMapPosition>,       // 迷雾等级发生变化的地块，供渲染使用
+    fog_version: u32,                       // 迷雾等级变化时递增，可投放区域据此刷新
     pub config: HexGridConfig,
 }
 
-const SQRT3: f32 = 1.7320508;
-
 impl SpatialPartition {
     pub fn new(config: HexGridConfig) -> Self {
-        let capacity = config.width * config.height;
-        let mut partitions = Vec::with_capacity(capacity);
-        partitions.resize_with(capacity, HashSet::new);
+        let chunks_x = config.width.div_ceil(PARTITION_CHUNK_SIZE);
+        let chunks_y = config.height.div_ceil(PARTITION_CHUNK_SIZE);
+        let chunk = PartitionChunk {
+            cells: vec![CellEntities::default(); PARTITION_CHUNK_SIZE * PARTITION_CHUNK_SIZE],
+            counts: HashMap::new(),
+        };
 
         Self {
-            cell_entity: vec![Entity::PLACEHOLDER; capacity],
-            ground_entities: partitions.clone(),
-            other_entities: partitions,
+            chunks: vec![chunk; chunks_x * chunks_y],
+            chunks_x,
             entities_map: HashMap::new(),
+            changed_cells: Vec::new(),
+            obstacles: HashSet::new(),
+            flooded: HashSet::new(),
+            obstacle_version: 0,
+            terrain_version: 0,
+            fog: None,
+            fog_changes: Vec::new(),
+            fog_version: 0,
+            fertility: None,
             config,
         }
     }
@@ -160,9 +299,200 @@ impl SpatialPartition {
             && pos.y < self.config.height as i32
     }
 
-    pub fn is_obstacle(&self, _pos: &HexMapPosition) -> bool {
-        //TODO : 实体检查
-        return false;
+    /// 地块是否不可通行
+    pub fn is_obstacle(&self, pos: &HexMapPosition) -> bool {
+        self.obstacles.contains(pos) || self.flooded.contains(pos)
+    }
+
+    /// 设置地块是否为障碍（岩石、树林等阻挡地形），障碍同时遮挡视线
+    pub fn set_obstacle(&mut self, pos: &HexMapPosition, blocked: bool) {
+        let changed = if blocked {
+            self.obstacles.insert(*pos)
+        } else {
+            self.obstacles.remove(pos)
+        };
+        if changed {
+            self.obstacle_version += 1;
+            self.terrain_version += 1;
+            self.changed_cells.push(*pos);
+        }
+    }
+
+    pub fn obstacle_version(&self) -> u32 {
+        self.obstacle_version
+    }
+
+    pub fn terrain_version(&self) -> u32 {
+        self.terrain_version
+    }
+
+    pub fn is_flooded(&self, pos: &HexMapPosition) -> bool {
+        self.flooded.contains(pos)
+    }
+
+    /// 设置地块是否被淹没，淹没的地块不可通行，但不影响视线
+    pub fn set_flooded(&mut self, pos: &HexMapPosition, flooded: bool) {
+        let changed = if flooded {
+            self.flooded.insert(*pos)
+        } else {
+            self.flooded.remove(pos)
+        };
+        if changed {
+            self.terrain_version += 1;
+            self.changed_cells.push(*pos);
+        }
+    }
+
+    /// from和to之间的视线是否没有被遮挡，两端的地块本身不算遮挡
+    pub fn has_line_of_sight(&self, from: &HexMapPosition, to: &HexMapPosition) -> bool {
+        if self.obstacles.is_empty() {
+            return true;
+        }
+        let line = cube_line(from.cube_coord(), to.cube_coord());
+        line.iter()
+            .skip(1)
+            .take(line.len().saturating_sub(2))
+            .all(|cube| !self.obstacles.contains(&HexMapPosition::from_cube(*cube)))
+    }
+
+    /// 从center能看到的radius范围内的地块，由近到远排列
+    ///
+    /// 对每个地块做一次直线追踪，障碍本身可见，障碍后面的地块不可见
+    pub fn visible_cells(&self, center: &HexMapPosition, radius: i32) -> Vec<HexMapPosition> {
+        let radius = radius.min((self.config.width + self.config.height) as i32);
+        cube_spiral(center.cube_coord(), radius)
+            .into_iter()
+            .map(HexMapPosition::from_cube)
+            .filter(|pos| self.is_valid_position(pos) && self.has_line_of_sight(center, pos))
+            .collect()
+    }
+
+    /// 地块上是否有动物等非地表实体
+    pub fn is_occupied(&self, pos: &HexMapPosition) -> bool {
+        self.is_valid_position(pos) && !self.cell(pos).others.is_empty()
+    }
+
+    /// 开启战争迷雾，所有地块回到未探明状态
+    pub fn enable_fog(&mut self) {
+        let capacity = self.config.width * self.config.height;
+        self.fog = Some(FogCells {
+            observers: vec![0; capacity],
+            explored: vec![false; capacity],
+        });
+    }
+
+    pub fn fog_enabled(&self) -> bool {
+        self.fog.is_some()
+    }
+
+    pub fn fog_level(&self, pos: &HexMapPosition) -> FogLevel {
+        let Some(fog) = self.fog.as_ref() else {
+            return FogLevel::Visible;
+        };
+        if !self.is_valid_position(pos) {
+            return FogLevel::Hidden;
+        }
+        let index = self.get_index(pos);
+        if fog.observers[index] > 0 {
+            FogLevel::Visible
+        } else if fog.explored[index] {
+            FogLevel::Explored
+        } else {
+            FogLevel::Hidden
+        }
+    }
+
+    /// 地块是否已揭示，未开启战争迷雾时总是true
+    pub fn is_revealed(&self, pos: &HexMapPosition) -> bool {
+        self.fog_level(pos) == FogLevel::Visible
+    }
+
+    /// 揭示center周围radius范围内的地块，不再调用conceal时即为永久揭示
+    pub fn reveal(&mut self, center: &HexMapPosition, radius: i32) {
+        self.update_observers(center, radius, |observers| *observers += 1);
+    }
+
+    /// 撤销一次reveal
+    pub fn conceal(&mut self, center: &HexMapPosition, radius: i32) {
+        self.update_observers(center, radius, |observers| {
+            *observers = observers.saturating_sub(1)
+        });
+    }
+
+    fn update_observers(&mut self, center: &HexMapPosition, radius: i32, f: impl Fn(&mut u16)) {
+        if self.fog.is_none() {
+            return;
+        }
+        for cube in cube_range(center.cube_coord(), radius) {
+            let pos = HexMapPosition::from_cube(cube);
+            if !self.is_valid_position(&pos) {
+                continue;
+            }
+            let before = self.fog_level(&pos);
+            let index = self.get_index(&pos);
+            let fog = self.fog.as_mut().unwrap();
+            f(&mut fog.observers[index]);
+            fog.explored[index] |= fog.observers[index] > 0;
+            if self.fog_level(&pos) != before {
+                self.fog_changes.push(pos);
+                self.fog_version += 1;
+            }
+        }
+    }
+
+    /// 开启养分循环，所有地块的肥力从base开始
+    pub fn enable_fertility(&mut self, base: f32) {
+        self.fertility = Some(vec![base; self.config.width * self.config.height]);
+    }
+
+    /// 地块肥力，未开启养分循环时总是NEUTRAL_FERTILITY
+    pub fn fertility(&self, pos: &HexMapPosition) -> f32 {
+        match self.fertility.as_ref() {
+            Some(fertility) if self.is_valid_position(pos) => fertility[self.get_index(pos)],
+            _ => NEUTRAL_FERTILITY,
+        }
+    }
+
+    /// 向地块施加养分，肥力不超过max，返回实际增加的量
+    pub fn fertilize(&mut self, pos: &HexMapPosition, amount: f32, max: f32) -> f32 {
+        self.update_fertility(pos, |fertility| {
+            let added = amount.clamp(0.0, (max - *fertility).max(0.0));
+            *fertility += added;
+            added
+        })
+    }
+
+    /// 从地块吸收养分，返回实际吸收的量
+    pub fn deplete(&mut self, pos: &HexMapPosition, amount: f32) -> f32 {
+        self.update_fertility(pos, |fertility| {
+            let taken = amount.clamp(0.0, *fertility);
+            *fertility -= taken;
+            taken
+        })
+    }
+
+    fn update_fertility(&mut self, pos: &HexMapPosition, f: impl FnOnce(&mut f32) -> f32) -> f32 {
+        if !self.is_valid_position(pos) {
+            return 0.0;
+        }
+        let index = self.get_index(pos);
+        self.fertility
+            .as_mut()
+            .map_or(0.0, |fertility| f(&mut fertility[index]))
+    }
+
+    pub fn fog_version(&self) -> u32 {
+        self.fog_version
+    }
+
+    /// 取出自上次调用以来迷雾等级发生变化的地块
+    pub fn take_fog_changes(&mut self) -> Vec<HexMapPosition> {
+        std::mem::take(&mut self.fog_changes)
+    }
+
+    /// 取出自上次调用以来占用状态发生变化的地块
+    pub fn take_changed_cells(&mut self) -> Vec<HexMapPosition> {
+        std::mem::take(&mut self.changed_cells)
     }
 
     pub fn check_entity_conflict_by_pos(
@@ -170,18 +500,14 @@ impl SpatialPartition {
         entity_type: EntityType,
         pos: &HexMapPosition,
     ) -> bool {
-        let index = self.get_index(pos);
+        let cell = self.cell(pos);
         match entity_type {
             EntityType::Cell => false,
-            EntityType::Grass => self.ground_entities[index].is_empty(),
-            _ => self.other_entities[index].is_empty(),
+            t if t.is_ground() => cell.ground.is_empty(),
+            _ => cell.others.is_empty(),
         }
     }
 
-    pub fn get_cell_by_pos(&self, pos: &HexMapPosition) -> Entity {
-        self.cell_entity[self.get_index(pos)]
-    }
-
     pub fn get_valid_neighbours(&self, pos: &HexMapPosition) -> Vec<HexMapPosition> {
         CUBE_DIRECTIONS
             .iter()
@@ -191,61 +517,86 @@ impl SpatialPartition {
     }
 
     pub fn remove_entity(&mut self, entity: Entity, pos: &HexMapPosition, entity_type: EntityType) {
-        let index = self.get_index(pos);
         self.entities_map.get_mut(&entity_type).map(|entities| {
             entities.remove(&EntityWithCoord {
                 entity,
                 pos: pos.clone(),
             });
         });
-        match entity_type {
-            EntityType::Cell => {}
-            EntityType::Grass => {
-                self.ground_entities[index].remove(&entity);
-            }
-            _ => {
-                self.other_entities[index].remove(&entity);
-            }
+        let is_ground = entity_type.is_ground();
+        let chunk = self.chunk_mut(pos);
+        let cell = &mut chunk.cells[Self::local_index(pos)];
+        let layer = if is_ground {
+            &mut cell.ground
+        } else {
+            &mut cell.others
+        };
+        let Some(index) = layer.iter().position(|(e, _)| *e == entity) else {
+            return;
+        };
+        layer.swap_remove(index);
+        chunk
+            .counts
+            .entry(entity_type)
+            .and_modify(|count| *count -= 1);
+        if !is_ground {
+            self.changed_cells.push(*pos);
+        }
+    }
+
+    /// 实体从from移动到to
+    pub fn move_entity(
+        &mut self,
+        entity: Entity,
+        from: &HexMapPosition,
+        to: &HexMapPosition,
+        entity_type: EntityType,
+    ) {
+        if from == to {
+            return;
         }
+        self.remove_entity(entity, from, entity_type.clone());
+        self.insert_cache_entity(entity, to, entity_type);
     }
 
-    /// 获取分区索引
+    /// 获取地块索引
     fn get_index(&self, pos: &HexMapPosition) -> usize {
         (pos.y as usize * self.config.width) + pos.x as usize
     }
 
+    fn chunk_index(&self, pos: &HexMapPosition) -> usize {
+        (pos.y as usize / PARTITION_CHUNK_SIZE) * self.chunks_x
+            + pos.x as usize / PARTITION_CHUNK_SIZE
+    }
+
+    // 地块在所属分区块内的索引
+    fn local_index(pos: &HexMapPosition) -> usize {
+        (pos.y as usize % PARTITION_CHUNK_SIZE) * PARTITION_CHUNK_SIZE
+            + pos.x as usize % PARTITION_CHUNK_SIZE
+    }
+
+    fn chunk(&self, pos: &HexMapPosition) -> &PartitionChunk {
+        &self.chunks[self.chunk_index(pos)]
+    }
+
+    fn chunk_mut(&mut self, pos: &HexMapPosition) -> &mut PartitionChunk {
+        let index = self.chunk_index(pos);
+        &mut self.chunks[index]
+    }
+
+    fn cell(&self, pos: &HexMapPosition) -> &CellEntities {
+        &self.chunk(pos).cells[Self::local_index(pos)]
+    }
+
     /// 将网格坐标转换为世界坐标
     pub fn grid_to_world(&self, pos: &IVec2) -> Vec3 {
-        let hex_size = self.config.size;
-        let x = hex_size * SQRT3 * (pos.x as f32 + 0.5 * (pos.y as f32 % 2.0));
-        let y = hex_size * 1.5 * pos.y as f32;
-        Vec3::new(x, y, 0.0)
+        self.config
+            .grid_to_world(&HexMapPosition::from(*pos))
+            .extend(0.0)
     }
 
     pub fn world_to_grid(&self, pos: &Vec2) -> HexMapPosition {
-        let hex_size = self.config.size;
-        let x = floor((pos.x + SQRT3 * hex_size * 0.5) / (hex_size * SQRT3)) as i32;
-        let y = floor((pos.y + hex_size * 0.5) / (hex_size * 1.5)) as i32;
-        let mut result = HexMapPosition::new(x, y);
-        let mut distance = self
-            .grid_to_world(&result.to_vec2())
-            .xy()
-            .distance(pos.clone());
-
-        let center = result.clone();
-        for offset in CUBE_DIRECTIONS {
-            let neighbour = center.clone().add_cube_coord(&offset);
-            let new_distance = self
-                .grid_to_world(&neighbour.to_vec2())
-                .xy()
-                .distance(pos.clone());
-            if new_distance < distance {
-                distance = new_distance;
-                result = neighbour;
-            }
-        }
-
-        return result;
+        self.config.world_to_grid(*pos)
     }
 
     /// 添加实体到分区
@@ -255,39 +606,31 @@ impl SpatialPartition {
         pos: &HexMapPosition,
         entity_type: EntityType,
     ) {
-        let index = self.get_index(pos);
-        match entity_type {
-            EntityType::Cell => {
-                self.cell_entity[index] = entity;
-            }
-            EntityType::Grass => {
-                self.ground_entities[index].insert(entity);
-                self.entities_map
-                    .entry(entity_type)
-                    .or_insert_with(|| HashSet::new())
-                    .insert(EntityWithCoord {
-                        entity,
-                        pos: pos.clone(),
-                    });
-            }
-            _ => {
-                self.other_entities[index].insert(entity);
-                self.entities_map
-                    .entry(entity_type)
-                    .or_insert_with(|| HashSet::new())
-                    .insert(EntityWithCoord {
-                        entity,
-                        pos: pos.clone(),
-                    });
-            }
+        let is_ground = entity_type.is_ground();
+        let chunk = self.chunk_mut(pos);
+        let cell = &mut chunk.cells[Self::local_index(pos)];
+        if is_ground {
+            cell.ground.push((entity, entity_type.clone()));
+        } else {
+            cell.others.push((entity, entity_type.clone()));
+        }
+        *chunk.counts.entry(entity_type.clone()).or_insert(0) += 1;
+        if !is_ground {
+            self.changed_cells.push(*pos);
         }
+        self.entities_map
+            .entry(entity_type)
+            .or_default()
+            .insert(EntityWithCoord { entity, pos: *pos });
     }
 
     pub fn entities_at(&self, pos: &HexMapPosition) -> Vec<Entity> {
-        let index = self.get_index(pos);
-        let mut result = self.ground_entities[index].clone();
-        result.extend(self.other_entities[index].clone());
-        return result.into_iter().collect::<Vec<_>>();
+        let cell = self.cell(pos);
+        cell.ground
+            .iter()
+            .chain(cell.others.iter())
+            .map(|(entity, _)| *entity)
+            .collect()
     }
 
     pub fn entities_by_type(&self, entity_type: &EntityType) -> Vec<EntityWithCoord> {
@@ -296,24 +639,118 @@ impl SpatialPartition {
             .map_or(Vec::new(), |e| e.clone().into_iter().collect::<Vec<_>>())
     }
 
+    /// 地图上还有实体存活的物种
+    pub fn living_species(&self) -> HashSet<EntityType> {
+        self.entities_map
+            .iter()
+            .filter(|(t, entities)| t.is_living() && !entities.is_empty())
+            .map(|(t, _)| t.clone())
+            .collect()
+    }
+
+    /// 地图上某种类型的实体数量
+    pub fn count_by_type(&self, entity_type: &EntityType) -> usize {
+        self.entities_map.get(entity_type).map_or(0, |e| e.len())
+    }
+
+    /// pos处第一个类型为entity_type的实体
+    pub fn entity_of_type_at(
+        &self,
+        pos: &HexMapPosition,
+        entity_type: &EntityType,
+    ) -> Option<EntityWithCoord> {
+        self.entities_of_type_at(pos, entity_type).next()
+    }
+
+    // pos处类型为entity_type的实体，所在分区块没有该类型时直接跳过
+    fn entities_of_type_at<'a>(
+        &'a self,
+        pos: &HexMapPosition,
+        entity_type: &'a EntityType,
+    ) -> impl Iterator<Item = EntityWithCoord> + 'a {
+        let cell = (self.is_valid_position(pos) && self.chunk(pos).contains_type(entity_type))
+            .then(|| self.cell(pos));
+        let pos = *pos;
+        cell.into_iter()
+            .flat_map(|cell| cell.ground.iter().chain(cell.others.iter()))
+            .filter(move |(_, t)| t == entity_type)
+            .map(move |(entity, _)| EntityWithCoord {
+                entity: *entity,
+                pos,
+            })
+    }
+
+    /// 从center开始逐圈向外查找，返回radius范围内离center最近且满足filter的某类实体
+    ///
+    /// 开销只和搜索半径内的地块数有关，与地图大小和实体总数无关
+    pub fn nearest_of_type(
+        &self,
+        center: &HexMapPosition,
+        radius: i32,
+        entity_type: &EntityType,
+        mut filter: impl FnMut(&EntityWithCoord) -> bool,
+    ) -> Option<EntityWithCoord> {
+        if self.count_by_type(entity_type) == 0 {
+            return None;
+        }
+        // 半径超过地图尺寸后不会再有新的地块
+        let radius = radius.min((self.config.width + self.config.height) as i32);
+        for r in 0..=radius {
+            for pos in hex_ring(*center, r) {
+                if let Some(found) = self
+                    .entities_of_type_at(&pos, entity_type)
+                    .find(|e| filter(e))
+                {
+                    return Some(found);
+                }
+            }
+        }
+        None
+    }
+
+    /// radius范围内所有某类实体
+    pub fn entities_of_type_within(
+        &self,
+        center: &HexMapPosition,
+        radius: i32,
+        entity_type: &EntityType,
+    ) -> Vec<EntityWithCoord> {
+        let mut results = Vec::new();
+        if self.count_by_type(entity_type) == 0 {
+            return results;
+        }
+        let radius = radius.min((self.config.width + self.config.height) as i32);
+        for r in 0..=radius {
+            for pos in hex_ring(*center, r) {
+                results.extend(self.entities_of_type_at(&pos, entity_type));
+            }
+        }
+        results
+    }
+
+    /// 可见地块上的某类实体，顺序与cells一致
+    pub fn visible_entities_of(
+        &self,
+        cells: &[HexMapPosition],
+        entity_type: &EntityType,
+    ) -> Vec<EntityWithCoord> {
+        if self.count_by_type(entity_type) == 0 {
+            return Vec::new();
+        }
+        cells
+            .iter()
+            .flat_map(|pos| self.entities_of_type_at(pos, entity_type))
+            .collect()
+    }
+
     /// 查询附近实体
     pub fn query(&self, center: HexMapPosition, radius: i32) -> Vec<Entity> {
         let mut results = Vec::new();
 
-        for dy in -radius..=radius {
-            for dx in -radius..=radius {
-                if hex_distance(&center, &HexMapPosition::new(center.x + dx, center.y + dy))
-                    <= radius
-                {
-                    let pos = HexMapPosition::new(center.x + dx, center.y + dy);
-                    if pos.x >= 0
-                        && pos.x < self.config.width as i32
-                        && pos.y >= 0
-                        && pos.y < self.config.height as i32
-                    {
-                        let index = self.get_index(&pos);
-                        results.extend(&self.other_entities[index]);
-                    }
+        for r in 0..=radius {
+            for pos in hex_ring(center, r) {
+                if self.is_valid_position(&pos) {
+                    results.extend(self.cell(&pos).others.iter().map(|(entity, _)| *entity));
                 }
             }
         }
@@ -328,33 +765,35 @@ impl SpatialPartition {
 //     pub hex: HexCoord,
 // }
 
-// 自定义边框着色器
+// 自定义边框着色器，整张地图共享一个材质，地块颜色和选中状态从cell_states纹理读取
 #[derive(AsBindGroup, Debug, Clone, Asset, TypePath)]
 pub struct HexagonBorderMaterial {
-    #[uniform(0)]
-    pub color: LinearRgba,
     #[uniform(0)]
     pub border_color: LinearRgba,
     #[uniform(0)]
+    pub selected_border_color: LinearRgba,
+    #[uniform(0)]
     pub border_width: f32,
+    #[uniform(0)]
+    pub selected_border_width: f32,
+    #[uniform(0)]
+    pub hex_size: f32,
+    #[uniform(0)]
+    pub grid_size: UVec2,
+    #[uniform(0)]
+    pub flat_top: u32, // 非0时为平顶布局
+    #[uniform(0)]
+    pub fog_strength: Vec2, // 已探索和未探明地块的变暗程度，0-1
+    #[uniform(0)]
+    pub tint: LinearRgba, // 季节、昼夜和天气的整体色调，与最终颜色相乘
+    #[texture(1)]
+    pub cell_states: Handle<Image>,
 }
 
 impl Material2d for HexagonBorderMaterial {
     fn fragment_shader() -> ShaderRef {
         "shaders/hexagon_border.wgsl".into()
     }
-
-    // fn vertex_shader() -> ShaderRef {
-    //     "shaders/hexagon_border.wgsl".into()
-    // }
-}
-
-/// 创建可复用的六边形网格
-pub fn create_hex_mesh(mut meshes: ResMut<Assets<Mesh>>, size: f32) -> Handle<Mesh> {
-    let mesh = Mesh::from(RegularPolygon::new(size, 6));
-    // mesh.rotate_by(Quat::from_rotation_z(std::f32::consts::FRAC_PI_6)); // 平顶布局需要旋转30度
-    // 注意，旋转mesh会同时旋转了uv，所以计算时应当以尖顶六边形的uv来计算
-    meshes.add(mesh)
 }
 
 #[cfg(test)]
@@ -366,7 +805,76 @@ mod tests {
     fn test_hex_distance() {
         let a = HexMapPosition::new(0, 0);
         let b = HexMapPosition::new(3, 2);
-        assert_eq!(hex_distance(&a, &b), 3);
+        assert_eq!(hex_distance(&a, &b), 4);
+        // 奇行偏移下(0,1)和(1,0)、(0,0)相邻
+        let c = HexMapPosition::new(0, 1);
+        assert_eq!(hex_distance(&c, &HexMapPosition::new(1, 0)), 1);
+        assert_eq!(hex_distance(&c, &a), 1);
+    }
+
+    #[test]
+    fn test_world_grid_round_trip() {
+        let partition = SpatialPartition::new(HexGridConfig::new(32.0, 10, 10, 1.0));
+        for y in -3..10 {
+            for x in -3..10 {
+                let pos = HexMapPosition::new(x, y);
+                let world = partition.grid_to_world(&pos.to_vec2());
+                assert_eq!(partition.world_to_grid(&world.xy()), pos);
+                assert_eq!(world_to_grid(&world, 32.0), pos);
+                // 靠近边缘的点仍然属于该六边形
+                let inner = world.xy() + Vec2::new(0.8 * 32.0 * 0.866, 0.0);
+                assert_eq!(partition.world_to_grid(&inner), pos);
+            }
+        }
+        // 负数行和正数行一样向右错开半格
+        let odd = partition.grid_to_world(&IVec2::new(0, -1));
+        assert_eq!(odd.x, partition.grid_to_world(&IVec2::new(0, 1)).x);
+    }
+
+    #[test]
+    fn test_flat_layout_world_grid_round_trip() {
+        let config = HexGridConfig::from_level(60.0, HexOrientation::Flat, UVec2::new(8, 5));
+        assert_eq!((config.width, config.height), (5, 8));
+        let partition = SpatialPartition::new(config.clone());
+        let bounds = config.bounds();
+        for col in 0..8 {
+            for row in 0..5 {
+                let pos = config.level_to_grid(IVec2::new(col, row));
+                let world = partition.grid_to_world(&pos);
+                assert_eq!(
+                    partition.world_to_grid(&world.xy()),
+                    HexMapPosition::from(pos)
+                );
+                assert!(bounds.contains(world.xy()));
+                // 平顶布局同一列的地块竖直排列，奇数列向上错开半格
+                let expected = HexLayout::new(HexOrientation::Flat, 60.0)
+                    .hex_to_pixel(oddq_to_cube(IVec2::new(col, row)));
+                assert!(world.xy().distance(expected) < 1e-3);
+            }
+        }
+        // 相邻关系与尖顶布局一致，相邻地块中心距离为√3倍边长
+        let center = HexMapPosition::new(2, 2);
+        for neighbour in partition.get_valid_neighbours(&center) {
+            let d = partition
+                .grid_to_world(&neighbour.to_vec2())
+                .distance(partition.grid_to_world(&center.to_vec2()));
+            assert!((d - 60.0 * SQRT3).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_move_towards_follows_line() {
+        let config = HexGridConfig::new(1.0, 10, 10, 1.0);
+        let target = HexMapPosition::new(5, 4);
+        let mut pos = HexMapPosition::new(0, 0);
+        let mut steps = 0;
+        while pos != target {
+            let before = pos;
+            pos.move_towards(&target, 1.0, &config);
+            assert_eq!(hex_distance(&before, &pos), 1);
+            steps += 1;
+        }
+        assert_eq!(steps, hex_distance(&HexMapPosition::new(0, 0), &target));
     }
 
     #[test]
@@ -376,6 +884,7 @@ mod tests {
             width: 10,
             height: 10,
             move_speed: 1.0,
+            orientation: HexOrientation::Pointy,
         };
         let mut partition = SpatialPartition::new(config);
         let entity = Entity::from_raw(0);
@@ -385,4 +894,112 @@ mod tests {
 
         assert!(results.contains(&entity));
     }
+
+    #[test]
+    fn test_hex_ring() {
+        let center = HexMapPosition::new(10, 10);
+        assert_eq!(hex_ring(center, 0), vec![center]);
+        for radius in 1..4 {
+            let ring = hex_ring(center, radius);
+            assert_eq!(ring.len(), 6 * radius as usize);
+            let cube = center.cube_coord();
+            assert!(ring.iter().all(|p| {
+                let d = (p.cube_coord() - cube).abs();
+                d.x.max(d.y).max(d.z) == radius
+            }));
+        }
+    }
+
+    #[test]
+    fn test_nearest_of_type_across_chunks() {
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 40, 40, 1.0));
+        let center = HexMapPosition::new(15, 15);
+        let near = HexMapPosition::new(17, 15);
+        let far = HexMapPosition::new(22, 15);
+        partition.insert_cache_entity(Entity::from_raw(1), &near, EntityType::Grass);
+        partition.insert_cache_entity(Entity::from_raw(2), &far, EntityType::Grass);
+        partition.insert_cache_entity(Entity::from_raw(3), &center, EntityType::Rabbit);
+
+        let found = partition.nearest_of_type(&center, 10, &EntityType::Grass, |_| true);
+        assert_eq!(found.map(|e| e.pos), Some(near));
+
+        // 最近的已经被占用时返回下一个，超出半径的忽略
+        let skip_near = |e: &EntityWithCoord| e.pos != near;
+        let found = partition.nearest_of_type(&center, 10, &EntityType::Grass, skip_near);
+        assert_eq!(found.map(|e| e.pos), Some(far));
+        assert!(
+            partition
+                .nearest_of_type(&center, 5, &EntityType::Grass, skip_near)
+                .is_none()
+        );
+
+        partition.remove_entity(Entity::from_raw(1), &near, EntityType::Grass);
+        assert_eq!(
+            partition
+                .entities_of_type_within(&center, 3, &EntityType::Grass)
+                .len(),
+            0
+        );
+        assert_eq!(partition.count_by_type(&EntityType::Grass), 1);
+    }
+
+    #[test]
+    fn test_fog_reveal_and_conceal() {
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        let center = HexMapPosition::new(5, 5);
+        let edge = HexMapPosition::new(7, 5);
+        assert!(partition.is_revealed(&center));
+
+        partition.enable_fog();
+        assert_eq!(partition.fog_level(&center), FogLevel::Hidden);
+
+        // 两个来源重叠4个地块，撤销其中一个不影响另一个
+        partition.reveal(&center, 2);
+        partition.reveal(&edge, 1);
+        assert!(partition.is_revealed(&edge));
+        assert_eq!(partition.take_fog_changes().len(), 19 + 7 - 4);
+
+        partition.conceal(&center, 2);
+        assert!(partition.is_revealed(&edge));
+        assert_eq!(partition.fog_level(&center), FogLevel::Explored);
+        assert_eq!(partition.take_fog_changes().len(), 19 - 4);
+        assert_eq!(partition.fog_version(), 19 + 7 - 4 + 19 - 4);
+        assert_eq!(
+            partition.fog_level(&HexMapPosition::new(9, 9)),
+            FogLevel::Hidden
+        );
+    }
+
+    #[test]
+    fn test_visible_cells_blocked_by_obstacle() {
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 12, 12, 1.0));
+        let center = HexMapPosition::new(5, 5);
+        assert_eq!(partition.visible_cells(&center, 3).len(), 37);
+        // 靠近地图边缘时只返回地图内的地块
+        assert_eq!(
+            partition.visible_cells(&HexMapPosition::new(0, 0), 1).len(),
+            3
+        );
+
+        let wall = HexMapPosition::new(6, 5);
+        let behind = HexMapPosition::new(8, 5);
+        partition.set_obstacle(&wall, true);
+        assert!(partition.is_obstacle(&wall));
+        let visible = partition.visible_cells(&center, 3);
+        assert_eq!(visible[0], center);
+        assert!(visible.contains(&wall));
+        assert!(!visible.contains(&behind));
+        assert!(visible.contains(&HexMapPosition::new(5, 8)));
+        assert!(!partition.has_line_of_sight(&behind, &center));
+
+        partition.insert_cache_entity(Entity::from_raw(1), &behind, EntityType::Fox);
+        partition.insert_cache_entity(Entity::from_raw(2), &wall, EntityType::Fox);
+        let seen = partition.visible_entities_of(&visible, &EntityType::Fox);
+        assert_eq!(seen.iter().map(|e| e.pos).collect::<Vec<_>>(), vec![wall]);
+
+        let version = partition.obstacle_version();
+        partition.set_obstacle(&wall, false);
+        assert!(partition.obstacle_version() > version);
+        assert!(partition.visible_cells(&center, 3).contains(&behind));
+    }
 }
@@ -0,0 +1,356 @@
+//! 六边形网格数学
+//!
+//! 内部统一使用立方体坐标(q, r, s)，q + r + s = 0。轴向坐标为(q, r)；
+//! 偏移坐标中尖顶布局使用奇行偏移(odd-r)，平顶布局使用奇列偏移(odd-q)。
+//! 算法参考 https://www.redblobgames.com/grids/hexagons/
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const SQRT3: f32 = 1.7320508;
+
+/// 立方体坐标的6个方向，按逆时针顺序排列，相邻两项夹角为60度
+pub const CUBE_DIRECTIONS: [IVec3; 6] = [
+    IVec3::new(1, -1, 0), // 右 → 东北
+    IVec3::new(1, 0, -1), // 右上 → 东
+    IVec3::new(0, 1, -1), // 左上 → 西北
+    IVec3::new(-1, 1, 0), // 左 → 西南
+    IVec3::new(-1, 0, 1), // 左下 → 西
+    IVec3::new(0, -1, 1), // 右下 → 东南
+];
+
+/// 轴向坐标转立方体坐标
+pub fn axial_to_cube(axial: IVec2) -> IVec3 {
+    IVec3::new(axial.x, axial.y, -axial.x - axial.y)
+}
+
+/// 立方体坐标转轴向坐标
+pub fn cube_to_axial(cube: IVec3) -> IVec2 {
+    cube.xy()
+}
+
+/// 奇行偏移坐标（尖顶布局）转立方体坐标
+pub fn oddr_to_cube(offset: IVec2) -> IVec3 {
+    // 对负数行同样成立：-1 & 1 == 1
+    let q = offset.x - (offset.y - (offset.y & 1)) / 2;
+    axial_to_cube(IVec2::new(q, offset.y))
+}
+
+/// 立方体坐标转奇行偏移坐标（尖顶布局）
+pub fn cube_to_oddr(cube: IVec3) -> IVec2 {
+    IVec2::new(cube.x + (cube.y - (cube.y & 1)) / 2, cube.y)
+}
+
+/// 奇列偏移坐标（平顶布局）转立方体坐标
+pub fn oddq_to_cube(offset: IVec2) -> IVec3 {
+    let r = offset.y - (offset.x - (offset.x & 1)) / 2;
+    axial_to_cube(IVec2::new(offset.x, r))
+}
+
+/// 立方体坐标转奇列偏移坐标（平顶布局）
+pub fn cube_to_oddq(cube: IVec3) -> IVec2 {
+    IVec2::new(cube.x, cube.y + (cube.x - (cube.x & 1)) / 2)
+}
+
+/// 两个立方体坐标之间的步数
+pub fn cube_distance(a: IVec3, b: IVec3) -> i32 {
+    let d = (a - b).abs();
+    d.x.max(d.y).max(d.z)
+}
+
+/// 把小数立方体坐标取整到最近的六边形
+pub fn cube_round(frac: Vec3) -> IVec3 {
+    let mut rounded = frac.round();
+    let diff = (rounded - frac).abs();
+    if diff.x > diff.y && diff.x > diff.z {
+        rounded.x = -rounded.y - rounded.z;
+    } else if diff.y > diff.z {
+        rounded.y = -rounded.x - rounded.z;
+    } else {
+        rounded.z = -rounded.x - rounded.y;
+    }
+    rounded.as_ivec3()
+}
+
+/// 半径为radius的环，从CUBE_DIRECTIONS[4]方向的角开始逆时针排列
+pub fn cube_ring(center: IVec3, radius: i32) -> Vec<IVec3> {
+    if radius <= 0 {
+        return vec![center];
+    }
+    let mut results = Vec::with_capacity(6 * radius as usize);
+    let mut cube = center + CUBE_DIRECTIONS[4] * radius;
+    for direction in CUBE_DIRECTIONS.iter() {
+        for _ in 0..radius {
+            results.push(cube);
+            cube += *direction;
+        }
+    }
+    results
+}
+
+/// 由内向外逐圈排列的螺旋，包含中心，共1 + 3 * radius * (radius + 1)个
+pub fn cube_spiral(center: IVec3, radius: i32) -> Vec<IVec3> {
+    (0..=radius.max(0))
+        .flat_map(|r| cube_ring(center, r))
+        .collect()
+}
+
+/// 距离center不超过radius的所有六边形，按q、r排列
+pub fn cube_range(center: IVec3, radius: i32) -> Vec<IVec3> {
+    let mut results = Vec::new();
+    for q in -radius..=radius {
+        for r in (-radius).max(-q - radius)..=radius.min(-q + radius) {
+            results.push(center + IVec3::new(q, r, -q - r));
+        }
+    }
+    results
+}
+
+/// 从a到b的直线经过的六边形，包含两端，相邻两项互为邻居
+pub fn cube_line(a: IVec3, b: IVec3) -> Vec<IVec3> {
+    let n = cube_distance(a, b);
+    if n == 0 {
+        return vec![a];
+    }
+    // 加一点偏移避免恰好落在两个六边形的边上时取整结果不稳定
+    let nudge = Vec3::new(1e-4, 2e-4, -3e-4);
+    let (start, end) = (a.as_vec3() + nudge, b.as_vec3() + nudge);
+    (0..=n)
+        .map(|i| cube_round(start.lerp(end, i as f32 / n as f32)))
+        .collect()
+}
+
+/// 绕center逆时针旋转steps个60度，负数为顺时针
+pub fn cube_rotate(cube: IVec3, center: IVec3, steps: i32) -> IVec3 {
+    let mut v = cube - center;
+    for _ in 0..steps.rem_euclid(6) {
+        // 逆时针60度: (q, r, s) -> (-r, -s, -q)
+        v = IVec3::new(-v.y, -v.z, -v.x);
+    }
+    center + v
+}
+
+/// 六边形朝向
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HexOrientation {
+    #[default]
+    Pointy, // 尖顶，同一行的六边形左右相邻
+    Flat, // 平顶，同一列的六边形上下相邻
+}
+
+/// 六边形布局，负责像素坐标和六边形坐标之间的转换，y轴向上
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HexLayout {
+    pub orientation: HexOrientation,
+    pub size: f32,    // 六边形外接圆半径（边长）
+    pub origin: Vec2, // 立方体坐标原点对应的像素坐标
+}
+
+impl HexLayout {
+    pub fn new(orientation: HexOrientation, size: f32) -> Self {
+        Self {
+            orientation,
+            size,
+            origin: Vec2::ZERO,
+        }
+    }
+
+    pub fn hex_to_pixel(&self, cube: IVec3) -> Vec2 {
+        let (q, r) = (cube.x as f32, cube.y as f32);
+        let offset = match self.orientation {
+            HexOrientation::Pointy => Vec2::new(SQRT3 * q + SQRT3 / 2.0 * r, 1.5 * r),
+            HexOrientation::Flat => Vec2::new(1.5 * q, SQRT3 / 2.0 * q + SQRT3 * r),
+        };
+        self.origin + offset * self.size
+    }
+
+    pub fn pixel_to_hex(&self, pixel: Vec2) -> IVec3 {
+        let p = (pixel - self.origin) / self.size;
+        let (q, r) = match self.orientation {
+            HexOrientation::Pointy => (SQRT3 / 3.0 * p.x - p.y / 3.0, 2.0 / 3.0 * p.y),
+            HexOrientation::Flat => (2.0 / 3.0 * p.x, -p.x / 3.0 + SQRT3 / 3.0 * p.y),
+        };
+        cube_round(Vec3::new(q, r, -q - r))
+    }
+
+    /// 该布局下地图使用的偏移坐标转立方体坐标
+    pub fn offset_to_cube(&self, offset: IVec2) -> IVec3 {
+        match self.orientation {
+            HexOrientation::Pointy => oddr_to_cube(offset),
+            HexOrientation::Flat => oddq_to_cube(offset),
+        }
+    }
+
+    /// 立方体坐标转该布局下地图使用的偏移坐标
+    pub fn cube_to_offset(&self, cube: IVec3) -> IVec2 {
+        match self.orientation {
+            HexOrientation::Pointy => cube_to_oddr(cube),
+            HexOrientation::Flat => cube_to_oddq(cube),
+        }
+    }
+
+    /// 六边形的6个顶点
+    pub fn corners(&self, cube: IVec3) -> [Vec2; 6] {
+        let center = self.hex_to_pixel(cube);
+        let start = match self.orientation {
+            HexOrientation::Pointy => 30.0_f32,
+            HexOrientation::Flat => 0.0,
+        };
+        std::array::from_fn(|i| {
+            let angle = (start + 60.0 * i as f32).to_radians();
+            center + Vec2::new(angle.cos(), angle.sin()) * self.size
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RADIUS: i32 = 8;
+
+    fn all_cubes() -> Vec<IVec3> {
+        cube_range(IVec3::ZERO, RADIUS)
+    }
+
+    fn is_cube(c: IVec3) -> bool {
+        c.x + c.y + c.z == 0
+    }
+
+    #[test]
+    fn test_coordinate_round_trips() {
+        for cube in all_cubes() {
+            assert!(is_cube(cube));
+            assert_eq!(axial_to_cube(cube_to_axial(cube)), cube);
+            assert_eq!(oddr_to_cube(cube_to_oddr(cube)), cube);
+            assert_eq!(oddq_to_cube(cube_to_oddq(cube)), cube);
+        }
+        for y in -RADIUS..=RADIUS {
+            for x in -RADIUS..=RADIUS {
+                let offset = IVec2::new(x, y);
+                assert_eq!(cube_to_oddr(oddr_to_cube(offset)), offset);
+                assert_eq!(cube_to_oddq(oddq_to_cube(offset)), offset);
+            }
+        }
+    }
+
+    #[test]
+    fn test_offset_neighbours_across_odd_rows() {
+        // 奇行偏移下(0,0)到(3,2)需要4步，而不是按x/y计算的3步
+        let a = oddr_to_cube(IVec2::new(0, 0));
+        let b = oddr_to_cube(IVec2::new(3, 2));
+        assert_eq!(cube_distance(a, b), 4);
+        // 负数行的邻居
+        let c = oddr_to_cube(IVec2::new(0, -1));
+        for direction in CUBE_DIRECTIONS {
+            assert_eq!(cube_distance(c, c + direction), 1);
+        }
+        assert_eq!(cube_distance(c, oddr_to_cube(IVec2::new(0, 0))), 1);
+        assert_eq!(cube_distance(c, oddr_to_cube(IVec2::new(1, 0))), 1);
+    }
+
+    #[test]
+    fn test_distance_is_a_metric() {
+        let cubes = cube_range(IVec3::ZERO, 3);
+        for &a in cubes.iter() {
+            assert_eq!(cube_distance(a, a), 0);
+            for &b in cubes.iter() {
+                let ab = cube_distance(a, b);
+                assert_eq!(ab, cube_distance(b, a));
+                assert_eq!(ab == 0, a == b);
+                for &c in cubes.iter() {
+                    assert!(cube_distance(a, c) <= ab + cube_distance(b, c));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ring_spiral_and_range() {
+        let center = IVec3::new(2, -5, 3);
+        for radius in 0..=RADIUS {
+            let ring = cube_ring(center, radius);
+            assert_eq!(ring.len(), (6 * radius).max(1) as usize);
+            assert!(ring.iter().all(|c| cube_distance(*c, center) == radius));
+            // 环上相邻两项互为邻居，首尾相接
+            for i in 0..ring.len() {
+                let next = ring[(i + 1) % ring.len()];
+                assert!(radius == 0 || cube_distance(ring[i], next) == 1);
+            }
+
+            let count = (1 + 3 * radius * (radius + 1)) as usize;
+            let spiral = cube_spiral(center, radius);
+            let mut range = cube_range(center, radius);
+            assert_eq!(spiral.len(), count);
+            assert_eq!(range.len(), count);
+            assert!(range.iter().all(|c| cube_distance(*c, center) <= radius));
+
+            let mut sorted = spiral.clone();
+            sorted.sort_by_key(|c| (c.x, c.y));
+            range.sort_by_key(|c| (c.x, c.y));
+            assert_eq!(sorted, range);
+        }
+    }
+
+    #[test]
+    fn test_line_is_connected() {
+        let cubes = cube_range(IVec3::ZERO, 4);
+        for &a in cubes.iter() {
+            for &b in cubes.iter() {
+                let line = cube_line(a, b);
+                assert_eq!(line.len() as i32, cube_distance(a, b) + 1);
+                assert_eq!(line.first(), Some(&a));
+                assert_eq!(line.last(), Some(&b));
+                assert!(line.windows(2).all(|w| cube_distance(w[0], w[1]) == 1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotation() {
+        let center = IVec3::new(1, 1, -2);
+        for cube in all_cubes() {
+            let mut rotated = cube;
+            for _ in 0..6 {
+                rotated = cube_rotate(rotated, center, 1);
+                assert!(is_cube(rotated));
+                assert_eq!(cube_distance(rotated, center), cube_distance(cube, center));
+            }
+            assert_eq!(rotated, cube);
+            assert_eq!(cube_rotate(cube_rotate(cube, center, 2), center, -2), cube);
+        }
+        // 逆时针旋转60度正好是方向表中的下一个方向
+        for i in 0..6 {
+            assert_eq!(
+                cube_rotate(CUBE_DIRECTIONS[i], IVec3::ZERO, 1),
+                CUBE_DIRECTIONS[(i + 1) % 6]
+            );
+        }
+    }
+
+    #[test]
+    fn test_pixel_round_trips_for_both_layouts() {
+        for orientation in [HexOrientation::Pointy, HexOrientation::Flat] {
+            let layout = HexLayout {
+                orientation,
+                size: 50.0,
+                origin: Vec2::new(-30.0, 12.5),
+            };
+            for cube in all_cubes() {
+                let center = layout.hex_to_pixel(cube);
+                assert_eq!(layout.pixel_to_hex(center), cube);
+                // 稍微向内收缩的顶点仍然属于同一个六边形
+                for corner in layout.corners(cube) {
+                    let inside = center.lerp(corner, 0.9);
+                    assert_eq!(layout.pixel_to_hex(inside), cube);
+                }
+                // 相邻六边形中心的距离为√3倍边长
+                for direction in CUBE_DIRECTIONS {
+                    let neighbour = layout.hex_to_pixel(cube + direction);
+                    assert!((center.distance(neighbour) - SQRT3 * 50.0).abs() < 1e-2);
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,17 @@
 use std::f32::consts::PI;
 
-use crate::core::entities::{OnMapEntitiesRoot, spawn_entity};
+use crate::core::fog::FogOfWar;
+use crate::core::grid::{HexCellStates, MapGridRoot, upload_cell_states_system};
 use crate::core::hex_grid::SpatialPartition;
-use crate::core::systems::hex_grid::{HexMapPosition, HexagonBorderMaterial};
-use crate::level::config::EntityConfig;
+use crate::core::placement_preview::{
+    PlacementPreview, placement_preview_system, update_placement_ghost_system,
+    update_placement_tooltip_system,
+};
+use crate::core::player_action::{CardAction, CardActionEvent};
+use crate::core::systems::hex_grid::HexMapPosition;
 use crate::scenes::LevelGold;
 use crate::scenes::scene_selector::SceneSystemSet;
-use crate::sprite::sprite_mgr;
-use crate::ui::{CardSelectedMarker, EntityCardInfo, SelectedCardHolder, show_error_tips};
+use crate::ui::{CardPlacementRules, EntityCardInfo, SelectedCardHolder, show_error_tips};
 use bevy::input::mouse::MouseButton;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
@@ -17,10 +21,11 @@ pub struct GlobalMousePosition {
     pub is_in_primary_window: bool,
     pub pos: Vec2,
 }
+/// 选中和悬停的地块，地块没有对应的实体，直接记录坐标
 #[derive(Resource, Default)]
 pub struct SpecialMapCellHolder {
-    pub selected: Option<Entity>,
-    pub hovered: Option<Entity>,
+    pub selected: Option<HexMapPosition>,
+    pub hovered: Option<HexMapPosition>,
 }
 
 // 动画曲线类型
@@ -37,19 +42,16 @@ impl Default for AnimationCurve {
     }
 }
 
-// 交互状态组件
+// 交互状态组件，挂在选中地块时生成的特效实体上，特效实体带有地块坐标
 #[derive(Component)]
 pub struct MapCellSelectedMarker {
     pub timer: Timer,
 }
 
-#[derive(Component)]
-pub struct MapCellHoveredMarker;
-
 #[derive(Component)]
 pub struct ClickEffect {
     pub timer: Timer,
-    pub scale: f32,
+    pub intensity: f32, // 闪光的最大强度
 }
 
 // 交互配置资源
@@ -58,7 +60,7 @@ pub struct MapCellEffectConfig {
     pub click_radius: f32,
     pub hover_radius: f32,
     pub click_effect_duration: f32,
-    pub click_effect_scale: f32,
+    pub click_effect_intensity: f32,
     pub particle_speed: f32,
     pub particle_count: u32,
     pub animation_curve: AnimationCurve,
@@ -70,7 +72,7 @@ impl Default for MapCellEffectConfig {
             click_radius: 43.3,
             hover_radius: 43.3,
             click_effect_duration: 0.5,
-            click_effect_scale: 1.2,
+            click_effect_intensity: 0.6,
             particle_speed: 200.0,
             particle_count: 8,
             animation_curve: AnimationCurve::default(),
@@ -82,18 +84,26 @@ impl Default for MapCellEffectConfig {
 #[derive(Resource)]
 pub struct MapCellColors {
     pub normal: Color,
+    pub obstacle: Color, // 阻挡地形
     pub hovered: Color,
     pub selected: Color,
     pub click_effect: Color,
+    pub placeable: Color, // 选中卡片时可投放的地块
+    pub valid: Color,     // 鼠标所在的地块可以作用
+    pub invalid: Color,   // 鼠标所在的地块不能作用
 }
 
 impl Default for MapCellColors {
     fn default() -> Self {
         Self {
             normal: Color::srgb(0.1, 0.55, 0.2),
+            obstacle: Color::srgb(0.42, 0.38, 0.33),
             hovered: Color::srgb(0.10, 0.80, 0.25),
             selected: Color::srgb(0.80, 0.45, 0.20),
             click_effect: Color::srgb(1.0, 1.0, 1.0),
+            placeable: Color::srgb(0.20, 0.65, 0.55),
+            valid: Color::srgb(0.35, 0.90, 0.35),
+            invalid: Color::srgb(0.85, 0.20, 0.20),
         }
     }
 }
@@ -114,29 +124,26 @@ pub fn update_global_mouse_position_system(
 }
 
 // 点击检测系统
-// 1. 有选中卡片的情况下，点击到地图单元上尝试放置选中的卡片对应的实体
+// 1. 有选中卡片的情况下，点击到地图单元上检查卡片规则，通过后发送CardActionEvent执行卡片操作
 // 2. 无选中卡片的情况下，处理地图单元的选中/取消选中
+// 3. 开启战争迷雾时，未揭示的地块不响应点击；已选择侦察时，点击的位置执行侦察
 pub fn map_cell_click_system(
     mut commands: Commands,
     mouse: Res<ButtonInput<MouseButton>>,
     mouse_position: Res<GlobalMousePosition>,
     interaction: Res<MapCellEffectConfig>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
-    card_q: Query<&EntityCardInfo, With<CardSelectedMarker>>,
-    cell_q: Query<(
-        Entity,
-        &HexMapPosition,
-        &MeshMaterial2d<HexagonBorderMaterial>,
-        Has<MapCellSelectedMarker>,
-    )>,
-    root_q: Query<Entity, With<OnMapEntitiesRoot>>,
+    mut card_q: Query<&mut EntityCardInfo>,
+    selected_q: Query<Entity, With<MapCellSelectedMarker>>,
+    grid_q: Query<Entity, With<MapGridRoot>>,
     mut cell_holder: ResMut<SpecialMapCellHolder>,
     card_holder: Res<SelectedCardHolder>,
     mut partition: ResMut<SpatialPartition>,
-    sprite_mgr: ResMut<sprite_mgr::SpriteManager>,
-    mut materials: ResMut<Assets<HexagonBorderMaterial>>,
-    colors: Res<MapCellColors>,
+    mut cell_states: ResMut<HexCellStates>,
     mut level_gold: ResMut<LevelGold>,
+    mut fog: Option<ResMut<FogOfWar>>,
+    rules: Res<CardPlacementRules>,
+    mut actions: EventWriter<CardActionEvent>,
 ) {
     if !mouse.just_pressed(MouseButton::Left) || !mouse_position.is_in_primary_window {
         return;
@@ -149,109 +156,97 @@ pub fn map_cell_click_system(
             let selected_card = card_holder.0.clone();
             // 计算当前鼠标位置对应的地块坐标
             let cell_pos = partition.world_to_grid(&pos);
+            // 侦察可以揭示未探明的区域，消耗金币后永久揭示
+            if let Some(fog) = fog.as_mut()
+                && fog.scouting
+            {
+                fog.scouting = false;
+                if !partition.is_valid_position(&cell_pos) {
+                    return;
+                }
+                if fog.config.scout_cost > level_gold.0 {
+                    show_error_tips(&mut commands, "金币不足!");
+                    return;
+                }
+                partition.reveal(&cell_pos, fog.config.scout_radius);
+                level_gold.0 -= fog.config.scout_cost;
+                return;
+            }
+
             // 地块坐标在地图范围内
             if partition.is_valid_position(&cell_pos) {
                 if let Some(card) = selected_card {
-                    if let Ok(card_info) = card_q.get(card) {
+                    if let Ok(mut card_info) = card_q.get_mut(card) {
                         // 清除当前的选中地块
-                        if let Some(selected_cell) = cell_holder.selected {
-                            if let Ok((_, _, material, is_selected)) = cell_q.get(selected_cell) {
-                                if is_selected {
-                                    remove_cell_selected_mark(
-                                        &mut commands,
-                                        &mut cell_holder,
-                                        &mut materials,
-                                        &colors,
-                                        material,
-                                        selected_cell,
-                                    );
-                                }
-                            }
-                        }
-
-                        if card_info.cost > level_gold.0 {
-                            show_error_tips(&mut commands, "金币不足!");
-                            return;
-                        }
+                        remove_cell_selected_mark(
+                            &mut commands,
+                            &mut cell_holder,
+                            &mut cell_states,
+                            &selected_q,
+                        );
 
-                        if !partition
-                            .check_entity_conflict_by_pos(card_info.entity_type.clone(), &cell_pos)
-                        {
-                            show_error_tips(&mut commands, "位置冲突!");
+                        // 次数、冷却、投放范围、迷雾、金币、作用目标依次检查，失败时提示原因
+                        let target =
+                            match rules.check(&card_info, &cell_pos, &partition, level_gold.0) {
+                                Ok(target) => target,
+                                Err(tips) => {
+                                    show_error_tips(&mut commands, tips);
+                                    return;
+                                }
+                            };
+                        // 迁移卡片第一次点击只选中实体，第二次点击目的地时才执行
+                        if card_info.action == CardAction::Relocate && card_info.pending.is_none() {
+                            card_info.pending = target;
                             return;
                         }
-
-                        let parent = root_q.single().unwrap();
-                        // 选择了卡片，则处理投放
-                        // TODO 这里直接调用spawn会需要很多额外参数，可以考虑加入事件，避免这些参数冗余
-                        spawn_entity(
-                            &mut commands,
-                            &EntityConfig {
-                                entity_type: card_info.entity_type.clone(),
-                                pos: cell_pos.to_vec2(),
-                                health: None,
-                                reproduction_rate: None,
-                                growth_rate: None,
-                                ..Default::default()
-                            },
-                            &sprite_mgr,
-                            &mut partition,
-                            &parent,
-                        );
-                        level_gold.0 -= card_info.cost;
+                        actions.write(CardActionEvent {
+                            card,
+                            pos: cell_pos,
+                            target: card_info.pending.take().or(target),
+                        });
                     }
                 } else {
-                    if let Some(selected_cell) = cell_holder.selected {
-                        if let Ok((_, old_pos, material, is_selected)) = cell_q.get(selected_cell) {
-                            if old_pos.eq(&cell_pos) && is_selected {
-                                // TODO 可以尝试reset effect的timer
-                                return;
-                            } else {
-                                remove_cell_selected_mark(
-                                    &mut commands,
-                                    &mut cell_holder,
-                                    &mut materials,
-                                    &colors,
-                                    material,
-                                    selected_cell,
-                                );
-                            }
-                        }
+                    if !partition.is_revealed(&cell_pos) {
+                        return;
                     }
-                    let entity = partition.get_cell_by_pos(&cell_pos);
-                    if let Ok((entity, _, _, _)) = cell_q.get(entity) {
-                        cell_holder.selected = Some(entity);
-                        // 未选择卡片则处理选中地块
-                        commands
-                            .entity(entity)
-                            .insert(MapCellSelectedMarker {
-                                timer: Timer::from_seconds(2., TimerMode::Repeating),
-                            })
-                            .insert(ClickEffect {
-                                timer: Timer::from_seconds(
-                                    interaction.click_effect_duration,
-                                    TimerMode::Once,
-                                ),
-                                scale: interaction.click_effect_scale,
-                            });
+                    if cell_holder.selected == Some(cell_pos) {
+                        // TODO 可以尝试reset effect的timer
+                        return;
                     }
+                    remove_cell_selected_mark(
+                        &mut commands,
+                        &mut cell_holder,
+                        &mut cell_states,
+                        &selected_q,
+                    );
+                    let Ok(grid) = grid_q.single() else {
+                        return;
+                    };
+                    cell_holder.selected = Some(cell_pos);
+                    // 未选择卡片则处理选中地块
+                    commands.spawn((
+                        cell_pos,
+                        MapCellSelectedMarker {
+                            timer: Timer::from_seconds(2., TimerMode::Repeating),
+                        },
+                        ClickEffect {
+                            timer: Timer::from_seconds(
+                                interaction.click_effect_duration,
+                                TimerMode::Once,
+                            ),
+                            intensity: interaction.click_effect_intensity,
+                        },
+                        ChildOf(grid),
+                    ));
                 }
             } else if selected_card.is_none() {
-                // 不在地图范围内时，仅处理未选中卡片的情况，取消已经设置了selected的地块组件
-                if let Some(selected_cell) = cell_holder.selected {
-                    if let Ok((_, _, material, is_selected)) = cell_q.get(selected_cell) {
-                        if is_selected {
-                            remove_cell_selected_mark(
-                                &mut commands,
-                                &mut cell_holder,
-                                &mut materials,
-                                &colors,
-                                material,
-                                selected_cell,
-                            );
-                        }
-                    }
-                }
+                // 不在地图范围内时，仅处理未选中卡片的情况，取消已经选中的地块
+                remove_cell_selected_mark(
+                    &mut commands,
+                    &mut cell_holder,
+                    &mut cell_states,
+                    &selected_q,
+                );
             }
         }
     }
@@ -260,36 +255,28 @@ pub fn map_cell_click_system(
 fn remove_cell_selected_mark(
     commands: &mut Commands,
     cell_holder: &mut SpecialMapCellHolder,
-    materials: &mut Assets<HexagonBorderMaterial>,
-    colors: &MapCellColors,
-    material: &MeshMaterial2d<HexagonBorderMaterial>,
-    selected_cell: Entity,
+    cell_states: &mut HexCellStates,
+    selected_q: &Query<Entity, With<MapCellSelectedMarker>>,
 ) {
-    commands
-        .entity(selected_cell)
-        .remove::<MapCellSelectedMarker>()
-        .remove::<ClickEffect>();
-    cell_holder.selected = None;
-    materials.get_mut(material.0.id()).map(|m| {
-        m.color = colors.normal.to_linear();
-        m.border_color = Color::srgb(1.0, 1.0, 1.0).to_linear();
-        m.border_width = 0.05;
+    for entity in selected_q.iter() {
+        commands.entity(entity).despawn();
+    }
+    let Some(pos) = cell_holder.selected.take() else {
+        return;
+    };
+    cell_states.update(&pos, |cell| {
+        cell.overlay = None;
+        cell.flash = 0.0;
+        cell.selected = false;
     });
 }
 
-// 悬停检测系统, 遍历所有的地图块效率太低。应当建立地图块的图元信息二维数组，进行精准定位计算
+// 悬停检测系统，鼠标位置直接换算成地块坐标
 pub fn map_cell_hover_system(
-    mut commands: Commands,
     mouse_position: Res<GlobalMousePosition>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
-    mut query: Query<(
-        Entity,
-        &MeshMaterial2d<HexagonBorderMaterial>,
-        &HexMapPosition,
-        Has<MapCellHoveredMarker>,
-    )>,
     colors: Res<MapCellColors>,
-    mut materials: ResMut<Assets<HexagonBorderMaterial>>,
+    mut cell_states: ResMut<HexCellStates>,
     mut holder: ResMut<SpecialMapCellHolder>,
     partition: Res<SpatialPartition>,
 ) {
@@ -306,31 +293,21 @@ pub fn map_cell_hover_system(
 
             // 已经存在hover的cell则判断是否和当前位置的cell相同，不同的时候则取消之前的hovered的cell
             if let Some(hover_cell) = holder.hovered {
-                if let Ok((entity, material, pos, is_hovered)) = query.get_mut(hover_cell) {
-                    if pos.eq(&cell) {
-                        // 是当前地块hover直接返回
-                        return;
-                    } else if is_hovered {
-                        // is_hovered其实可以省略
-                        commands.entity(entity).remove::<MapCellHoveredMarker>();
-                        holder.hovered = None;
-                        materials.get_mut(material.0.id()).map(|m| {
-                            m.color = colors.normal.to_linear();
-                        });
-                    }
+                if hover_cell.eq(&cell) {
+                    // 是当前地块hover直接返回
+                    return;
+                }
+                holder.hovered = None;
+                // 选中地块的高亮由selected_effect_system维护
+                if holder.selected != Some(hover_cell) {
+                    cell_states.set_overlay(&hover_cell, None);
                 }
             }
 
-            // 当前hover的MapHexPosition只有在地图内时，才将对应的cell设置为hovered
-            if partition.is_valid_position(&cell) {
-                let entity = partition.get_cell_by_pos(&cell);
-                if let Ok((entity, material, _, _)) = query.get_mut(entity) {
-                    commands.entity(entity).insert(MapCellHoveredMarker);
-                    holder.hovered = Some(entity);
-                    materials.get_mut(material.0.id()).map(|m| {
-                        m.color = colors.hovered.to_linear();
-                    });
-                }
+            // 当前hover的MapHexPosition只有在地图内且已揭示时，才将对应的cell设置为hovered
+            if partition.is_valid_position(&cell) && partition.is_revealed(&cell) {
+                holder.hovered = Some(cell);
+                cell_states.set_overlay(&cell, Some(colors.hovered));
             }
         }
     }
@@ -340,20 +317,21 @@ pub fn map_cell_hover_system(
 pub fn click_effect_system(
     mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(Entity, &mut ClickEffect, &mut Transform)>,
+    mut query: Query<(Entity, &mut ClickEffect, &HexMapPosition)>,
+    mut cell_states: ResMut<HexCellStates>,
 ) {
-    for (entity, mut effect, mut transform) in query.iter_mut() {
+    for (entity, mut effect, pos) in query.iter_mut() {
         effect.timer.tick(time.delta());
         let progress = effect.timer.fraction();
 
-        // 脉冲动画：先放大后缩小
-        let current_scale = if progress < 0.5 {
-            lerp(1.0, effect.scale, progress * 2.0)
+        // 脉冲动画：先变亮后恢复
+        let flash = if progress < 0.5 {
+            lerp(0.0, effect.intensity, progress * 2.0)
         } else {
-            lerp(effect.scale, 1.0, (progress - 0.5) * 2.0)
+            lerp(effect.intensity, 0.0, (progress - 0.5) * 2.0)
         };
 
-        transform.scale = Vec3::splat(current_scale);
+        cell_states.set_flash(pos, flash);
 
         // 完成后自己移除
         if effect.timer.finished() {
@@ -362,9 +340,15 @@ pub fn click_effect_system(
     }
 }
 
-pub fn on_remove_click_effect_system(mut query: Query<&mut Transform, With<HexMapPosition>>) {
-    for mut trans in query.iter_mut() {
-        trans.scale = Vec3::splat(1.0);
+pub fn on_remove_click_effect_system(
+    mut removed: RemovedComponents<ClickEffect>,
+    query: Query<&HexMapPosition>,
+    mut cell_states: ResMut<HexCellStates>,
+) {
+    for entity in removed.read() {
+        if let Ok(pos) = query.get(entity) {
+            cell_states.set_flash(pos, 0.0);
+        }
     }
 }
 
@@ -372,27 +356,52 @@ pub fn on_remove_click_effect_system(mut query: Query<&mut Transform, With<HexMa
 pub fn selected_effect_system(
     time: Res<Time>,
     colors: Res<MapCellColors>,
-    mut materials: ResMut<Assets<HexagonBorderMaterial>>,
-    mut query: Query<(
-        &mut MapCellSelectedMarker,
-        &mut MeshMaterial2d<HexagonBorderMaterial>,
-    )>,
+    mut cell_states: ResMut<HexCellStates>,
+    mut query: Query<(&mut MapCellSelectedMarker, &HexMapPosition)>,
 ) {
-    for (mut selected, material) in query.iter_mut() {
+    for (mut selected, pos) in query.iter_mut() {
         selected.timer.tick(time.delta());
 
         // 选中状态闪烁效果
         let blink_factor = (selected.timer.elapsed_secs() * 0.5 * PI).sin().abs();
         let color = colors.selected.to_srgba() * (0.5 + 0.5 * blink_factor);
 
-        materials.get_mut(material.0.id()).map(|m| {
-            m.color = color.into();
-            m.border_color = Color::srgb(0.00, 1.00, 1.00).into();
-            m.border_width = 0.1;
+        cell_states.update(pos, |cell| {
+            cell.overlay = Some(Color::from(color).to_linear());
+            cell.selected = true;
         });
     }
 }
 
+// 选中卡片时高亮可以投放的地块，取消选中时恢复
+pub fn highlight_placement_area_system(
+    card_holder: Res<SelectedCardHolder>,
+    card_q: Query<&EntityCardInfo>,
+    rules: Res<CardPlacementRules>,
+    partition: Res<SpatialPartition>,
+    colors: Res<MapCellColors>,
+    mut cell_states: ResMut<HexCellStates>,
+    mut highlighted: Local<Vec<HexMapPosition>>,
+) {
+    for pos in highlighted.drain(..) {
+        cell_states.set_highlight(&pos, None);
+    }
+    let Some(card) = card_holder.0.and_then(|card| card_q.get(card).ok()) else {
+        return;
+    };
+    for pos in rules.allowed_cells(card, &partition) {
+        cell_states.set_highlight(&pos, Some(colors.placeable));
+        highlighted.push(pos);
+    }
+}
+
+/// 迷雾等级自上次检查以来是否变化，揭示或重新遮盖区域会改变可投放的地块
+pub fn fog_changed(partition: Res<SpatialPartition>, mut version: Local<u32>) -> bool {
+    let changed = partition.fog_version() != *version;
+    *version = partition.fog_version();
+    changed
+}
+
 fn lerp(a: f32, b: f32, t: f32) -> f32 {
     a + (b - a) * t
 }
@@ -406,6 +415,7 @@ impl Plugin for MapInteractionPlugin {
             .init_resource::<MapCellColors>()
             .insert_resource::<GlobalMousePosition>(GlobalMousePosition::default())
             .insert_resource(SpecialMapCellHolder::default())
+            .init_resource::<PlacementPreview>()
             .add_systems(
                 Update,
                 (
@@ -416,6 +426,21 @@ impl Plugin for MapInteractionPlugin {
                         click_effect_system,
                         selected_effect_system,
                         on_remove_click_effect_system.run_if(any_component_removed::<ClickEffect>),
+                        // 投放或侦察都会消耗金币，迷雾变化会改变已揭示的区域，可投放区域可能随之变化
+                        highlight_placement_area_system.run_if(
+                            resource_exists::<CardPlacementRules>.and(
+                                resource_changed::<SelectedCardHolder>
+                                    .or(resource_changed::<LevelGold>)
+                                    .or(fog_changed),
+                            ),
+                        ),
+                    ),
+                    // 预览的染色覆盖悬停和选中的颜色
+                    placement_preview_system,
+                    upload_cell_states_system,
+                    (
+                        update_placement_ghost_system,
+                        update_placement_tooltip_system,
                     ),
                 )
                     .in_set(SceneSystemSet::GameSystems)
@@ -0,0 +1,205 @@
+//! 视口外实体休眠
+//!
+//! 以视口尺寸的load_radius_factor倍作为活动范围。范围外的植物隐藏并跳过逐帧的
+//! 表现层系统（地块由整张网格统一绘制，不需要单独处理）；范围外的动物挂上Sleeping，AI每隔sleep_interval个固定帧才更新一次，
+//! 更新时使用累计的时间追帧，保证和全速模拟的结果一致。
+
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use super::camera::CameraController;
+use super::hex_grid::SpatialPartition;
+use crate::ai::AnimalActorBoard;
+use crate::sprite::animation::SpriteAnimation;
+
+/// 休眠控制参数
+#[derive(Resource, Debug, Clone)]
+pub struct SimulationLod {
+    pub load_radius_factor: f32,   // 活动范围相对视口尺寸的倍数
+    pub sleep_interval: u32,       // 休眠的动物每隔多少个固定帧更新一次
+    pub active_area: Option<Rect>, // 当前活动范围（世界坐标），None表示全部活动
+    tick: u32,
+}
+
+impl Default for SimulationLod {
+    fn default() -> Self {
+        Self {
+            load_radius_factor: 3.0,
+            sleep_interval: 8,
+            active_area: None,
+            tick: 0,
+        }
+    }
+}
+
+impl SimulationLod {
+    pub fn is_active(&self, pos: Vec2) -> bool {
+        self.active_area.is_none_or(|area| area.contains(pos))
+    }
+
+    // 按实体错开更新帧，避免休眠的动物集中在同一帧更新
+    fn is_due(&self, entity: Entity) -> bool {
+        (self.tick + entity.index()).is_multiple_of(self.sleep_interval.max(1))
+    }
+}
+
+/// 休眠中的动物
+#[derive(Component, Debug, Clone, Default)]
+pub struct Sleeping {
+    pub elapsed: Duration, // 上次更新以来累计的时间
+    pub due: bool,         // 本帧是否更新
+    pub waking: bool,      // 已回到活动范围，本帧结算后移除
+}
+
+/// 本帧模拟使用的时间步长，休眠且本帧不更新时返回None
+pub fn simulation_delta(sleeping: Option<&Sleeping>, time: &Time) -> Option<Duration> {
+    match sleeping {
+        None => Some(time.delta()),
+        Some(sleeping) if sleeping.due => Some(sleeping.elapsed),
+        Some(_) => None,
+    }
+}
+
+/// 根据相机视口计算活动范围
+pub fn update_active_area_system(
+    camera_q: Query<(&Camera, &GlobalTransform), With<CameraController>>,
+    mut lod: ResMut<SimulationLod>,
+) {
+    let Ok((camera, camera_transform)) = camera_q.single() else {
+        return;
+    };
+    let Some(viewport) = camera.logical_viewport_rect() else {
+        return;
+    };
+    let (Ok(min), Ok(max)) = (
+        camera.viewport_to_world_2d(camera_transform, viewport.min),
+        camera.viewport_to_world_2d(camera_transform, viewport.max),
+    ) else {
+        return;
+    };
+
+    // 视口坐标y轴向下，转换后的两个角不一定是min和max
+    let view = Rect::from_corners(min, max);
+    let area = Rect::from_center_size(view.center(), view.size() * lod.load_radius_factor);
+    if lod.active_area != Some(area) {
+        lod.active_area = Some(area);
+    }
+}
+
+type StaticEntityData = (
+    Entity,
+    &'static GlobalTransform,
+    Has<Sleeping>,
+    &'static mut Visibility,
+);
+
+/// 活动范围变化后隐藏或显示范围外的植物
+pub fn sleep_static_entities_system(
+    mut commands: Commands,
+    mut query: Query<StaticEntityData, (With<SpriteAnimation>, Without<AnimalActorBoard>)>,
+    lod: Res<SimulationLod>,
+) {
+    if !lod.is_changed() {
+        return;
+    }
+    for (entity, transform, sleeping, mut visibility) in query.iter_mut() {
+        let active = lod.is_active(transform.translation().xy());
+        if active && sleeping {
+            commands.entity(entity).remove::<Sleeping>();
+            *visibility = Visibility::Inherited;
+        } else if !active && !sleeping {
+            commands.entity(entity).insert(Sleeping::default());
+            *visibility = Visibility::Hidden;
+        }
+    }
+}
+
+/// 固定帧开始时决定哪些动物休眠、哪些休眠的动物本帧需要更新
+pub fn schedule_sleeping_actors_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &AnimalActorBoard, Option<&mut Sleeping>)>,
+    partition: Res<SpatialPartition>,
+    mut lod: ResMut<SimulationLod>,
+    time: Res<Time>,
+) {
+    // tick每帧都变，不应触发依赖活动范围变化的系统
+    lod.bypass_change_detection().tick += 1;
+
+    for (entity, board, sleeping) in query.iter_mut() {
+        let pos = partition.grid_to_world(&board.current_pos.to_vec2()).xy();
+        let active = lod.is_active(pos);
+        match sleeping {
+            Some(mut sleeping) => {
+                sleeping.elapsed += time.delta();
+                sleeping.waking = active;
+                sleeping.due = active || lod.is_due(entity);
+            }
+            None if !active => {
+                // 本帧已经开始休眠，时间计入下一次更新
+                commands.entity(entity).insert(Sleeping {
+                    elapsed: time.delta(),
+                    ..Default::default()
+                });
+            }
+            None => {}
+        }
+    }
+}
+
+/// 固定帧结束时清空已结算的累计时间，回到活动范围的动物恢复全速更新
+pub fn settle_sleeping_actors_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Sleeping), With<AnimalActorBoard>>,
+) {
+    for (entity, mut sleeping) in query.iter_mut() {
+        if !sleeping.due {
+            continue;
+        }
+        if sleeping.waking {
+            commands.entity(entity).remove::<Sleeping>();
+        } else {
+            sleeping.elapsed = Duration::ZERO;
+            sleeping.due = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleeping_actor_catches_up_elapsed_time() {
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(20));
+        let mut sleeping = Sleeping::default();
+        let mut total = Duration::ZERO;
+        for _ in 0..8 {
+            sleeping.elapsed += time.delta();
+            if let Some(delta) = simulation_delta(Some(&sleeping), &time) {
+                total += delta;
+            }
+        }
+        assert_eq!(total, Duration::ZERO);
+
+        // 到了更新帧时一次性结算全部跳过的时间
+        sleeping.due = true;
+        assert_eq!(
+            simulation_delta(Some(&sleeping), &time),
+            Some(Duration::from_millis(160))
+        );
+        assert_eq!(
+            simulation_delta(None, &time),
+            Some(Duration::from_millis(20))
+        );
+    }
+
+    #[test]
+    fn test_sleep_interval_staggers_entities() {
+        let lod = SimulationLod::default();
+        let due = (0..8).filter(|i| lod.is_due(Entity::from_raw(*i))).count();
+        assert_eq!(due, 1);
+        assert!(lod.is_active(Vec2::new(1.0e6, 1.0e6)));
+    }
+}
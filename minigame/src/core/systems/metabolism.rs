@@ -0,0 +1,444 @@
+//! 能量代谢
+//!
+//! 生物体内的能量都在Metabolism中：植物按秒光合作用积累能量，动物按秒支付基础代谢，
+//! 移动和繁殖的消耗由对应的系统扣除，进食按食物链的能量转化率吸收猎物的能量。
+//! 吃饱的动物定期繁殖，由亲代支付后代出生时的能量。动物能量耗尽时饿死，植物的光合作用抵不上基础代谢时慢慢枯萎，两者都留下尸体。
+//!
+//! 所有进出生态系统的能量都记在EnergyLedger中，定期核对账目与生物体内和尸体中实际
+//! 储存的能量总和，不一致说明有代码绕过了账目修改能量。
+
+use bevy::platform::collections::HashSet;
+use bevy::prelude::*;
+
+use super::economy::EcologyEvent;
+use super::environment::Environment;
+use super::hex_grid::SpatialPartition;
+use super::lod::{Sleeping, simulation_delta};
+use super::nutrient::{CorpseEvent, Nutrients};
+use super::player_action::release_reservation;
+use crate::ai::{AnimalActorBoard, EdibleEntity};
+use crate::core::components::{Corpse, EntityType, Metabolism};
+use crate::core::entities::SpawnEntityEvent;
+use crate::level::config::EntityConfig;
+use crate::sprite::animation::{SpriteAnimation, spawn_death_animation};
+use crate::sprite::sprite_mgr::SpriteManager;
+
+/// 核对能量账目的间隔（秒）
+const AUDIT_INTERVAL: f32 = 5.0;
+/// 账目允许的相对误差，来自逐个实体的f32运算
+const AUDIT_TOLERANCE: f64 = 1e-3;
+/// 动物尝试繁殖的间隔（秒）
+const REPRODUCTION_INTERVAL: f32 = 10.0;
+
+/// 生态系统的能量账目
+#[derive(Resource, Debug, Clone, Default)]
+pub struct EnergyLedger {
+    pub injected: f64,      // 生成实体和玩家喂食带入的能量
+    pub produced: f64,      // 光合作用产生的能量
+    pub basal: f64,         // 基础代谢消耗的能量
+    pub movement: f64,      // 移动消耗的能量
+    pub reproduction: f64,  // 亲代交给后代的能量，后代生成时再记入injected
+    pub transfer_loss: f64, // 进食时没有被吸收的能量
+    pub removed: f64,       // 随实体移除离开生物体的能量
+    pub deposited: f64,     // 死亡后留在尸体中的能量，来自removed或transfer_loss
+    pub decayed: f64,       // 尸体分解或渗入土壤时散失的能量
+    pub stored: f64,        // 最近一次核对时生物体内和尸体中的能量总和
+}
+
+impl EnergyLedger {
+    /// 按账目应当储存在生物体内和尸体中的能量，分解者从尸体中获得的能量是内部转移
+    pub fn expected_stored(&self) -> f64 {
+        self.injected + self.produced + self.deposited
+            - self.basal
+            - self.movement
+            - self.reproduction
+            - self.transfer_loss
+            - self.removed
+            - self.decayed
+    }
+
+    /// 账目与实际储存的能量之差，超出误差时返回
+    pub fn imbalance(&self, stored: f64) -> Option<f64> {
+        let expected = self.expected_stored();
+        let diff = stored - expected;
+        (diff.abs() > AUDIT_TOLERANCE * expected.abs().max(1.0)).then_some(diff)
+    }
+}
+
+/// 光合作用和基础代谢，开启环境系统时分别受季节和天气影响，开启养分循环时光合作用随地块肥力变化
+pub fn metabolism_system(
+    mut query: Query<(
+        &mut Metabolism,
+        &Transform,
+        Option<&Sleeping>,
+        Has<AnimalActorBoard>,
+    )>,
+    mut ledger: ResMut<EnergyLedger>,
+    partition: Res<SpatialPartition>,
+    nutrients: Option<Res<Nutrients>>,
+    environment: Option<Res<Environment>>,
+    time: Res<Time>,
+) {
+    let (growth, basal_factor) = environment.as_ref().map_or((1.0, 1.0), |env| {
+        (env.growth_factor(), env.metabolism_factor())
+    });
+    for (mut metabolism, transform, sleeping, is_animal) in query.iter_mut() {
+        // 休眠的动物在自己的更新帧结算累计的时间，植物休眠只是隐藏，照常生长
+        let sleeping = sleeping.filter(|_| is_animal);
+        let Some(delta) = simulation_delta(sleeping, &time) else {
+            continue;
+        };
+        let secs = delta.as_secs_f32();
+        let mut produced = metabolism.production * growth * secs;
+        if produced > 0.0
+            && let Some(nutrients) = nutrients.as_ref()
+        {
+            produced *= nutrients.growth_factor_at(&partition, transform);
+        }
+        ledger.produced += metabolism.gain(produced) as f64;
+        let basal = metabolism.basal * basal_factor * secs;
+        ledger.basal += metabolism.spend(basal) as f64;
+    }
+}
+
+/// 能量耗尽的动物饿死，在原地留下尸体
+#[allow(clippy::too_many_arguments)]
+pub fn starvation_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Metabolism), With<AnimalActorBoard>>,
+    mut board_q: Query<&mut AnimalActorBoard>,
+    mut edible_q: Query<&mut EdibleEntity>,
+    sprite_q: Query<(&SpriteAnimation, &Sprite, &Transform, &ChildOf)>,
+    sprite_manager: Res<SpriteManager>,
+    mut partition: ResMut<SpatialPartition>,
+    mut ecology: EventWriter<EcologyEvent>,
+    mut corpses: EventWriter<CorpseEvent>,
+) {
+    let starved: Vec<_> = query
+        .iter()
+        .filter(|(_, metabolism)| metabolism.is_starved())
+        .filter_map(|(entity, metabolism)| {
+            // 动物的行为数据通过board_q读取，同一系统中不能再用只读查询访问AnimalActorBoard
+            let board = board_q.get(entity).ok()?;
+            Some((
+                entity,
+                board.current_pos,
+                board.entity_type.clone(),
+                metabolism.energy,
+            ))
+        })
+        .collect();
+    for (entity, pos, entity_type, energy) in starved {
+        if let Ok((animation, sprite, transform, parent)) = sprite_q.get(entity) {
+            spawn_death_animation(
+                &mut commands,
+                &sprite_manager,
+                animation,
+                sprite,
+                transform,
+                parent,
+            );
+        }
+        release_reservation(&mut board_q, &mut edible_q, entity);
+        partition.remove_entity(entity, &pos, entity_type.clone());
+        commands.entity(entity).despawn();
+        ecology.write(EcologyEvent::Death(entity_type));
+        corpses.write(CorpseEvent { pos, energy });
+    }
+}
+
+/// 能量耗尽的植物枯萎，在原地留下尸体。只有植物进行光合作用，目前只有青草一种
+pub fn wither_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Metabolism, &Transform), Without<AnimalActorBoard>>,
+    sprite_q: Query<(&SpriteAnimation, &Sprite, &ChildOf)>,
+    sprite_manager: Res<SpriteManager>,
+    mut partition: ResMut<SpatialPartition>,
+    mut ecology: EventWriter<EcologyEvent>,
+    mut corpses: EventWriter<CorpseEvent>,
+) {
+    for (entity, metabolism, transform) in query.iter() {
+        if !metabolism.is_withered() {
+            continue;
+        }
+        if let Ok((animation, sprite, parent)) = sprite_q.get(entity) {
+            spawn_death_animation(
+                &mut commands,
+                &sprite_manager,
+                animation,
+                sprite,
+                transform,
+                parent,
+            );
+        }
+        // 植物没有AnimalActorBoard，按渲染位置换算所在地块
+        let pos = partition.world_to_grid(&transform.translation.xy());
+        partition.remove_entity(entity, &pos, EntityType::Grass);
+        commands.entity(entity).despawn();
+        ecology.write(EcologyEvent::Death(EntityType::Grass));
+        corpses.write(CorpseEvent {
+            pos,
+            energy: metabolism.energy,
+        });
+    }
+}
+
+/// 吃饱的动物定期在相邻的空地上繁殖，后代交给实体生成系统，由它向亲代收取出生时的能量
+pub fn animal_reproduction_system(
+    query: Query<(Entity, &AnimalActorBoard, &Metabolism)>,
+    partition: Res<SpatialPartition>,
+    mut spawn_events: EventWriter<SpawnEntityEvent>,
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+) {
+    *elapsed += time.delta_secs();
+    if *elapsed < REPRODUCTION_INTERVAL {
+        return;
+    }
+    *elapsed = 0.0;
+
+    // 同一轮中每个地块只接受一个后代
+    let mut occupied = HashSet::new();
+    for (entity, board, metabolism) in query.iter() {
+        if !metabolism.can_reproduce() {
+            continue;
+        }
+        let candidates: Vec<_> = partition
+            .get_valid_neighbours(&board.current_pos)
+            .into_iter()
+            .filter(|cell| {
+                partition.check_entity_conflict_by_pos(board.entity_type.clone(), cell)
+                    && !occupied.contains(cell)
+            })
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+        let target = candidates[rand::random_range(0..candidates.len())];
+        occupied.insert(target);
+        spawn_events.write(SpawnEntityEvent {
+            config: EntityConfig {
+                entity_type: board.entity_type.clone(),
+                pos: target.to_vec2(),
+                ..Default::default()
+            },
+            by_player: false,
+            parent: Some(entity),
+        });
+    }
+}
+
+/// 新生成的生物带入的能量
+pub fn on_add_metabolism(
+    trigger: Trigger<OnAdd, Metabolism>,
+    query: Query<&Metabolism>,
+    ledger: Option<ResMut<EnergyLedger>>,
+) {
+    if let (Ok(metabolism), Some(mut ledger)) = (query.get(trigger.target()), ledger) {
+        ledger.injected += metabolism.energy as f64;
+    }
+}
+
+/// 被移除的生物带走剩余的能量，被吃掉的生物此时能量已经转移给了捕食者
+pub fn on_remove_metabolism(
+    trigger: Trigger<OnRemove, Metabolism>,
+    query: Query<&Metabolism>,
+    ledger: Option<ResMut<EnergyLedger>>,
+) {
+    if let (Ok(metabolism), Some(mut ledger)) = (query.get(trigger.target()), ledger) {
+        ledger.removed += metabolism.energy as f64;
+    }
+}
+
+/// 尸体被移除时剩余的能量全部散失
+pub fn on_remove_corpse(
+    trigger: Trigger<OnRemove, Corpse>,
+    query: Query<&Corpse>,
+    ledger: Option<ResMut<EnergyLedger>>,
+) {
+    if let (Ok(corpse), Some(mut ledger)) = (query.get(trigger.target()), ledger) {
+        ledger.decayed += corpse.energy as f64;
+    }
+}
+
+/// 定期核对能量账目
+pub fn audit_energy_system(
+    query: Query<&Metabolism>,
+    corpse_q: Query<&Corpse>,
+    mut ledger: ResMut<EnergyLedger>,
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+) {
+    *elapsed += time.delta_secs();
+    if *elapsed < AUDIT_INTERVAL {
+        return;
+    }
+    *elapsed = 0.0;
+
+    let stored: f64 = query.iter().map(|m| m.energy as f64).sum::<f64>()
+        + corpse_q.iter().map(|c| c.energy as f64).sum::<f64>();
+    ledger.stored = stored;
+    if let Some(diff) = ledger.imbalance(stored) {
+        warn!(
+            "energy ledger out of balance by {:.3}: stored {:.3}, expected {:.3}",
+            diff,
+            stored,
+            ledger.expected_stored()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::core::HexGridConfig;
+    use crate::core::entities::{
+        OnMapEntitiesRoot, SpeciesConfig, SpeciesStats, spawn_entity_event_system,
+    };
+    use crate::core::hex_grid::HexMapPosition;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn test_energy_is_conserved() {
+        let mut world = World::new();
+        world.insert_resource(EnergyLedger::default());
+        world.add_observer(on_add_metabolism);
+        world.add_observer(on_remove_metabolism);
+
+        world.insert_resource(SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0)));
+
+        let rabbit = world
+            .spawn((
+                Metabolism {
+                    energy: 50.0,
+                    max: 100.0,
+                    basal: 2.0,
+                    move_cost: 1.0,
+                    ..Default::default()
+                },
+                Transform::default(),
+            ))
+            .id();
+        let grass = world
+            .spawn((
+                Metabolism {
+                    energy: 10.0,
+                    max: 100.0,
+                    production: 5.0,
+                    ..Default::default()
+                },
+                Transform::default(),
+            ))
+            .id();
+        world.flush();
+
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(2));
+        world.insert_resource(time);
+        world.run_system_once(metabolism_system).unwrap();
+        assert_eq!(world.get::<Metabolism>(rabbit).unwrap().energy, 46.0);
+        assert_eq!(world.get::<Metabolism>(grass).unwrap().energy, 20.0);
+
+        // 兔子吃掉青草，按0.5的转化率吸收
+        let prey = world.get_mut::<Metabolism>(grass).unwrap().take();
+        let absorbed = world
+            .get_mut::<Metabolism>(rabbit)
+            .unwrap()
+            .gain(prey * 0.5);
+        let mut ledger = world.resource_mut::<EnergyLedger>();
+        ledger.transfer_loss += (prey - absorbed) as f64;
+        world.despawn(grass);
+
+        let stored: f64 = world
+            .query::<&Metabolism>()
+            .iter(&world)
+            .map(|m| m.energy as f64)
+            .sum();
+        let ledger = world.resource::<EnergyLedger>();
+        assert_eq!(stored, 56.0);
+        assert_eq!(ledger.imbalance(stored), None);
+        assert!(ledger.imbalance(stored + 1.0).is_some());
+    }
+
+    #[test]
+    fn test_production_scales_with_fertility() {
+        let mut world = World::new();
+        world.insert_resource(EnergyLedger::default());
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        partition.enable_fertility(2.0);
+        world.insert_resource(partition);
+        world.insert_resource(Nutrients::new(Default::default()));
+        let grass = world
+            .spawn((
+                Metabolism {
+                    energy: 10.0,
+                    max: 100.0,
+                    production: 5.0,
+                    basal: 1.0,
+                    ..Default::default()
+                },
+                Transform::default(),
+            ))
+            .id();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+
+        // 肥力是基准的两倍，光合作用翻倍，基础代谢不受影响
+        world.run_system_once(metabolism_system).unwrap();
+        assert_eq!(world.get::<Metabolism>(grass).unwrap().energy, 19.0);
+        assert_eq!(world.resource::<EnergyLedger>().produced, 10.0);
+    }
+
+    #[test]
+    fn test_reproduction_is_paid_by_parent() {
+        let mut world = World::new();
+        world.insert_resource(EnergyLedger::default());
+        world.add_observer(on_add_metabolism);
+        world.add_observer(on_remove_metabolism);
+        world.insert_resource(SpriteManager::empty());
+        world.insert_resource(SpeciesConfig::default());
+        world.init_resource::<Events<SpawnEntityEvent>>();
+        world.init_resource::<Events<EcologyEvent>>();
+        world.spawn(OnMapEntitiesRoot);
+
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        let pos = HexMapPosition::new(3, 3);
+        let stats = SpeciesStats::of(&EntityType::Rabbit).unwrap();
+        let mut metabolism = stats.metabolism();
+        metabolism.energy = metabolism.max;
+        let parent = world
+            .spawn((
+                AnimalActorBoard {
+                    current_pos: pos,
+                    entity_type: EntityType::Rabbit,
+                    ..Default::default()
+                },
+                metabolism,
+            ))
+            .id();
+        partition.insert_cache_entity(parent, &pos, EntityType::Rabbit);
+        world.insert_resource(partition);
+        let injected = world.resource::<EnergyLedger>().injected;
+
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(REPRODUCTION_INTERVAL));
+        world.insert_resource(time);
+        world.run_system_once(animal_reproduction_system).unwrap();
+        world.run_system_once(spawn_entity_event_system).unwrap();
+
+        // 亲代交出的能量正好是后代带入的能量
+        let ledger = world.resource::<EnergyLedger>();
+        assert_eq!(ledger.reproduction, stats.birth_energy as f64);
+        assert_eq!(ledger.injected - injected, ledger.reproduction);
+        assert_eq!(
+            world.get::<Metabolism>(parent).unwrap().energy,
+            stats.max_energy - stats.birth_energy
+        );
+        let partition = world.resource::<SpatialPartition>();
+        assert_eq!(partition.count_by_type(&EntityType::Rabbit), 2);
+        assert_eq!(world.resource::<Events<EcologyEvent>>().len(), 1);
+    }
+}
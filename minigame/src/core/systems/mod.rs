@@ -2,13 +2,28 @@
 
 pub mod camera;
 pub mod debug;
+pub mod dstar_lite;
+pub mod economy;
+pub mod environment;
+pub mod fog;
 pub mod grid;
 pub mod hex_grid;
+pub mod hex_math;
 pub mod interaction;
+pub mod lod;
+pub mod metabolism;
+pub mod move_animation;
 pub mod movement;
-pub mod state_machine;
+pub mod nutrient;
+pub mod placement_preview;
+pub mod player_action;
+pub mod vision;
 
 pub use debug::*;
+pub use fog::*;
 pub use grid::*;
+pub use lod::*;
+pub use move_animation::*;
 pub use movement::*;
-pub use state_machine::*;
+pub use player_action::*;
+pub use vision::*;
@@ -0,0 +1,110 @@
+//! 动物移动的表现层动画
+//!
+//! 逻辑坐标AnimalActorBoard::current_pos在FixedUpdate中逐格更新，这里只在Update中
+//! 观察它的变化，用补间动画把Transform从当前显示位置平滑移动到新的格子中心，
+//! 并按移动方向翻转精灵。动画不会反过来影响逻辑坐标。
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_tweening::{Animator, Lens, Targetable, Tween};
+
+use super::hex_grid::{HexMapPosition, SpatialPartition, hex_distance};
+use super::lod::Sleeping;
+use crate::ai::AnimalActorBoard;
+
+/// 动物精灵相对地块的高度
+const ACTOR_Z: f32 = 3.0;
+/// 补间的最短时长，避免速度过快时动画退化为瞬移
+const MIN_TWEEN_SECS: f32 = 0.05;
+
+/// 移动动画配置，挂在动物实体上
+#[derive(Component, Debug, Clone, Default)]
+pub struct MoveAnimation {
+    pub hop_height: f32,              // 跳跃高度（像素），0表示平移
+    pub faces_left: bool,             // 精灵原图是否朝左
+    last_pos: Option<HexMapPosition>, // 上一次开始动画时的逻辑坐标
+}
+
+impl MoveAnimation {
+    pub fn hopping(hop_height: f32) -> Self {
+        Self {
+            hop_height,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_faces_left(mut self, faces_left: bool) -> Self {
+        self.faces_left = faces_left;
+        self
+    }
+}
+
+/// 从start平移到end，中途按正弦曲线向上跳起height
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HopLens {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub height: f32,
+}
+
+impl Lens<Transform> for HopLens {
+    fn lerp(&mut self, target: &mut dyn Targetable<Transform>, ratio: f32) {
+        let hop = (ratio * PI).sin() * self.height;
+        target.translation = self.start.lerp(self.end, ratio) + Vec3::Y * hop;
+    }
+}
+
+type AnimatedActorData = (
+    Entity,
+    &'static AnimalActorBoard,
+    &'static mut MoveAnimation,
+    &'static mut Transform,
+    Option<&'static mut Sprite>,
+);
+
+/// 逻辑坐标变化后启动补间动画
+pub fn animate_actor_movement_system(
+    mut commands: Commands,
+    mut query: Query<AnimatedActorData, (Changed<AnimalActorBoard>, Without<Sleeping>)>,
+    partition: Res<SpatialPartition>,
+) {
+    for (entity, board, mut animation, mut transform, sprite) in query.iter_mut() {
+        let Some(last_pos) = animation.last_pos else {
+            animation.last_pos = Some(board.current_pos);
+            continue;
+        };
+        if last_pos == board.current_pos {
+            continue;
+        }
+        animation.last_pos = Some(board.current_pos);
+
+        let end = partition.grid_to_world(&board.current_pos.to_vec2()) + Vec3::Z * ACTOR_Z;
+        // 一次跨越多格（例如固定帧追帧）时直接对齐，不做补间
+        if hex_distance(&last_pos, &board.current_pos) > 1 {
+            transform.translation = end;
+            commands.entity(entity).remove::<Animator<Transform>>();
+            continue;
+        }
+
+        let start = transform.translation;
+        if let Some(mut sprite) = sprite
+            && (end.x - start.x).abs() > f32::EPSILON
+        {
+            sprite.flip_x = (end.x < start.x) != animation.faces_left;
+        }
+
+        let secs = (1.0 / board.move_speed.max(f32::EPSILON)).max(MIN_TWEEN_SECS);
+        let tween = Tween::new(
+            EaseFunction::QuadraticInOut,
+            Duration::from_secs_f32(secs),
+            HopLens {
+                start,
+                end,
+                height: animation.hop_height,
+            },
+        );
+        commands.entity(entity).insert(Animator::new(tween));
+    }
+}
@@ -1,113 +1,161 @@
 //! 实体移动系统实现
-use super::super::components::{EnergyStore, MoveTo, VisionRange};
-use super::super::hex_grid::{HexGridConfig, HexMapPosition, hex_distance};
+//!
+//! AI行为只负责给动物挂上MoveTo，移动系统按speed（格/秒）沿路径逐格前进，
+//! 每走一格消耗Metabolism中的能量，目标超出VisionRange时放弃移动。
+//! 这里只更新逻辑坐标，画面上的平滑移动由move_animation负责。
+use super::super::components::{Metabolism, MoveTo, VisionRange};
+use super::super::hex_grid::{HexMapPosition, hex_distance};
+use super::environment::Environment;
+use super::lod::{Sleeping, simulation_delta};
+use super::metabolism::EnergyLedger;
+use crate::ai::AnimalActorBoard;
 use crate::core::hex_grid::SpatialPartition;
-use crate::core::state::GameState;
 use bevy::prelude::*;
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
-pub enum MovementSystemSet {
-    Pathfinding,
-    Movement,
-}
+// 移动中的动物，休眠的动物在自己的更新帧结算累计的时间
+type MovingActorData = (
+    Entity,
+    &'static mut AnimalActorBoard,
+    &'static mut MoveTo,
+    Option<&'static mut Metabolism>,
+    Option<&'static VisionRange>,
+    Option<&'static Sleeping>,
+);
 
-pub fn configure_movement_sets(app: &mut App) {
-    app.configure_sets(
-        Update,
-        (MovementSystemSet::Pathfinding, MovementSystemSet::Movement)
-            .chain()
-            .run_if(in_state(GameState::Playing)),
-    );
-}
+/// 移动执行系统
+pub fn movement_system(
+    mut commands: Commands,
+    mut query: Query<MovingActorData>,
+    mut partition: ResMut<SpatialPartition>,
+    mut ledger: ResMut<EnergyLedger>,
+    environment: Option<Res<Environment>>,
+    time: Res<Time>,
+) {
+    // 夜间动物的活动变慢
+    let activity = environment
+        .as_ref()
+        .map_or(1.0, |env| env.activity_factor());
+    for (entity, mut board, mut move_to, mut energy, vision_range, sleeping) in &mut query {
+        // 休眠的动物在更新帧一次走完累计时间内的格子
+        let Some(delta) = simulation_delta(sleeping, &time) else {
+            continue;
+        };
 
-pub fn register_movement_systems(app: &mut App) {
-    app.add_systems(
-        Update,
-        (
-            pathfinding_system.in_set(MovementSystemSet::Pathfinding),
-            update_paths.in_set(MovementSystemSet::Movement),
-            movement_system.in_set(MovementSystemSet::Movement),
-        ),
-    );
-}
+        // 目标超出视野范围，放弃移动
+        if let Some(vision_range) = vision_range
+            && hex_distance(&board.current_pos, &move_to.target) > vision_range.radius
+        {
+            commands.entity(entity).remove::<MoveTo>();
+            continue;
+        }
 
-/// 寻路系统
-pub fn pathfinding_system(
-    mut query: Query<(&HexMapPosition, &mut MoveTo, Option<&VisionRange>)>,
-    _config: Res<HexGridConfig>,
-) {
-    for (current_pos, mut move_to, vision_range) in &mut query {
-        if move_to.path.is_empty() || hex_distance(current_pos, &move_to.target) > 1 {
-            // 检查目标是否在视野范围内
-            if let Some(vision_range) = vision_range {
-                if hex_distance(current_pos, &move_to.target) > vision_range.radius {
-                    continue;
+        move_to.progress += move_to.speed * activity * delta.as_secs_f32();
+        let mut interrupted = false;
+        while move_to.progress >= 1.0 {
+            let Some(next_pos) = move_to.path.first().copied() else {
+                break;
+            };
+
+            // 下一格不可通行时中断移动，由AI行为重新规划
+            if !partition.is_valid_position(&next_pos) || partition.is_obstacle(&next_pos) {
+                interrupted = true;
+                break;
+            }
+
+            // 能量不足时停止移动
+            if let Some(energy) = energy.as_mut() {
+                if !energy.can_move() {
+                    interrupted = true;
+                    break;
                 }
+                let cost = energy.move_cost;
+                ledger.movement += energy.spend(cost) as f64;
             }
-            // 简单直线移动作为临时实现
-            // TODO: 实现完整A*寻路算法
-            move_to.path = vec![move_to.target];
+
+            move_to.path.remove(0);
+            move_to.progress -= 1.0;
+            step_to(entity, &mut board, &next_pos, &mut partition);
         }
-    }
-}
 
-/// 路径更新系统
-pub fn update_paths(query: Query<(&HexMapPosition, &MoveTo), Changed<HexMapPosition>>) {
-    for (_position, _move_to) in query.iter() {
-        // 路径更新逻辑
-        // 当位置变化时更新路径
+        if interrupted || move_to.path.is_empty() {
+            commands.entity(entity).remove::<MoveTo>();
+        }
     }
 }
 
-/// 移动执行系统
-#[allow(unused_variables)]
-pub fn movement_system(
-    mut commands: Commands,
-    mut query: Query<(
-        Entity,
-        &mut HexMapPosition,
-        &mut MoveTo,
-        Option<&mut EnergyStore>,
-        Option<&VisionRange>,
-    )>,
-    time: Res<Time>,
-    config: Res<HexGridConfig>,
-    partition: Res<SpatialPartition>,
+// 实体移动到相邻的一格，同步SpatialPartition和AnimalActorBoard
+fn step_to(
+    entity: Entity,
+    board: &mut AnimalActorBoard,
+    next_pos: &HexMapPosition,
+    partition: &mut SpatialPartition,
 ) {
-    for (entity, mut position, mut move_to, mut energy, vision_range) in &mut query {
-        if let Some(next_pos) = move_to.path.first() {
-            // 检查能量是否足够（如果有能量组件）
-            if let Some(ref mut energy) = energy {
-                if energy.value <= 0.0 {
-                    commands.entity(entity).remove::<MoveTo>();
-                    continue;
-                }
+    partition.move_entity(
+        entity,
+        &board.current_pos,
+        next_pos,
+        board.entity_type.clone(),
+    );
+    board.current_pos = *next_pos;
+}
 
-                // 消耗能量
-                energy.value -= (0.5 * energy.max) * time.delta_secs();
-            }
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-            // 检查位置有效性
-            if !partition.is_valid_position(next_pos) {
-                commands.entity(entity).remove::<MoveTo>();
-                continue;
-            }
+    use super::*;
+    use crate::core::components::EntityType;
+    use crate::core::hex_grid::HexGridConfig;
+    use bevy::ecs::system::RunSystemOnce;
 
-            // 检查目标是否在视野范围内
-            if let Some(vision_range) = vision_range {
-                if hex_distance(&position, &move_to.target) > vision_range.radius {
-                    commands.entity(entity).remove::<MoveTo>();
-                    continue;
-                }
-            }
+    #[test]
+    fn test_movement_follows_path_and_consumes_energy() {
+        let mut world = World::new();
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 8, 8, 1.0));
+        let start = HexMapPosition::new(1, 1);
+        let path = vec![
+            HexMapPosition::new(2, 1),
+            HexMapPosition::new(3, 1),
+            HexMapPosition::new(4, 1),
+        ];
 
-            // 更新位置
-            *position = *next_pos;
+        let entity = world
+            .spawn((
+                AnimalActorBoard {
+                    current_pos: start,
+                    entity_type: EntityType::Rabbit,
+                    ..Default::default()
+                },
+                MoveTo::new(path.clone(), 2.0).unwrap(),
+                Metabolism {
+                    energy: 10.0,
+                    max: 10.0,
+                    move_cost: 1.0,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        partition.insert_cache_entity(entity, &start, EntityType::Rabbit);
+        world.insert_resource(partition);
+        world.insert_resource(EnergyLedger::default());
 
-            // 如果到达目标点，清除路径
-            if *position == move_to.target {
-                move_to.path.clear();
-            }
-        }
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+        world.run_system_once(movement_system).unwrap();
+
+        // 速度为每秒2格，1秒后走到路径的第二格
+        let board = world.get::<AnimalActorBoard>(entity).unwrap();
+        assert_eq!(board.current_pos, path[1]);
+        assert_eq!(world.get::<Metabolism>(entity).unwrap().energy, 8.0);
+        assert_eq!(world.resource::<EnergyLedger>().movement, 2.0);
+        assert_eq!(world.get::<MoveTo>(entity).unwrap().path, vec![path[2]]);
+
+        world.run_system_once(movement_system).unwrap();
+        assert_eq!(
+            world.get::<AnimalActorBoard>(entity).unwrap().current_pos,
+            path[2]
+        );
+        assert!(world.get::<MoveTo>(entity).is_none());
     }
 }
@@ -0,0 +1,431 @@
+//! 养分循环
+//!
+//! 关卡可以选择开启养分循环。生物死亡后体内剩余的能量留在地块上的尸体中，尸体缓慢渗入
+//! 土壤，分解者（真菌）分解附近的尸体，吸收其中一部分能量，分解的尸体按比例转化为肥力。地块肥力记录在
+//! SpatialPartition中：生长中的植物吸收肥力，肥力越高植物长得越快、越容易向相邻的空地播种。
+//! 附近没有分解者的尸体会不时在旁边长出真菌。
+
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::economy::EcologyEvent;
+use super::hex_grid::{HexMapPosition, SpatialPartition, hex_distance};
+use super::metabolism::EnergyLedger;
+use crate::core::components::{Corpse, Decomposer, EntityType, GrowthStage, Metabolism, Sterile};
+use crate::core::entities::{OnMapEntitiesRoot, SpawnEntityEvent, SpeciesConfig, spawn_entity};
+use crate::level::config::EntityConfig;
+use crate::sprite::sprite_mgr::SpriteManager;
+
+/// 肥力最低时植物的生长速度系数，贫瘠的土地上植物仍能缓慢生长
+const MIN_GROWTH_FACTOR: f32 = 0.25;
+/// 能量低于这个值的尸体视为分解完毕
+const MIN_CORPSE_ENERGY: f32 = 0.5;
+/// 真菌长在尸体相邻的地块上，这个范围内已有分解者时不再长出新的真菌
+const SPORE_RADIUS: i32 = 1;
+
+/// 关卡中的养分循环配置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NutrientConfig {
+    pub base_fertility: f32,   // 地块的初始肥力，植物在此肥力下按正常速度生长
+    pub max_fertility: f32,    // 地块肥力上限
+    pub corpse_nutrients: f32, // 每单位尸体能量分解后还给土壤的肥力
+    pub leach_rate: f32,       // 没有分解者时尸体每秒渗入土壤的能量比例
+    pub uptake: f32,           // 生长中的植物每秒吸收的肥力
+    pub decomposer_yield: f32, // 分解者吸收的尸体能量比例，其余的散失
+    pub seed_interval: f32,    // 成熟植物尝试播种的间隔（秒）
+    pub seed_chance: f32,      // 基础肥力下每次播种的概率，按肥力缩放
+    pub seed_growth_rate: f32, // 种子每个生长阶段持续的时间（秒）
+    pub spore_chance: f32,     // 附近没有分解者的尸体每轮长出真菌的概率
+}
+
+impl Default for NutrientConfig {
+    fn default() -> Self {
+        Self {
+            base_fertility: 1.0,
+            max_fertility: 3.0,
+            corpse_nutrients: 0.02,
+            leach_rate: 0.02,
+            uptake: 0.01,
+            decomposer_yield: 0.5,
+            seed_interval: 10.0,
+            seed_chance: 0.1,
+            seed_growth_rate: 5.0,
+            spore_chance: 0.05,
+        }
+    }
+}
+
+impl NutrientConfig {
+    /// 肥力对植物生长速度和播种概率的系数
+    pub fn growth_factor(&self, fertility: f32) -> f32 {
+        let base = self.base_fertility.max(f32::EPSILON);
+        (fertility / base).clamp(MIN_GROWTH_FACTOR, (self.max_fertility / base).max(1.0))
+    }
+}
+
+/// 当前关卡开启了养分循环
+#[derive(Resource, Debug, Clone)]
+pub struct Nutrients {
+    pub config: NutrientConfig,
+}
+
+impl Nutrients {
+    pub fn new(config: NutrientConfig) -> Self {
+        Self { config }
+    }
+
+    /// 植物所在地块的肥力对生长和光合作用的影响系数
+    pub fn growth_factor_at(&self, partition: &SpatialPartition, transform: &Transform) -> f32 {
+        self.config
+            .growth_factor(partition.fertility(&cell_of(partition, transform)))
+    }
+}
+
+/// 生物死亡后在地块上留下尸体，energy为死亡时体内剩余、没有被捕食者吸收的能量
+#[derive(Event, Debug, Clone)]
+pub struct CorpseEvent {
+    pub pos: HexMapPosition,
+    pub energy: f32,
+}
+
+// 静态实体没有AnimalActorBoard，按渲染位置换算所在地块
+fn cell_of(partition: &SpatialPartition, transform: &Transform) -> HexMapPosition {
+    partition.world_to_grid(&transform.translation.xy())
+}
+
+/// 生成尸体，同一地块上的尸体合并在一起。能量太少的尸体不生成，这部分能量已经随生物
+/// 的移除或进食的损耗记入账目
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_corpse_system(
+    mut commands: Commands,
+    mut events: EventReader<CorpseEvent>,
+    sprite_manager: ResMut<SpriteManager>,
+    species: Res<SpeciesConfig>,
+    mut partition: ResMut<SpatialPartition>,
+    root: Query<Entity, With<OnMapEntitiesRoot>>,
+    mut corpse_q: Query<&mut Corpse>,
+    mut ledger: ResMut<EnergyLedger>,
+) {
+    let Ok(root) = root.single() else {
+        return;
+    };
+    // 新生成的尸体本帧还查询不到，先按地块合并
+    let mut deposits: HashMap<HexMapPosition, f32> = HashMap::new();
+    for event in events.read() {
+        *deposits.entry(event.pos).or_default() += event.energy.max(0.0);
+    }
+    for (pos, amount) in deposits {
+        if amount < MIN_CORPSE_ENERGY {
+            continue;
+        }
+        ledger.deposited += amount as f64;
+        if let Some(existing) = partition.entity_of_type_at(&pos, &EntityType::Corpse)
+            && let Ok(mut corpse) = corpse_q.get_mut(existing.entity)
+        {
+            corpse.energy += amount;
+            continue;
+        }
+        let config = EntityConfig {
+            entity_type: EntityType::Corpse,
+            pos: pos.to_vec2(),
+            ..Default::default()
+        };
+        let entity = spawn_entity(
+            &mut commands,
+            &config,
+            &sprite_manager,
+            &species,
+            &mut partition,
+            &root,
+        );
+        commands.entity(entity).insert(Corpse { energy: amount });
+    }
+}
+
+/// 土壤的养分交换：尸体渗入土壤转化为肥力，能量散失；生长中的植物吸收肥力
+pub fn soil_system(
+    mut commands: Commands,
+    mut corpse_q: Query<(Entity, &mut Corpse, &Transform)>,
+    plant_q: Query<(&GrowthStage, &Transform)>,
+    mut partition: ResMut<SpatialPartition>,
+    mut ledger: ResMut<EnergyLedger>,
+    nutrients: Res<Nutrients>,
+    time: Res<Time>,
+) {
+    let config = &nutrients.config;
+    let secs = time.delta_secs();
+    for (entity, mut corpse, transform) in corpse_q.iter_mut() {
+        let pos = cell_of(&partition, transform);
+        let leached = corpse.energy * (config.leach_rate * secs).min(1.0);
+        corpse.energy -= leached;
+        ledger.decayed += leached as f64;
+        partition.fertilize(
+            &pos,
+            leached * config.corpse_nutrients,
+            config.max_fertility,
+        );
+        // 分解完毕的尸体剩下的部分全部还给土壤，剩余的能量在移除时记入账目
+        if corpse.energy < MIN_CORPSE_ENERGY {
+            partition.fertilize(
+                &pos,
+                corpse.energy * config.corpse_nutrients,
+                config.max_fertility,
+            );
+            partition.remove_entity(entity, &pos, EntityType::Corpse);
+            commands.entity(entity).despawn();
+        }
+    }
+
+    for (growth, transform) in plant_q.iter() {
+        if growth.is_mature() {
+            continue;
+        }
+        let pos = cell_of(&partition, transform);
+        partition.deplete(&pos, config.uptake * secs);
+    }
+}
+
+/// 分解者分解附近的尸体，没有能量时枯萎
+#[allow(clippy::too_many_arguments)]
+pub fn decomposer_system(
+    mut commands: Commands,
+    mut decomposer_q: Query<(Entity, &Decomposer, &mut Metabolism, &Transform)>,
+    mut corpse_q: Query<&mut Corpse>,
+    mut partition: ResMut<SpatialPartition>,
+    mut ledger: ResMut<EnergyLedger>,
+    mut ecology: EventWriter<EcologyEvent>,
+    mut corpses: EventWriter<CorpseEvent>,
+    nutrients: Res<Nutrients>,
+    time: Res<Time>,
+) {
+    let config = &nutrients.config;
+    for (entity, decomposer, mut metabolism, transform) in decomposer_q.iter_mut() {
+        let pos = cell_of(&partition, transform);
+        if metabolism.is_starved() {
+            partition.remove_entity(entity, &pos, EntityType::Fungus);
+            commands.entity(entity).despawn();
+            ecology.write(EcologyEvent::Death(EntityType::Fungus));
+            corpses.write(CorpseEvent {
+                pos,
+                energy: metabolism.energy,
+            });
+            continue;
+        }
+
+        // 由近到远分解尸体，分解出的肥力留在尸体所在的地块，分解者只吸收一部分能量
+        let mut budget = decomposer.rate * time.delta_secs();
+        for found in partition.entities_of_type_within(&pos, decomposer.radius, &EntityType::Corpse)
+        {
+            if budget <= 0.0 {
+                break;
+            }
+            let Ok(mut corpse) = corpse_q.get_mut(found.entity) else {
+                continue;
+            };
+            let amount = budget.min(corpse.energy);
+            corpse.energy -= amount;
+            budget -= amount;
+            partition.fertilize(
+                &found.pos,
+                amount * config.corpse_nutrients,
+                config.max_fertility,
+            );
+            let absorbed = metabolism.gain(amount * config.decomposer_yield.clamp(0.0, 1.0));
+            ledger.decayed += (amount - absorbed) as f64;
+        }
+    }
+}
+
+/// 成熟的植物定期向相邻的空地播种，种子的能量由亲代支付
+pub fn plant_seeding_system(
+    plant_q: Query<(Entity, &GrowthStage, &Transform), Without<Sterile>>,
+    partition: Res<SpatialPartition>,
+    nutrients: Res<Nutrients>,
+    mut spawn_events: EventWriter<SpawnEntityEvent>,
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+) {
+    let config = &nutrients.config;
+    *elapsed += time.delta_secs();
+    if *elapsed < config.seed_interval {
+        return;
+    }
+    *elapsed = 0.0;
+
+    // 同一轮中每个地块只接受一颗种子
+    let mut seeded = HashSet::new();
+    for (entity, growth, transform) in plant_q.iter() {
+        if !growth.is_mature() {
+            continue;
+        }
+        let pos = cell_of(&partition, transform);
+        let chance = config.seed_chance * config.growth_factor(partition.fertility(&pos));
+        if !rand::random_bool(chance.clamp(0.0, 1.0) as f64) {
+            continue;
+        }
+        let candidates: Vec<_> = partition
+            .get_valid_neighbours(&pos)
+            .into_iter()
+            .filter(|cell| {
+                partition.check_entity_conflict_by_pos(EntityType::Grass, cell)
+                    && !seeded.contains(cell)
+            })
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+        let target = candidates[rand::random_range(0..candidates.len())];
+        seeded.insert(target);
+        spawn_events.write(SpawnEntityEvent {
+            config: EntityConfig {
+                entity_type: EntityType::Grass,
+                pos: target.to_vec2(),
+                growth_rate: Some(config.seed_growth_rate),
+                ..Default::default()
+            },
+            by_player: false,
+            parent: Some(entity),
+        });
+    }
+}
+
+/// 附近没有分解者的尸体按播种的间隔尝试在相邻的空地上长出真菌
+pub fn spore_system(
+    corpse_q: Query<&Transform, With<Corpse>>,
+    partition: Res<SpatialPartition>,
+    nutrients: Res<Nutrients>,
+    mut spawn_events: EventWriter<SpawnEntityEvent>,
+    mut elapsed: Local<f32>,
+    time: Res<Time>,
+) {
+    let config = &nutrients.config;
+    *elapsed += time.delta_secs();
+    if *elapsed < config.seed_interval {
+        return;
+    }
+    *elapsed = 0.0;
+
+    // 本轮长出的真菌还不在SpatialPartition中，单独记录
+    let mut sprouted: Vec<HexMapPosition> = Vec::new();
+    for transform in corpse_q.iter() {
+        let pos = cell_of(&partition, transform);
+        let tended = !partition
+            .entities_of_type_within(&pos, SPORE_RADIUS, &EntityType::Fungus)
+            .is_empty()
+            || sprouted
+                .iter()
+                .any(|cell| hex_distance(cell, &pos) <= SPORE_RADIUS);
+        if tended || !rand::random_bool(config.spore_chance.clamp(0.0, 1.0) as f64) {
+            continue;
+        }
+        let candidates: Vec<_> = partition
+            .get_valid_neighbours(&pos)
+            .into_iter()
+            .filter(|cell| partition.check_entity_conflict_by_pos(EntityType::Fungus, cell))
+            .collect();
+        if candidates.is_empty() {
+            continue;
+        }
+        let target = candidates[rand::random_range(0..candidates.len())];
+        sprouted.push(target);
+        spawn_events.write(SpawnEntityEvent {
+            config: EntityConfig {
+                entity_type: EntityType::Fungus,
+                pos: target.to_vec2(),
+                ..Default::default()
+            },
+            by_player: false,
+            parent: None,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::core::HexGridConfig;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn test_nutrient_config_ron() {
+        let config = ron::de::from_str::<NutrientConfig>("(seed_chance:0.5)").unwrap();
+        assert_eq!(config.seed_chance, 0.5);
+        assert_eq!(config.base_fertility, 1.0);
+    }
+
+    #[test]
+    fn test_decomposer_returns_nutrients_to_soil() {
+        let mut world = World::new();
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        partition.enable_fertility(1.0);
+        let fungus_pos = HexMapPosition::new(3, 3);
+        let corpse_pos = HexMapPosition::new(4, 3);
+        let transform_of = |partition: &SpatialPartition, pos: &HexMapPosition| {
+            Transform::from_translation(partition.grid_to_world(&pos.to_vec2()))
+        };
+
+        let fungus = world
+            .spawn((
+                Decomposer {
+                    rate: 0.5,
+                    radius: 1,
+                },
+                Metabolism {
+                    energy: 10.0,
+                    max: 50.0,
+                    ..Default::default()
+                },
+                transform_of(&partition, &fungus_pos),
+            ))
+            .id();
+        let corpse = world
+            .spawn((
+                Corpse { energy: 2.0 },
+                transform_of(&partition, &corpse_pos),
+            ))
+            .id();
+        partition.insert_cache_entity(fungus, &fungus_pos, EntityType::Fungus);
+        partition.insert_cache_entity(corpse, &corpse_pos, EntityType::Corpse);
+        world.insert_resource(partition);
+        world.insert_resource(Nutrients::new(NutrientConfig {
+            corpse_nutrients: 1.0,
+            ..Default::default()
+        }));
+        world.insert_resource(EnergyLedger::default());
+        world.init_resource::<Events<EcologyEvent>>();
+        world.init_resource::<Events<CorpseEvent>>();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs(1));
+        world.insert_resource(time);
+
+        world.run_system_once(decomposer_system).unwrap();
+        // 分解0.5能量，肥力回到土壤，分解者吸收一半能量，另一半散失
+        assert_eq!(world.get::<Corpse>(corpse).unwrap().energy, 1.5);
+        assert_eq!(world.get::<Metabolism>(fungus).unwrap().energy, 10.25);
+        let ledger = world.resource::<EnergyLedger>();
+        assert_eq!(ledger.decayed, 0.25);
+        assert_eq!(ledger.produced, 0.0);
+        let partition = world.resource::<SpatialPartition>();
+        assert_eq!(partition.fertility(&corpse_pos), 1.5);
+        assert_eq!(partition.fertility(&fungus_pos), 1.0);
+
+        // 肥力影响生长速度，有上下限
+        let config = NutrientConfig::default();
+        assert_eq!(config.growth_factor(1.5), 1.5);
+        assert_eq!(config.growth_factor(0.0), MIN_GROWTH_FACTOR);
+        assert_eq!(config.growth_factor(10.0), 3.0);
+
+        // 没有能量的分解者枯萎
+        world.get_mut::<Metabolism>(fungus).unwrap().energy = 0.0;
+        world.run_system_once(decomposer_system).unwrap();
+        assert!(world.get_entity(fungus).is_err());
+        let partition = world.resource::<SpatialPartition>();
+        assert_eq!(partition.count_by_type(&EntityType::Fungus), 0);
+        // 枯萎的分解者同样留下尸体
+        assert_eq!(world.resource::<Events<CorpseEvent>>().len(), 1);
+    }
+}
@@ -0,0 +1,204 @@
+//! 投放预览
+//!
+//! 选中卡片时，鼠标所在的地块按卡片规则染成绿色或红色，不能作用时在鼠标旁提示原因。
+//! 投放卡片（以及已选中实体的迁移卡片）还会在地块上显示半透明的实体预览。
+
+use bevy::prelude::*;
+
+use super::grid::HexCellStates;
+use super::hex_grid::{HexMapPosition, SpatialPartition};
+use super::interaction::{GlobalMousePosition, MapCellColors, SpecialMapCellHolder};
+use crate::core::components::EntityType;
+use crate::core::entities::OnMapEntitiesRoot;
+use crate::core::player_action::CardAction;
+use crate::scenes::{GameSceneUIRoot, LevelGold};
+use crate::sprite::sprite_mgr::SpriteManager;
+use crate::ui::{CardPlacementRules, EntityCardInfo, SelectedCardHolder};
+
+const GHOST_ALPHA: f32 = 0.55;
+const GHOST_Z: f32 = 3.0;
+
+/// 当前的预览结果
+#[derive(Resource, Default)]
+pub struct PlacementPreview {
+    pub pos: Option<HexMapPosition>, // 鼠标所在的地块，未选中卡片时为None
+    pub invalid: Option<&'static str>, // 不能作用的原因
+    tinted: Option<HexMapPosition>,  // 已经染色的地块
+}
+
+/// 半透明的实体预览
+#[derive(Component)]
+pub struct PlacementGhost(EntityType);
+
+/// 鼠标旁的提示
+#[derive(Component)]
+pub struct PlacementTooltip;
+
+/// 按卡片规则检查鼠标所在的地块并染色
+#[allow(clippy::too_many_arguments)]
+pub fn placement_preview_system(
+    mouse_position: Res<GlobalMousePosition>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+    card_holder: Res<SelectedCardHolder>,
+    card_q: Query<&EntityCardInfo>,
+    rules: Option<Res<CardPlacementRules>>,
+    partition: Res<SpatialPartition>,
+    level_gold: Res<LevelGold>,
+    colors: Res<MapCellColors>,
+    cell_holder: Res<SpecialMapCellHolder>,
+    mut cell_states: ResMut<HexCellStates>,
+    mut preview: ResMut<PlacementPreview>,
+) {
+    let card = card_holder.0.and_then(|card| card_q.get(card).ok());
+    let pos = card
+        .filter(|_| mouse_position.is_in_primary_window)
+        .and_then(|_| camera_q.single().ok())
+        .and_then(|(camera, transform)| {
+            camera
+                .viewport_to_world_2d(transform, mouse_position.pos)
+                .ok()
+        })
+        .map(|world| partition.world_to_grid(&world))
+        .filter(|pos| partition.is_valid_position(pos));
+
+    // 离开的地块恢复悬停或默认的颜色
+    if let Some(old) = preview.tinted
+        && Some(old) != pos
+    {
+        let hovered = cell_holder.hovered == Some(old);
+        cell_states.set_overlay(&old, hovered.then_some(colors.hovered));
+        preview.tinted = None;
+    }
+
+    let (Some(card), Some(pos), Some(rules)) = (card, pos, rules) else {
+        preview.pos = None;
+        preview.invalid = None;
+        return;
+    };
+    let invalid = rules.check(card, &pos, &partition, level_gold.0).err();
+    let color = match invalid {
+        Some(_) => colors.invalid,
+        None => colors.valid,
+    };
+    cell_states.set_overlay(&pos, Some(color));
+    preview.tinted = Some(pos);
+    preview.pos = Some(pos);
+    preview.invalid = invalid;
+}
+
+/// 在预览的地块上显示将要投放的实体
+#[allow(clippy::too_many_arguments)]
+pub fn update_placement_ghost_system(
+    mut commands: Commands,
+    preview: Res<PlacementPreview>,
+    card_holder: Res<SelectedCardHolder>,
+    card_q: Query<&EntityCardInfo>,
+    mut ghost_q: Query<(
+        Entity,
+        &PlacementGhost,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+    root_q: Query<Entity, With<OnMapEntitiesRoot>>,
+    sprite_mgr: Res<SpriteManager>,
+    partition: Res<SpatialPartition>,
+) {
+    let ghost_type = card_holder
+        .0
+        .and_then(|card| card_q.get(card).ok())
+        .filter(|card| card.action == CardAction::Spawn || card.pending.is_some())
+        .map(|card| card.entity_type.clone());
+
+    // 卡片对应的实体类型变化后重新生成预览
+    let mut current = None;
+    for (entity, ghost, transform, sprite, visibility) in ghost_q.iter_mut() {
+        if Some(&ghost.0) == ghost_type.as_ref() {
+            current = Some((transform, sprite, visibility));
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    let Some(entity_type) = ghost_type else {
+        return;
+    };
+    let Some((mut transform, mut sprite, mut visibility)) = current else {
+        if let Ok(root) = root_q.single() {
+            let name = entity_type.to_string().to_lowercase();
+            let mut sprite = sprite_mgr.get_sprite_by_name(&name);
+            sprite.color = sprite.color.with_alpha(GHOST_ALPHA);
+            commands.spawn((
+                PlacementGhost(entity_type),
+                sprite,
+                Transform::default(),
+                Visibility::Hidden,
+                ChildOf(root),
+            ));
+        }
+        return;
+    };
+
+    let Some(pos) = preview.pos else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+    transform.translation = partition.grid_to_world(&pos.to_vec2()).with_z(GHOST_Z);
+    // 不能投放时预览偏红
+    let tint = match preview.invalid {
+        Some(_) => Color::srgba(1.0, 0.35, 0.35, GHOST_ALPHA),
+        None => Color::WHITE.with_alpha(GHOST_ALPHA),
+    };
+    if sprite.color != tint {
+        sprite.color = tint;
+    }
+    visibility.set_if_neq(Visibility::Inherited);
+}
+
+/// 不能作用时在鼠标旁提示原因
+pub fn update_placement_tooltip_system(
+    mut commands: Commands,
+    preview: Res<PlacementPreview>,
+    mouse_position: Res<GlobalMousePosition>,
+    mut tooltip_q: Query<(&mut Node, &mut Text, &mut Visibility), With<PlacementTooltip>>,
+    ui_root: Query<Entity, With<GameSceneUIRoot>>,
+    asset_server: Res<AssetServer>,
+) {
+    let Ok((mut node, mut text, mut visibility)) = tooltip_q.single_mut() else {
+        if let Ok(root) = ui_root.single() {
+            commands.spawn((
+                PlacementTooltip,
+                Node {
+                    position_type: PositionType::Absolute,
+                    padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                    ..Default::default()
+                },
+                BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.75)),
+                BorderRadius::all(Val::Px(4.0)),
+                Text::new(""),
+                TextFont {
+                    font: asset_server.load("fonts/msyh.ttc"),
+                    font_size: 16.0,
+                    ..Default::default()
+                },
+                TextColor(Color::srgb(1.00, 0.45, 0.45)),
+                GlobalZIndex(50),
+                Visibility::Hidden,
+                ChildOf(root),
+            ));
+        }
+        return;
+    };
+
+    let Some(reason) = preview.invalid else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+    if text.0 != reason {
+        text.0 = reason.to_string();
+    }
+    // 显示在鼠标右下方，避免挡住地块
+    node.left = Val::Px(mouse_position.pos.x + 16.0);
+    node.top = Val::Px(mouse_position.pos.y + 16.0);
+    visibility.set_if_neq(Visibility::Inherited);
+}
@@ -0,0 +1,221 @@
+//! 玩家操作
+//!
+//! 卡片除了投放实体，还可以移除、喂食、迁移或绝育地图上的实体。点击地图时由
+//! map_cell_click_system按卡片规则检查目标并发送CardActionEvent，这里统一执行操作，
+//! 成功后才扣除金币和使用次数；投放实体再转成SpawnEntityEvent交给实体生成系统。
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::economy::EcologyEvent;
+use super::hex_grid::{EntityWithCoord, HexMapPosition, SpatialPartition};
+use super::metabolism::EnergyLedger;
+use super::nutrient::CorpseEvent;
+use crate::ai::{AnimalActorBoard, EdibleEntity, PathfindingTask};
+use crate::core::components::{EntityType, GrowthStage, Metabolism, MoveTo, Sterile};
+use crate::core::entities::SpawnEntityEvent;
+use crate::level::config::EntityConfig;
+use crate::scenes::LevelGold;
+use crate::ui::{CardPlacementRules, EntityCardInfo};
+
+/// 喂食一次增加的能量占上限的比例
+pub const FEED_FRACTION: f32 = 0.3;
+
+/// 卡片的操作类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CardAction {
+    #[default]
+    Spawn, // 投放卡片对应的实体
+    Remove,    // 移除卡片对应类型的实体
+    Feed,      // 喂食动物，或者催熟植物
+    Relocate,  // 先选中实体，再点击目的地迁移
+    Sterilise, // 绝育，植物不再播种
+}
+
+/// 卡片可以作用的地块
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardTarget {
+    EmptyCell, // 没有同层实体的地块
+    Animal,    // 有卡片对应动物的地块
+    Plant,     // 有卡片对应植物的地块
+}
+
+impl CardAction {
+    /// 投放的目标是空地块，其它操作作用于卡片对应类型的实体
+    pub fn target(&self, entity_type: &EntityType) -> CardTarget {
+        match (self, entity_type) {
+            (CardAction::Spawn, _) => CardTarget::EmptyCell,
+            (_, t) if t.is_ground() => CardTarget::Plant,
+            _ => CardTarget::Animal,
+        }
+    }
+
+    /// 操作能否作用于该类型的实体，只有植物会繁殖，绝育只对植物有效
+    pub fn applies_to(&self, entity_type: &EntityType) -> bool {
+        match self {
+            CardAction::Sterilise => entity_type.is_plant(),
+            _ => true,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CardAction::Spawn => "投放",
+            CardAction::Remove => "移除",
+            CardAction::Feed => "喂食",
+            CardAction::Relocate => "迁移",
+            CardAction::Sterilise => "绝育",
+        }
+    }
+}
+
+/// 通过了卡片规则检查的操作
+#[derive(Event, Debug, Clone)]
+pub struct CardActionEvent {
+    pub card: Entity,
+    pub pos: HexMapPosition,             // 点击的地块，迁移时为目的地
+    pub target: Option<EntityWithCoord>, // 作用的实体，投放时为None
+}
+
+/// 执行卡片操作，实体已经不存在时不扣除任何消耗
+#[allow(clippy::too_many_arguments)]
+pub fn apply_card_action_system(
+    mut commands: Commands,
+    mut events: EventReader<CardActionEvent>,
+    mut spawn_events: EventWriter<SpawnEntityEvent>,
+    mut card_q: Query<&mut EntityCardInfo>,
+    mut board_q: Query<&mut AnimalActorBoard>,
+    mut edible_q: Query<&mut EdibleEntity>,
+    mut plant_q: Query<(&mut Transform, Option<&mut GrowthStage>), Without<AnimalActorBoard>>,
+    rules: Res<CardPlacementRules>,
+    mut partition: ResMut<SpatialPartition>,
+    mut metabolism_q: Query<&mut Metabolism>,
+    mut ledger: ResMut<EnergyLedger>,
+    mut level_gold: ResMut<LevelGold>,
+    mut ecology: EventWriter<EcologyEvent>,
+    mut corpses: EventWriter<CorpseEvent>,
+) {
+    for event in events.read() {
+        let Ok(card) = card_q.get(event.card) else {
+            continue;
+        };
+        let (action, entity_type, cost) = (card.action, card.entity_type.clone(), card.cost);
+
+        let done = match (action, event.target) {
+            (CardAction::Spawn, _) => {
+                spawn_events.write(SpawnEntityEvent {
+                    config: EntityConfig {
+                        entity_type,
+                        pos: event.pos.to_vec2(),
+                        ..Default::default()
+                    },
+                    by_player: true,
+                    parent: None,
+                });
+                true
+            }
+            (_, None) => false,
+            (CardAction::Remove, Some(target)) => {
+                let Ok(mut entity) = commands.get_entity(target.entity) else {
+                    continue;
+                };
+                // 移除实体前先释放它的预占，并从SpatialPartition中移除
+                let pos = current_pos(&board_q, &target);
+                release_reservation(&mut board_q, &mut edible_q, target.entity);
+                partition.remove_entity(target.entity, &pos, entity_type.clone());
+                entity.despawn();
+                // 被移除的生物带着剩余的能量留下尸体，尸体本身没有Metabolism
+                if let Ok(metabolism) = metabolism_q.get(target.entity) {
+                    corpses.write(CorpseEvent {
+                        pos,
+                        energy: metabolism.energy,
+                    });
+                }
+                ecology.write(EcologyEvent::Death(entity_type));
+                true
+            }
+            (CardAction::Feed, Some(target)) => {
+                if board_q.contains(target.entity)
+                    && let Ok(mut metabolism) = metabolism_q.get_mut(target.entity)
+                {
+                    let amount = metabolism.max * FEED_FRACTION;
+                    ledger.injected += metabolism.gain(amount) as f64;
+                    true
+                } else if let Ok((_, Some(mut growth))) = plant_q.get_mut(target.entity) {
+                    growth.stage = growth.max_stage;
+                    true
+                } else {
+                    false
+                }
+            }
+            (CardAction::Relocate, Some(target)) => {
+                if commands.get_entity(target.entity).is_err() {
+                    continue;
+                }
+                let from = current_pos(&board_q, &target);
+                release_reservation(&mut board_q, &mut edible_q, target.entity);
+                partition.move_entity(target.entity, &from, &event.pos, entity_type);
+                if let Ok(mut board) = board_q.get_mut(target.entity) {
+                    // 动物的位置由移动动画同步，正在进行的移动和寻路全部作废
+                    board.current_pos = event.pos;
+                    board.clear_forage_target();
+                    commands
+                        .entity(target.entity)
+                        .remove::<(MoveTo, PathfindingTask)>();
+                } else if let Ok((mut transform, _)) = plant_q.get_mut(target.entity) {
+                    let z = transform.translation.z;
+                    transform.translation = partition.grid_to_world(&event.pos.to_vec2());
+                    transform.translation.z = z;
+                }
+                true
+            }
+            (CardAction::Sterilise, Some(target)) => match commands.get_entity(target.entity) {
+                Ok(mut entity) => {
+                    entity.insert(Sterile);
+                    true
+                }
+                Err(_) => false,
+            },
+        };
+        if !done {
+            continue;
+        }
+
+        level_gold.0 = level_gold.0.saturating_sub(cost);
+        // 扣除使用次数，开始本卡片的冷却和所有卡片的公共冷却
+        if let Ok(mut card) = card_q.get_mut(event.card) {
+            rules.consume(&mut card);
+        }
+        for mut card in card_q.iter_mut() {
+            card.start_cooldown(rules.operation_cooldown);
+        }
+    }
+}
+
+// 动物在选中之后可能已经移动，以当前位置为准
+fn current_pos(board_q: &Query<&mut AnimalActorBoard>, target: &EntityWithCoord) -> HexMapPosition {
+    board_q
+        .get(target.entity)
+        .map_or(target.pos, |board| board.current_pos)
+}
+
+// 实体被移除或迁移后，它预占的食物和预占它的觅食者都要放弃
+pub(crate) fn release_reservation(
+    board_q: &mut Query<&mut AnimalActorBoard>,
+    edible_q: &mut Query<&mut EdibleEntity>,
+    entity: Entity,
+) {
+    if let Some(food) = board_q.get(entity).ok().and_then(|b| b.forage_target)
+        && let Ok(mut edible) = edible_q.get_mut(food)
+        && edible.reserved_by == Some(entity)
+    {
+        edible.reserved_by = None;
+    }
+    if let Ok(mut edible) = edible_q.get_mut(entity)
+        && let Some(actor) = edible.reserved_by.take()
+        && let Ok(mut board) = board_q.get_mut(actor)
+    {
+        board.clear_forage_target();
+    }
+}
@@ -1,112 +0,0 @@
-//! 实体状态机系统实现
-
-use super::super::components::*;
-use bevy::prelude::*;
-
-/// 实体状态类型
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
-pub enum EntityState {
-    Idle,
-    Moving,
-    Resting,
-}
-
-/// 状态组件
-#[derive(Component)]
-pub struct State {
-    pub current: EntityState,
-    pub previous: EntityState,
-}
-
-impl Default for State {
-    fn default() -> Self {
-        Self {
-            current: EntityState::Idle,
-            previous: EntityState::Idle,
-        }
-    }
-}
-
-impl State {
-    pub fn update(&mut self, _time: &Time) {
-        // 基于时间的状态更新逻辑
-        match self.current {
-            EntityState::Moving => {
-                // 移动状态下的更新逻辑
-            }
-            EntityState::Resting => {
-                // 休息状态下的更新逻辑
-            }
-            EntityState::Idle => {
-                // 空闲状态下的更新逻辑
-            }
-        }
-    }
-}
-
-/// 状态转换系统
-#[allow(unused_variables)]
-pub fn state_transition_system(mut query: Query<(&mut State, &EnergyStore, Option<&VisionRange>)>) {
-    for (mut state, energy, vision_range) in query.iter_mut() {
-        state.previous = state.current.clone();
-
-        match state.current {
-            EntityState::Moving if energy.value <= 0.0 => {
-                state.current = EntityState::Resting;
-            }
-            EntityState::Resting if energy.value >= energy.max => {
-                state.current = EntityState::Idle;
-            }
-            _ => {}
-        }
-    }
-}
-
-/// 状态机更新系统
-pub fn update_state_machine(mut query: Query<&mut State>) {
-    for _state in query.iter_mut() {
-        // 状态机更新逻辑
-    }
-}
-
-/// 状态转换条件检查系统
-#[allow(unused_variables)]
-pub fn transition_states(query: Query<(&State, &EnergyStore), Changed<State>>) {
-    for (state, energy) in query.iter() {
-        // 状态转换条件检查逻辑
-    }
-}
-
-/// 能量更新系统
-pub fn update_energy(mut query: Query<&mut EnergyStore>) {
-    for mut energy in query.iter_mut() {
-        // 简单能量更新逻辑
-        match energy.value {
-            v if v <= 0.0 => energy.value = 0.0,
-            v if v >= energy.max => energy.value = energy.max,
-            _ => energy.value -= 1.0,
-        }
-    }
-}
-
-/// 状态机系统集合
-pub fn state_machine_schedule() -> Schedule {
-    let mut schedule = Schedule::default();
-    schedule.add_systems(
-        (
-            update_state_machine,
-            update_energy,
-            transition_states,
-            state_transition_system,
-        )
-            .run_if(in_state(crate::core::state::GameState::Playing)),
-    );
-    schedule
-}
-
-/// 状态机主系统
-pub fn state_machine_system(mut query: Query<&mut State>, time: Res<Time>) {
-    for mut state in query.iter_mut() {
-        state.update(&time);
-    }
-}
@@ -0,0 +1,49 @@
+//! 视野计算
+//!
+//! 每个固定帧开始时为带有VisionRange的动物计算能看到的地块，写入FieldOfView。
+//! 觅食、逃跑等AI行为只在FieldOfView中查找目标，障碍后面的实体看不到。
+//! 开启环境系统时夜间的视野半径缩小。
+
+use bevy::prelude::*;
+
+use super::environment::Environment;
+use super::hex_grid::SpatialPartition;
+use super::lod::{Sleeping, simulation_delta};
+use crate::ai::AnimalActorBoard;
+use crate::core::components::{FieldOfView, VisionRange};
+
+/// 更新视野，所在地块、视野半径和障碍都没有变化时沿用上次的结果
+pub fn update_field_of_view_system(
+    mut query: Query<(
+        &AnimalActorBoard,
+        &VisionRange,
+        &mut FieldOfView,
+        Option<&Sleeping>,
+    )>,
+    partition: Res<SpatialPartition>,
+    environment: Option<Res<Environment>>,
+    time: Res<Time>,
+) {
+    let version = partition.obstacle_version();
+    for (board, vision, mut fov, sleeping) in query.iter_mut() {
+        // 休眠的动物只在自己的更新帧需要视野
+        if simulation_delta(sleeping, &time).is_none() {
+            continue;
+        }
+        let radius = environment
+            .as_ref()
+            .map_or(vision.radius, |env| env.vision_radius(vision.radius));
+        if fov.origin == Some(board.current_pos)
+            && fov.radius == radius
+            && fov.obstacle_version == version
+        {
+            continue;
+        }
+
+        fov.cells = partition.visible_cells(&board.current_pos, radius);
+        fov.visible = fov.cells.iter().copied().collect();
+        fov.origin = Some(board.current_pos);
+        fov.radius = radius;
+        fov.obstacle_version = version;
+    }
+}
@@ -1,6 +1,15 @@
+use crate::ai::PotentialFieldWeights;
 use crate::core::components::EntityType;
+use crate::core::economy::EconomyConfig;
+use crate::core::entities::SpeciesStats;
+use crate::core::environment::EnvironmentConfig;
+use crate::core::fog::FogOfWarConfig;
+use crate::core::hex_math::HexOrientation;
+use crate::core::nutrient::NutrientConfig;
+use crate::core::player_action::CardAction;
+use crate::level::food_chain::{FoodChain, MAX_FOOD_CHAIN_TEMPLATES};
 use bevy::{
-    asset::{AssetLoader, LoadContext, io::Reader},
+    asset::{AssetLoader, LoadContext, LoadDirectError, io::Reader},
     platform::collections::{HashMap, HashSet},
     prelude::*,
 };
@@ -19,22 +28,46 @@ pub struct EntityFoodRelations {
 
 #[derive(Asset, TypePath, Debug, Serialize, Deserialize, Default)]
 pub struct GlobalConfiguration {
-    pub entity_configs: HashMap<EntityType, EntityConfig>, 
-    
+    pub entity_configs: HashMap<EntityType, EntityConfig>,
 }
 
 /// 关卡配置
 #[derive(Asset, TypePath, Debug, Serialize, Deserialize, Default)]
 pub struct LevelConfigAsset {
-    pub name: String,                      // 关卡名称，貌似没什么用
-    pub size: UVec2,                       // 关卡地图大小
+    pub name: String, // 关卡名称，貌似没什么用
+    pub size: UVec2,  // 关卡地图大小
+    #[serde(default)]
+    pub orientation: HexOrientation, // 六边形朝向，平顶地图的坐标为奇列偏移(列, 行)
+    #[serde(default = "default_hex_size")]
+    pub hex_size: f32, // 六边形边长（像素），默认为60
     pub startup_camera_pos: Option<IVec2>, // 初始相机位置，可选项
     #[serde(default = "default_init_gold")]
     pub init_gold: u32, // 地图初始化金币，默认为10
-    pub entities: Vec<EntityConfig>,       // 地图上初始实体列表
-    pub useable_cards: Vec<CardConfig>,    // 本关卡可用卡片
+    pub entities: Vec<EntityConfig>, // 地图上初始实体列表
+    #[serde(default)]
+    pub obstacles: Vec<IVec2>, // 岩石、树林等阻挡通行和视线的地块
+    pub useable_cards: Vec<CardConfig>, // 本关卡可用卡片
+    #[serde(default = "default_operation_cooldown")]
+    pub operation_cooldown: f32, // 每次投放后所有卡片的公共冷却（秒）
 
+    #[serde(default)]
     pub food_chains: HashMap<EntityType, EntityFoodRelations>,
+    #[serde(default)]
+    pub food_chain_templates: Vec<String>, // 引用的食物链模板id，最多3条，会合并到food_chains中
+    #[serde(skip)]
+    pub food_chain_docs: Vec<FoodChain>, // 加载好的食物链模板
+    #[serde(default)]
+    pub potential_fields: HashMap<EntityType, PotentialFieldWeights>, // 按物种覆盖势场权重
+    #[serde(default)]
+    pub species: HashMap<EntityType, SpeciesStats>, // 按物种覆盖速度、视野和代谢等属性
+    #[serde(default)]
+    pub fog_of_war: Option<FogOfWarConfig>, // 战争迷雾，不配置则不开启
+    #[serde(default)]
+    pub economy: EconomyConfig, // 金币收入规则，不配置则没有收入
+    #[serde(default)]
+    pub nutrients: Option<NutrientConfig>, // 养分循环，不配置则不开启
+    #[serde(default)]
+    pub environment: Option<EnvironmentConfig>, // 季节、昼夜和天气，不配置则不开启
 }
 
 // #[derive(Asset, TypePath, Debug, Serialize, Deserialize)]
@@ -51,15 +84,29 @@ pub struct LevelConfigAsset {
 #[derive(Asset, TypePath, Debug, Serialize, Deserialize, Clone)]
 pub struct CardConfig {
     #[serde(rename = "type")]
-    pub entity_type: EntityType, // 卡片对应的实体类型
+    pub entity_type: EntityType, // 卡片对应的实体类型，非投放卡片为作用的实体类型
+    #[serde(default)]
+    pub action: CardAction, // 卡片的操作，默认为投放
     pub cost: u32,        // 放置卡片对应的实体需要的金币数量
     pub count_limit: u32, // 本关卡此卡片可用数量，0表示无限制
+    #[serde(default)]
+    pub placement_radius: Option<i32>, // 投放范围，距初始相机位置的格数，不配置则不限制
+    #[serde(default)]
+    pub cooldown: f32, // 每次投放后此卡片的冷却（秒）
 }
 
 fn default_init_gold() -> u32 {
     10
 }
 
+fn default_hex_size() -> f32 {
+    60.0
+}
+
+fn default_operation_cooldown() -> f32 {
+    1.0
+}
+
 #[derive(Default)]
 pub struct LevelConfigAssetLoader;
 
@@ -73,6 +120,34 @@ pub enum LevelConfigAssetLoaderError {
     /// A [RON](ron) Error
     #[error("Could not parse RON: {0}")]
     RonSpannedError(#[from] ron::error::SpannedError),
+    /// 引用的食物链模板加载失败
+    #[error("Could not load food chain: {0}")]
+    FoodChain(#[from] Box<LoadDirectError>),
+    #[error("Too many food chain templates: {0}, at most {MAX_FOOD_CHAIN_TEMPLATES}")]
+    TooManyFoodChains(usize),
+    #[error("Food chain template `{0}` declares a different id `{1}`")]
+    FoodChainIdMismatch(String, String),
+    #[error("Card action {0:?} can't target {1:?}")]
+    InvalidCardAction(CardAction, EntityType),
+}
+
+impl LevelConfigAsset {
+    /// 把食物链模板中的捕食关系合并到food_chains
+    pub fn merge_food_chain(&mut self, chain: FoodChain) {
+        for (prey, predator, _) in chain.links() {
+            self.food_chains
+                .entry(predator.clone())
+                .or_default()
+                .preys_on
+                .insert(prey.clone());
+            self.food_chains
+                .entry(prey.clone())
+                .or_default()
+                .predators_of
+                .insert(predator.clone());
+        }
+        self.food_chain_docs.push(chain);
+    }
 }
 
 /// 实体配置
@@ -97,11 +172,44 @@ impl AssetLoader for LevelConfigAssetLoader {
         &self,
         reader: &mut dyn Reader,
         _settings: &(),
-        _load_context: &mut LoadContext<'_>,
+        load_context: &mut LoadContext<'_>,
     ) -> Result<Self::Asset, Self::Error> {
         let mut bytes = Vec::new();
         reader.read_to_end(&mut bytes).await?;
-        let level_asset = ron::de::from_bytes::<LevelConfigAsset>(&bytes)?;
+        let mut level_asset = ron::de::from_bytes::<LevelConfigAsset>(&bytes)?;
+        if let Some(card) = level_asset
+            .useable_cards
+            .iter()
+            .find(|card| !card.action.applies_to(&card.entity_type))
+        {
+            return Err(LevelConfigAssetLoaderError::InvalidCardAction(
+                card.action,
+                card.entity_type.clone(),
+            ));
+        }
+
+        // 食物链模板在关卡加载完成前就要合并，直接加载而不是作为依赖异步加载
+        let templates = level_asset.food_chain_templates.clone();
+        if templates.len() > MAX_FOOD_CHAIN_TEMPLATES {
+            return Err(LevelConfigAssetLoaderError::TooManyFoodChains(
+                templates.len(),
+            ));
+        }
+        for id in templates {
+            let chain = load_context
+                .loader()
+                .immediate()
+                .load::<FoodChain>(FoodChain::path_of(&id))
+                .await
+                .map_err(Box::new)?
+                .take();
+            if chain.id != id {
+                return Err(LevelConfigAssetLoaderError::FoodChainIdMismatch(
+                    id, chain.id,
+                ));
+            }
+            level_asset.merge_food_chain(chain);
+        }
         Ok(level_asset)
     }
 
@@ -113,7 +221,7 @@ impl AssetLoader for LevelConfigAssetLoader {
 #[cfg(test)]
 mod tests {
     use crate::{
-        core::components::EntityType,
+        core::{components::EntityType, hex_math::HexOrientation},
         level::config::{EntityConfig, EntityFoodRelations, LevelConfigAsset},
     };
     use bevy::{
@@ -141,6 +249,7 @@ mod tests {
             init_gold: 10,
             entities: vec![entity.clone()],
             food_chains: HashMap::new(),
+            potential_fields: HashMap::new(),
             ..Default::default()
         };
 
@@ -155,4 +264,23 @@ mod tests {
             ron::ser::to_string(&entity).unwrap()
         );
     }
+
+    #[test]
+    fn ron_layout_test() {
+        let level = "(name:\"t\",size:(4,4),startup_camera_pos:None,entities:[],useable_cards:[],food_chains:{})";
+        let cfg = ron::de::from_str::<LevelConfigAsset>(level).unwrap();
+        assert_eq!(cfg.orientation, HexOrientation::Pointy);
+        assert_eq!(cfg.hex_size, 60.0);
+        assert_eq!(cfg.operation_cooldown, 1.0);
+
+        let level = level.replace("size:(4,4),", "size:(4,4),orientation:flat,hex_size:40.0,");
+        let cfg = ron::de::from_str::<LevelConfigAsset>(&level).unwrap();
+        assert_eq!(cfg.orientation, HexOrientation::Flat);
+        assert_eq!(cfg.hex_size, 40.0);
+        assert!(cfg.obstacles.is_empty());
+
+        let obstacles = level.replace("entities:[],", "entities:[],obstacles:[(1,2),(2,2)],");
+        let cfg = ron::de::from_str::<LevelConfigAsset>(&obstacles).unwrap();
+        assert_eq!(cfg.obstacles, vec![IVec2::new(1, 2), IVec2::new(2, 2)]);
+    }
 }
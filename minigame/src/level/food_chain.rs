@@ -0,0 +1,267 @@
+//! 食物链模板
+//!
+//! 设计文档中的FoodChain 2.0格式：节点带营养级和可视化坐标，连线带能量转化率。
+//! 模板是独立的`.fcn`资源（RON格式），关卡在`food_chain_templates`中按id引用1-3条，
+//! 加载关卡时合并进food_chains，能量转化率用于计算进食获得的能量。
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::config::LevelConfigAsset;
+use crate::core::components::EntityType;
+
+pub const FOOD_CHAIN_VERSION: &str = "2.0";
+/// 每个关卡最多引用的食物链模板数量
+pub const MAX_FOOD_CHAIN_TEMPLATES: usize = 3;
+/// 模板中没有定义的捕食关系使用的能量转化率
+pub const DEFAULT_ENERGY_RATE: f32 = 0.5;
+
+/// 食物链模板
+#[derive(Asset, TypePath, Debug, Clone, Serialize, Deserialize)]
+pub struct FoodChain {
+    pub id: String,
+    pub version: String, // 结构版本标识，目前只支持2.0
+    pub nodes: Vec<FoodChainNode>,
+    pub edges: Vec<FoodChainEdge>,
+    #[serde(default)]
+    pub visual: Option<FoodChainVisual>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoodChainNode {
+    pub id: String, // 节点唯一ID
+    pub entity: EntityType,
+    pub level: u32, // 食物链层级，1为生产者
+    #[serde(default)]
+    pub position: Option<Vec2>, // 可视化坐标(0-100)
+}
+
+/// 能量从source流向target，即target捕食source
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoodChainEdge {
+    pub source: String,
+    pub target: String,
+    pub energy: f32, // 能量转化率(0-1)
+    #[serde(default)]
+    pub branch: bool, // 是否为分支路径
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FoodChainVisual {
+    pub color: String,
+    pub thickness: f32, // 连线粗细(px)
+}
+
+/// 食物链模板校验错误
+#[derive(Debug, Error, PartialEq)]
+pub enum FoodChainError {
+    #[error("food chain `{id}`: unsupported version `{version}`")]
+    UnsupportedVersion { id: String, version: String },
+    #[error("food chain `{id}`: duplicated node `{node}`")]
+    DuplicatedNode { id: String, node: String },
+    #[error("food chain `{id}`: edge refers to unknown node `{node}`")]
+    UnknownNode { id: String, node: String },
+    #[error("food chain `{id}`: energy rate {energy} of edge {from}->{to} is out of 0-1")]
+    InvalidEnergy {
+        id: String,
+        from: String,
+        to: String,
+        energy: f32,
+    },
+}
+
+impl FoodChain {
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, FoodChainLoaderError> {
+        let chain = ron::de::from_bytes::<Self>(bytes)?;
+        chain.validate()?;
+        Ok(chain)
+    }
+
+    /// 模板在assets目录中的路径
+    pub fn path_of(id: &str) -> String {
+        format!("food_chains/{}.fcn", id)
+    }
+
+    pub fn validate(&self) -> Result<(), FoodChainError> {
+        if self.version != FOOD_CHAIN_VERSION {
+            return Err(FoodChainError::UnsupportedVersion {
+                id: self.id.clone(),
+                version: self.version.clone(),
+            });
+        }
+        let mut ids = HashSet::new();
+        for node in self.nodes.iter() {
+            if !ids.insert(node.id.as_str()) {
+                return Err(FoodChainError::DuplicatedNode {
+                    id: self.id.clone(),
+                    node: node.id.clone(),
+                });
+            }
+        }
+        for edge in self.edges.iter() {
+            for node in [&edge.source, &edge.target] {
+                if !ids.contains(node.as_str()) {
+                    return Err(FoodChainError::UnknownNode {
+                        id: self.id.clone(),
+                        node: node.clone(),
+                    });
+                }
+            }
+            if !(0.0..=1.0).contains(&edge.energy) {
+                return Err(FoodChainError::InvalidEnergy {
+                    id: self.id.clone(),
+                    from: edge.source.clone(),
+                    to: edge.target.clone(),
+                    energy: edge.energy,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn entity_of(&self, node: &str) -> Option<&EntityType> {
+        self.nodes.iter().find(|n| n.id == node).map(|n| &n.entity)
+    }
+
+    /// 模板中的捕食关系：(猎物, 捕食者, 能量转化率)
+    pub fn links(&self) -> impl Iterator<Item = (&EntityType, &EntityType, f32)> {
+        self.edges.iter().filter_map(|edge| {
+            Some((
+                self.entity_of(&edge.source)?,
+                self.entity_of(&edge.target)?,
+                edge.energy,
+            ))
+        })
+    }
+}
+
+/// 当前关卡各捕食关系的能量转化率
+#[derive(Resource, Debug, Clone, Default)]
+pub struct EnergyTransfer {
+    rates: HashMap<(EntityType, EntityType), f32>,
+}
+
+impl EnergyTransfer {
+    pub fn from_level(level: &LevelConfigAsset) -> Self {
+        let mut rates = HashMap::new();
+        // 多条模板定义了同一关系时，以先引用的模板为准
+        for chain in level.food_chain_docs.iter() {
+            for (prey, predator, energy) in chain.links() {
+                rates
+                    .entry((prey.clone(), predator.clone()))
+                    .or_insert(energy);
+            }
+        }
+        Self { rates }
+    }
+
+    pub fn rate(&self, prey: &EntityType, predator: &EntityType) -> f32 {
+        self.rates
+            .get(&(prey.clone(), predator.clone()))
+            .copied()
+            .unwrap_or(DEFAULT_ENERGY_RATE)
+    }
+
+    /// 吃掉储存了prey_energy能量的猎物后获得的能量
+    pub fn meal(&self, prey: &EntityType, predator: &EntityType, prey_energy: f32) -> f32 {
+        prey_energy.max(0.0) * self.rate(prey, predator)
+    }
+}
+
+#[derive(Default)]
+pub struct FoodChainLoader;
+
+/// 食物链模板加载错误
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum FoodChainLoaderError {
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse RON: {0}")]
+    RonSpannedError(#[from] ron::error::SpannedError),
+    #[error("Invalid food chain: {0}")]
+    Invalid(#[from] FoodChainError),
+}
+
+impl AssetLoader for FoodChainLoader {
+    type Asset = FoodChain;
+    type Settings = ();
+    type Error = FoodChainLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        FoodChain::from_ron(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["fcn"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIN: &str = r##"(
+        id: "chain_1",
+        version: "2.0",
+        nodes: [
+            (id: "n1", entity: (type: grass), level: 1, position: Some((30.0, 20.0))),
+            (id: "n2", entity: (type: rabbit), level: 2),
+            (id: "n3", entity: (type: fox), level: 3),
+        ],
+        edges: [
+            (source: "n1", target: "n2", energy: 0.3),
+            (source: "n2", target: "n3", energy: 0.2, branch: true),
+        ],
+        visual: Some((color: "#4CAF50", thickness: 2.0)),
+    )"##;
+
+    #[test]
+    fn test_food_chain_template() {
+        let chain = FoodChain::from_ron(CHAIN.as_bytes()).unwrap();
+        let links: Vec<_> = chain.links().collect();
+        assert_eq!(links[0], (&EntityType::Grass, &EntityType::Rabbit, 0.3));
+        assert!(chain.edges[1].branch);
+
+        let mut level = LevelConfigAsset::default();
+        level.merge_food_chain(chain.clone());
+        let rabbit = &level.food_chains[&EntityType::Rabbit];
+        assert!(rabbit.preys_on.contains(&EntityType::Grass));
+        assert!(rabbit.predators_of.contains(&EntityType::Fox));
+
+        let energy = EnergyTransfer::from_level(&level);
+        let meal = energy.meal(&EntityType::Grass, &EntityType::Rabbit, 100.0);
+        assert!((meal - 30.0).abs() < 1e-4);
+        // 模板中没有的关系使用默认转化率
+        assert_eq!(
+            energy.meal(&EntityType::Grass, &EntityType::Fox, 100.0),
+            50.0
+        );
+
+        let mut invalid = chain;
+        invalid.edges[0].target = "n9".to_string();
+        assert_eq!(
+            invalid.validate(),
+            Err(FoodChainError::UnknownNode {
+                id: "chain_1".to_string(),
+                node: "n9".to_string(),
+            })
+        );
+        invalid.version = "1.0".to_string();
+        assert!(matches!(
+            invalid.validate(),
+            Err(FoodChainError::UnsupportedVersion { .. })
+        ));
+    }
+}
@@ -1,5 +1,6 @@
 use super::config::LevelConfigAsset;
 use crate::core::GameState;
+use crate::sprite::sprite_mgr::SpriteManager;
 use bevy::asset::LoadState;
 use bevy::prelude::*;
 
@@ -8,6 +9,7 @@ use bevy::prelude::*;
 pub struct LevelLoader {
     pub current_level: Option<String>,
     pub loading: bool,
+    pub ready: bool, // 关卡数据已加载，等待精灵图集加载完成后进入游戏
     pub level_data: Handle<LevelConfigAsset>,
 }
 
@@ -33,7 +35,7 @@ pub fn load_level_system(
         match load_state {
             Some(LoadState::Loaded) => {
                 info!("Loaded level {}", level_name);
-                game_state.set(GameState::Playing);
+                level_loader.ready = true;
             }
             Some(LoadState::Failed(err)) => {
                 error!("Failed to load level {}: {:?}", level_name, err);
@@ -46,3 +48,16 @@ pub fn load_level_system(
         level_loader.current_level = None;
     }
 }
+
+/// 关卡数据和精灵图集都加载完成后进入游戏，避免卡片和实体停留在占位图
+pub fn finish_level_loading_system(
+    asset_server: Res<AssetServer>,
+    sprite_manager: Res<SpriteManager>,
+    mut level_loader: ResMut<LevelLoader>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if level_loader.ready && sprite_manager.is_ready(&asset_server) {
+        level_loader.ready = false;
+        game_state.set(GameState::Playing);
+    }
+}
@@ -3,5 +3,6 @@
 //! 负责加载和管理游戏关卡
 
 pub mod config;
+pub mod food_chain;
 pub mod loader;
 // pub mod systems;
\ No newline at end of file
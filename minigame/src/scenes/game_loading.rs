@@ -9,7 +9,6 @@ pub fn on_level_config_load_event(
     mut loader: ResMut<LevelLoader>,
     mut events: EventReader<AssetEvent<LevelConfigAsset>>,
     // level_data: Res<Assets<LevelConfigAsset>>,
-    mut game_state: ResMut<NextState<GameState>>,
 ) {
     for event in events.read() {
         info!("Got level data event: {:?}", event);
@@ -18,7 +17,7 @@ pub fn on_level_config_load_event(
         // 这样写更简洁，效果是一样的
         if event.is_added(loader.level_data.id()) {
             loader.loading = false;
-            game_state.set(GameState::Playing);
+            loader.ready = true;
             break;
         }
     }
@@ -34,6 +33,7 @@ pub fn on_level_config_load_failed_event(
         info!("Got level data event: {:?}", event);
         if event.id.eq(&loader.level_data.id()) {
             loader.loading = false;
+            loader.ready = false;
 
             game_state.set(GameState::MainMenu);
             break;
@@ -5,7 +5,13 @@ use crate::{
         HexGridConfig,
         camera::CameraController,
         components::Player,
+        economy::Economy,
+        environment::Environment,
+        fog::FogOfWar,
+        grid::MAX_MAP_SIZE,
         hex_grid::{HexMapPosition, SpatialPartition},
+        metabolism::EnergyLedger,
+        nutrient::Nutrients,
     },
     level::{config::LevelConfigAsset, loader::*},
 };
@@ -35,14 +41,57 @@ pub fn setup_game_scene(
     info!("Setup game scene");
 
     let cfg = level_data.get(&loader.level_data).unwrap();
-    let config = HexGridConfig::new(50.0, cfg.size.x as usize, cfg.size.y as usize, 0.0);
-    let partition = SpatialPartition::new(config.clone());
-    let center = partition.grid_to_world(&cfg.startup_camera_pos.unwrap_or_default());
+    let size = cfg.size.min(UVec2::splat(MAX_MAP_SIZE as u32));
+    if size != cfg.size {
+        warn!(
+            "level size {:?} exceeds max map size {}, clamped",
+            cfg.size, MAX_MAP_SIZE
+        );
+    }
+    let config = HexGridConfig::from_level(cfg.hex_size, cfg.orientation, size);
+    let mut partition = SpatialPartition::new(config.clone());
+    for pos in cfg.obstacles.iter() {
+        let pos = HexMapPosition::from(config.level_to_grid(*pos));
+        if partition.is_valid_position(&pos) {
+            partition.set_obstacle(&pos, true);
+        } else {
+            warn!("obstacle {:?} is outside the map, skipped", pos);
+        }
+    }
+    let start = config.level_to_grid(cfg.startup_camera_pos.unwrap_or_default());
+    let center = partition.grid_to_world(&start);
+
+    // 战争迷雾，初始相机位置周围永久揭示
+    match cfg.fog_of_war.clone() {
+        Some(fog) => {
+            partition.enable_fog();
+            partition.reveal(&HexMapPosition::from(start), fog.start_radius);
+            commands.insert_resource(FogOfWar::new(fog));
+        }
+        None => commands.remove_resource::<FogOfWar>(),
+    }
+
+    // 养分循环，所有地块从基础肥力开始
+    match cfg.nutrients.clone() {
+        Some(nutrients) => {
+            partition.enable_fertility(nutrients.base_fertility);
+            commands.insert_resource(Nutrients::new(nutrients));
+        }
+        None => commands.remove_resource::<Nutrients>(),
+    }
+
+    // 季节、昼夜和天气
+    match cfg.environment.clone() {
+        Some(environment) => commands.insert_resource(Environment::new(environment)),
+        None => commands.remove_resource::<Environment>(),
+    }
 
     commands.insert_resource(config);
     commands.insert_resource(partition);
     commands.insert_resource(LevelGold(cfg.init_gold));
+    commands.insert_resource(Economy::new(cfg.economy.clone()));
     commands.insert_resource(LevelElapsed(0.0));
+    commands.insert_resource(EnergyLedger::default());
 
     // 摄像机
     commands.spawn((
@@ -1,18 +1,48 @@
 use bevy::{asset::AssetLoadFailedEvent, prelude::*};
-use bevy_behave::prelude::BehaveCtx;
 
 use crate::{
     ai::*,
     core::{
-        GameState,
-        entities::{spawn_entities_system, spawn_satiety_pbar_onadd},
-        render_grid_system, setup_grid,
+        GameState, animate_actor_movement_system, check_frame_rate_system,
+        economy::{EcologyEvent, Economy, detect_extinction_system, economy_system},
+        entities::{
+            SpawnEntityEvent, spawn_entities_system, spawn_entity_event_system,
+            spawn_satiety_pbar_onadd,
+        },
+        environment::{Environment, environment_system, sync_environment_render_system},
+        fog::{
+            FogOfWar, hide_fogged_animals_system, on_remove_fog_revealer, sync_fog_cells_system,
+            update_fog_revealers_system,
+        },
+        grid::upload_cell_states_system,
+        interaction::map_cell_click_system,
+        lod::{
+            schedule_sleeping_actors_system, settle_sleeping_actors_system,
+            sleep_static_entities_system, update_active_area_system,
+        },
+        metabolism::{
+            EnergyLedger, animal_reproduction_system, audit_energy_system, metabolism_system,
+            on_add_metabolism, on_remove_corpse, on_remove_metabolism, starvation_system,
+            wither_system,
+        },
+        movement_system,
+        nutrient::{
+            CorpseEvent, Nutrients, decomposer_system, plant_seeding_system, soil_system,
+            spawn_corpse_system, spore_system,
+        },
+        player_action::{CardActionEvent, apply_card_action_system},
+        render_grid_system, update_field_of_view_system,
     },
     level::{
         config::{LevelConfigAsset, LevelConfigAssetLoader},
-        loader::load_level_system,
+        food_chain::{FoodChain, FoodChainLoader},
+        loader::{finish_level_loading_system, load_level_system},
     },
     scenes::{game_loading::*, *},
+    sprite::animation::{
+        advance_sprite_animation_system, despawn_death_animation_system, growth_system,
+        select_actor_clip_system, select_plant_clip_system,
+    },
     ui::spawn_card_ui,
 };
 
@@ -46,6 +76,8 @@ impl Plugin for SceneSelectorPlugin {
 
         app.init_asset::<LevelConfigAsset>()
             .init_asset_loader::<LevelConfigAssetLoader>()
+            .init_asset::<FoodChain>()
+            .init_asset_loader::<FoodChainLoader>()
             .add_systems(OnEnter(GameState::MainMenu), setup_main_menu)
             .add_systems(
                 Update,
@@ -59,14 +91,15 @@ impl Plugin for SceneSelectorPlugin {
                     on_level_config_load_event.run_if(on_event::<AssetEvent<LevelConfigAsset>>),
                     on_level_config_load_failed_event
                         .run_if(on_event::<AssetLoadFailedEvent<LevelConfigAsset>>),
+                    finish_level_loading_system,
                 )
+                    .chain()
                     .in_set(SceneSystemSet::LoadingSystem),
             )
             .add_systems(
                 OnEnter(GameState::Playing),
                 (
                     setup_game_scene,
-                    setup_grid,
                     render_grid_system,
                     // pre_spawn_entities_system,
                     spawn_entities_system,
@@ -79,27 +112,133 @@ impl Plugin for SceneSelectorPlugin {
             .add_systems(
                 FixedUpdate,
                 (
+                    schedule_sleeping_actors_system,
+                    update_field_of_view_system,
                     udpate_board_state_system,
+                    poll_path_tasks_system,
+                    sync_dstar_planners_system,
+                    flee_action_system,
                     idle_action_system,
                     forage_action_system,
+                    movement_system,
+                    metabolism_system,
+                    starvation_system,
+                    wither_system,
+                    animal_reproduction_system,
+                    dispatch_path_requests_system,
+                    settle_sleeping_actors_system,
+                )
+                    .chain()
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            .add_systems(
+                FixedUpdate,
+                growth_system.in_set(SceneSystemSet::GameSystems),
+            )
+            // 环境时间在AI决策之前推进，本帧的视野、移动和代谢都使用新的环境状态
+            .add_systems(
+                FixedUpdate,
+                environment_system
+                    .before(schedule_sleeping_actors_system)
+                    .run_if(resource_exists::<Environment>)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            .add_systems(
+                Update,
+                sync_environment_render_system
+                    .before(upload_cell_states_system)
+                    .run_if(resource_exists::<Environment>)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            // 养分循环，尸体在生物死亡的同一帧生成，种子和真菌交给实体生成系统
+            .add_event::<CorpseEvent>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    decomposer_system,
+                    soil_system,
+                    plant_seeding_system,
+                    spore_system,
+                )
+                    .chain()
+                    .before(growth_system)
+                    .run_if(resource_exists::<Nutrients>)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            .add_systems(
+                Update,
+                spawn_corpse_system
+                    .run_if(resource_exists::<Nutrients>)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            .add_systems(
+                Update,
+                (
+                    render_gizmos,
+                    animate_actor_movement_system,
+                    check_frame_rate_system,
+                    audit_energy_system.run_if(resource_exists::<EnergyLedger>),
+                )
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            // 战争迷雾，迷雾变化需要在上传地块状态纹理之前写入
+            .add_systems(
+                Update,
+                (
+                    update_fog_revealers_system,
+                    sync_fog_cells_system,
+                    hide_fogged_animals_system,
+                )
+                    .chain()
+                    .before(upload_cell_states_system)
+                    .run_if(resource_exists::<FogOfWar>)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            .add_observer(on_remove_fog_revealer)
+            // 能量账目，实体生成和移除时记录带入和带走的能量
+            .add_observer(on_add_metabolism)
+            .add_observer(on_remove_metabolism)
+            .add_observer(on_remove_corpse)
+            // 玩家操作，投放的实体在同一帧生成
+            .add_event::<CardActionEvent>()
+            .add_event::<SpawnEntityEvent>()
+            .add_systems(
+                Update,
+                (apply_card_action_system, spawn_entity_event_system)
+                    .chain()
+                    .after(map_cell_click_system)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            // 金币收入，结算玩家操作和AI产生的生态事件
+            .add_event::<EcologyEvent>()
+            .add_systems(
+                Update,
+                (detect_extinction_system, economy_system)
+                    .chain()
+                    .after(spawn_entity_event_system)
+                    .run_if(resource_exists::<Economy>)
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            // 视口外实体休眠
+            .add_systems(
+                Update,
+                (update_active_area_system, sleep_static_entities_system)
+                    .chain()
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            // 精灵帧动画
+            .add_systems(
+                Update,
+                (
+                    (select_actor_clip_system, select_plant_clip_system),
+                    advance_sprite_animation_system,
+                    despawn_death_animation_system,
                 )
                     .chain()
                     .in_set(SceneSystemSet::GameSystems),
             )
-            .add_systems(Update, render_gizmos.in_set(SceneSystemSet::GameSystems))
             // .add_observer(onadd_idle_action)
-            // .add_observer(on_new_behaviour)
             // 退出Playing状态的系统注册
             .add_systems(OnExit(GameState::Playing), despawn_scene);
     }
 }
-
-#[allow(unused)]
-fn on_new_behaviour(
-    trigger: Trigger<OnAdd, BehaveCtx>,
-    q: Query<(Entity, Option<&Name>, &BehaveCtx)>,
-) {
-    if let Ok((entity, name, ctx)) = q.get(trigger.target()) {
-        info!("New behaviour spawned {entity} {ctx} = {name:?}");
-    }
-}
@@ -0,0 +1,223 @@
+//! 精灵帧动画
+//!
+//! 动画片段定义在图集配置sprite_sheet.atlas中，动物根据ActorState选择片段，植物根据生长阶段显示对应帧。
+//! 没有配置片段的精灵保持静态图片。
+
+use bevy::prelude::*;
+
+use super::sprite_mgr::SpriteManager;
+use crate::ai::{ActorState, AnimalActorBoard};
+use crate::core::components::{GrowthStage, MoveTo};
+use crate::core::environment::Environment;
+use crate::core::hex_grid::SpatialPartition;
+use crate::core::lod::Sleeping;
+use crate::core::nutrient::Nutrients;
+
+pub const CLIP_IDLE: &str = "idle";
+pub const CLIP_WALK: &str = "walk";
+pub const CLIP_EAT: &str = "eat";
+pub const CLIP_FLEE: &str = "flee";
+pub const CLIP_DIE: &str = "die";
+pub const CLIP_GROW: &str = "grow";
+
+/// 精灵动画播放状态
+#[derive(Component, Debug, Clone)]
+pub struct SpriteAnimation {
+    pub sprite: String, // 图集配置中的精灵名称
+    pub clip: String,   // 当前播放的片段
+    pub frame: usize,   // 片段内的帧序号
+    pub paused: bool,   // 暂停时由外部直接指定帧，例如植物生长阶段
+    pub finished: bool, // 非循环片段是否已经播放完
+    timer: Timer,
+}
+
+impl SpriteAnimation {
+    pub fn new(sprite: impl Into<String>) -> Self {
+        Self {
+            sprite: sprite.into(),
+            clip: CLIP_IDLE.to_string(),
+            frame: 0,
+            paused: false,
+            finished: false,
+            timer: Timer::default(),
+        }
+    }
+
+    /// 切换到clip片段，正在播放的非循环片段（例如eat）播放完之前不会被打断
+    pub fn play(&mut self, clip: &str) {
+        if self.clip == clip || (self.is_one_shot() && !self.finished) {
+            return;
+        }
+        self.play_now(clip);
+    }
+
+    /// 立即切换到clip片段
+    pub fn play_now(&mut self, clip: &str) {
+        self.clip = clip.to_string();
+        self.frame = 0;
+        self.paused = false;
+        self.finished = false;
+        self.timer.reset();
+    }
+
+    /// 停在clip片段的第frame帧
+    pub fn show_frame(&mut self, clip: &str, frame: usize) {
+        self.clip = clip.to_string();
+        self.frame = frame;
+        self.paused = true;
+    }
+
+    fn is_one_shot(&self) -> bool {
+        self.clip == CLIP_EAT || self.clip == CLIP_DIE
+    }
+}
+
+/// 死亡动画的替身，die片段播放完后销毁
+#[derive(Component, Debug, Clone)]
+pub struct DeathAnimation {
+    timer: Timer,
+}
+
+/// 生物死亡时逻辑实体立即销毁，在原位置生成一个播放die片段的替身，
+/// 不影响同一帧的模拟。没有die片段的精灵直接消失
+pub fn spawn_death_animation(
+    commands: &mut Commands,
+    sprite_manager: &SpriteManager,
+    animation: &SpriteAnimation,
+    sprite: &Sprite,
+    transform: &Transform,
+    parent: &ChildOf,
+) {
+    let Some(clip) = sprite_manager.clip(&animation.sprite, CLIP_DIE) else {
+        return;
+    };
+    // 按片段时长销毁，循环播放的die片段也只播放一遍
+    let secs = clip.frame_count() as f32 / clip.fps.max(1.0);
+    let mut dying = SpriteAnimation::new(animation.sprite.clone());
+    dying.play_now(CLIP_DIE);
+    commands.spawn((
+        DeathAnimation {
+            timer: Timer::from_seconds(secs, TimerMode::Once),
+        },
+        dying,
+        sprite.clone(),
+        *transform,
+        ChildOf(parent.parent()),
+    ));
+}
+
+/// die片段播放完后销毁替身
+pub fn despawn_death_animation_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut DeathAnimation)>,
+    time: Res<Time>,
+) {
+    for (entity, mut death) in query.iter_mut() {
+        if death.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// 根据动物的行为状态选择动画片段
+pub fn select_actor_clip_system(
+    mut query: Query<(&AnimalActorBoard, Has<MoveTo>, &mut SpriteAnimation)>,
+) {
+    for (board, moving, mut animation) in query.iter_mut() {
+        let clip = match board.state {
+            ActorState::Flee => CLIP_FLEE,
+            _ if moving => CLIP_WALK,
+            _ => CLIP_IDLE,
+        };
+        animation.play(clip);
+    }
+}
+
+/// 植物按生长阶段显示grow片段中的对应帧，阶段数与帧数不同时按比例换算，没有grow片段的植物不变
+pub fn select_plant_clip_system(
+    mut query: Query<(&GrowthStage, &mut SpriteAnimation), Changed<GrowthStage>>,
+    sprite_manager: Res<SpriteManager>,
+) {
+    for (growth, mut animation) in query.iter_mut() {
+        let Some(clip) = sprite_manager.clip(&animation.sprite, CLIP_GROW) else {
+            continue;
+        };
+        animation.show_frame(CLIP_GROW, growth_frame(growth, clip.frame_count()));
+    }
+}
+
+// 第0阶段对应第一帧，成熟对应最后一帧
+fn growth_frame(growth: &GrowthStage, frame_count: usize) -> usize {
+    let last = frame_count.saturating_sub(1);
+    if growth.max_stage == 0 {
+        return last;
+    }
+    growth.stage.min(growth.max_stage) * last / growth.max_stage
+}
+
+/// 推进动画帧并写入TextureAtlas::index
+pub fn advance_sprite_animation_system(
+    mut query: Query<(&mut SpriteAnimation, &mut Sprite), Without<Sleeping>>,
+    sprite_manager: Res<SpriteManager>,
+    time: Res<Time>,
+) {
+    for (mut animation, mut sprite) in query.iter_mut() {
+        let Some(clip) = sprite_manager.clip(&animation.sprite, &animation.clip) else {
+            continue;
+        };
+
+        if !animation.paused && !animation.finished {
+            let frame_secs = 1.0 / clip.fps;
+            if animation.timer.duration().as_secs_f32() != frame_secs {
+                animation
+                    .timer
+                    .set_duration(std::time::Duration::from_secs_f32(frame_secs));
+                animation.timer.set_mode(TimerMode::Repeating);
+            }
+            animation.timer.tick(time.delta());
+            let steps = animation.timer.times_finished_this_tick() as usize;
+            if steps > 0 {
+                let next = animation.frame + steps;
+                if next < clip.frame_count() {
+                    animation.frame = next;
+                } else if clip.looping {
+                    animation.frame = next % clip.frame_count();
+                } else {
+                    animation.frame = clip.frame_count() - 1;
+                    animation.finished = true;
+                }
+            }
+        }
+
+        let index = clip.index(animation.frame);
+        if let Some(atlas) = sprite.texture_atlas.as_mut()
+            && atlas.index != index
+        {
+            atlas.index = index;
+        }
+    }
+}
+
+/// 植物生长，开启养分循环时生长速度随地块肥力变化，开启环境系统时随季节和天气变化
+pub fn growth_system(
+    mut query: Query<(&mut GrowthStage, &Transform)>,
+    partition: Res<SpatialPartition>,
+    nutrients: Option<Res<Nutrients>>,
+    environment: Option<Res<Environment>>,
+    time: Res<Time>,
+) {
+    let season = environment.as_ref().map_or(1.0, |env| env.growth_factor());
+    for (mut growth, transform) in query.iter_mut() {
+        if growth.is_mature() {
+            continue;
+        }
+        let factor = season
+            * nutrients.as_ref().map_or(1.0, |nutrients| {
+                nutrients.growth_factor_at(&partition, transform)
+            });
+        growth.timer.tick(time.delta().mul_f32(factor));
+        if growth.timer.just_finished() {
+            growth.stage += 1;
+        }
+    }
+}
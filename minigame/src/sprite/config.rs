@@ -1,12 +1,19 @@
 use crate::core::components::EntityType;
-use bevy::prelude::*;
+use bevy::{
+    asset::{AssetLoader, LoadContext, io::Reader},
+    prelude::*,
+};
 use serde::Deserialize;
 use std::collections::HashMap;
+use thiserror::Error;
 
-/// 关卡配置
-#[derive(Debug, Deserialize, Clone, Default)]
+/// 图集配置，JSON格式，使用`.atlas`扩展名
+#[derive(Asset, TypePath, Debug, Deserialize, Clone, Default)]
 pub struct AtlasConfig {
     pub name: String,
+    // 图集图片路径，相对于配置文件所在目录，默认为`{name}.png`
+    #[serde(default)]
+    pub image: Option<String>,
     pub cell_width: u32,
     pub cell_height: u32,
     pub rows: u32,
@@ -14,6 +21,11 @@ pub struct AtlasConfig {
     pub sprites: Vec<EntitySpriteConfig>,
     #[serde(skip)]
     pub sprites_map: HashMap<String, EntitySpriteConfig>,
+    #[serde(skip)]
+    #[dependency]
+    pub texture: Handle<Image>,
+    #[serde(skip)]
+    pub layout: Handle<TextureAtlasLayout>,
 }
 
 /// 实体配置
@@ -23,14 +35,227 @@ pub struct EntitySpriteConfig {
     #[serde(rename = "type")]
     pub entity_type: Option<EntityType>,
     pub index: usize,
+    // 原图中的精灵是否朝左，移动时据此决定是否翻转
+    #[serde(default)]
+    pub faces_left: bool,
+    // 动画片段，key为片段名称，例如idle、walk、eat、flee、die、grow
+    #[serde(default)]
+    pub clips: HashMap<String, AnimationClip>,
+}
+
+/// 动画片段，播放图集中[first, last]范围内的帧
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AnimationClip {
+    pub first: usize,
+    pub last: usize,
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+    #[serde(default = "default_looping")]
+    pub looping: bool,
+}
+
+fn default_fps() -> f32 {
+    8.0
+}
+
+fn default_looping() -> bool {
+    true
+}
+
+impl AnimationClip {
+    pub fn frame_count(&self) -> usize {
+        self.last - self.first + 1
+    }
+
+    /// 片段中第frame帧在图集中的索引
+    pub fn index(&self, frame: usize) -> usize {
+        self.first + frame.min(self.last - self.first)
+    }
+}
+
+/// 图集配置校验错误
+#[derive(Debug, Error, PartialEq)]
+pub enum AtlasConfigError {
+    #[error("sprite `{sprite}`: index {index} out of atlas range {frames}")]
+    IndexOutOfRange {
+        sprite: String,
+        index: usize,
+        frames: usize,
+    },
+    #[error("sprite `{sprite}` clip `{clip}`: first frame {first} is after last frame {last}")]
+    InvalidRange {
+        sprite: String,
+        clip: String,
+        first: usize,
+        last: usize,
+    },
+    #[error("sprite `{sprite}` clip `{clip}`: frame {last} out of atlas range {frames}")]
+    FrameOutOfRange {
+        sprite: String,
+        clip: String,
+        last: usize,
+        frames: usize,
+    },
+    #[error("sprite `{sprite}` clip `{clip}`: fps must be positive, got {fps}")]
+    InvalidFps {
+        sprite: String,
+        clip: String,
+        fps: f32,
+    },
 }
 
 impl AtlasConfig {
-    pub fn from(json: &str) -> Self {
-        let mut config = serde_json::from_str::<Self>(json).unwrap();
+    pub fn from_json(json: &[u8]) -> Result<Self, serde_json::Error> {
+        let mut config = serde_json::from_slice::<Self>(json)?;
+        // 去掉校验失败的动画片段，对应的实体退化为静态图片
+        for error in config.validate() {
+            error!("AtlasConfig: {error}");
+            if let AtlasConfigError::InvalidRange { sprite, clip, .. }
+            | AtlasConfigError::FrameOutOfRange { sprite, clip, .. }
+            | AtlasConfigError::InvalidFps { sprite, clip, .. } = error
+                && let Some(cfg) = config.sprites.iter_mut().find(|s| s.name == sprite)
+            {
+                cfg.clips.remove(&clip);
+            }
+        }
         for cfg in config.sprites.iter() {
             config.sprites_map.insert(cfg.name.clone(), cfg.clone());
         }
-        return config;
+        Ok(config)
+    }
+
+    /// 图集中的总帧数
+    pub fn frame_count(&self) -> usize {
+        (self.rows * self.columns) as usize
+    }
+
+    /// 校验精灵索引和动画片段定义，返回所有错误
+    pub fn validate(&self) -> Vec<AtlasConfigError> {
+        let frames = self.frame_count();
+        let mut errors = Vec::new();
+        for sprite in self.sprites.iter() {
+            if sprite.index >= frames {
+                errors.push(AtlasConfigError::IndexOutOfRange {
+                    sprite: sprite.name.clone(),
+                    index: sprite.index,
+                    frames,
+                });
+            }
+            for (name, clip) in sprite.clips.iter() {
+                let (sprite, clip_name) = (sprite.name.clone(), name.clone());
+                if clip.first > clip.last {
+                    errors.push(AtlasConfigError::InvalidRange {
+                        sprite,
+                        clip: clip_name,
+                        first: clip.first,
+                        last: clip.last,
+                    });
+                } else if clip.last >= frames {
+                    errors.push(AtlasConfigError::FrameOutOfRange {
+                        sprite,
+                        clip: clip_name,
+                        last: clip.last,
+                        frames,
+                    });
+                } else if clip.fps.is_nan() || clip.fps <= 0.0 {
+                    errors.push(AtlasConfigError::InvalidFps {
+                        sprite,
+                        clip: clip_name,
+                        fps: clip.fps,
+                    });
+                }
+            }
+        }
+        errors
+    }
+
+    pub fn clip(&self, sprite: &str, clip: &str) -> Option<&AnimationClip> {
+        self.sprites_map.get(sprite)?.clips.get(clip)
+    }
+}
+
+#[derive(Default)]
+pub struct AtlasConfigLoader;
+
+/// 图集配置加载错误
+#[non_exhaustive]
+#[derive(Debug, Error)]
+pub enum AtlasConfigLoaderError {
+    #[error("Could not load asset: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+impl AssetLoader for AtlasConfigLoader {
+    type Asset = AtlasConfig;
+    type Settings = ();
+    type Error = AtlasConfigLoaderError;
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut config = AtlasConfig::from_json(&bytes)?;
+
+        // 图片作为依赖资源加载，修改图片同样会触发热重载
+        let image = config
+            .image
+            .clone()
+            .unwrap_or_else(|| format!("{}.png", config.name));
+        let image_path = load_context.path().with_file_name(image);
+        config.texture = load_context.load(image_path);
+
+        let layout = TextureAtlasLayout::from_grid(
+            UVec2::new(config.cell_width, config.cell_height),
+            config.columns,
+            config.rows,
+            None,
+            None,
+        );
+        config.layout = load_context.add_labeled_asset("layout".to_string(), layout);
+        Ok(config)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["atlas"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_clips_are_rejected() {
+        let json = r#"{
+            "name": "sprite_sheet", "cell_width": 64, "cell_height": 64, "rows": 2, "columns": 4,
+            "sprites": [{
+                "name": "rabbit", "type": {"type": "rabbit"}, "index": 0, "faces_left": true,
+                "clips": {
+                    "walk": {"first": 1, "last": 3, "fps": 10.0},
+                    "eat": {"first": 5, "last": 4},
+                    "flee": {"first": 6, "last": 9},
+                    "die": {"first": 6, "last": 7, "fps": 0.0, "looping": false}
+                }
+            }]
+        }"#;
+
+        let config = AtlasConfig::from_json(json.as_bytes()).unwrap();
+        assert!(config.sprites_map["rabbit"].faces_left);
+        let clips = &config.sprites_map["rabbit"].clips;
+        assert_eq!(clips.len(), 1);
+        let walk = config.clip("rabbit", "walk").unwrap();
+        assert!(walk.looping);
+        assert_eq!(walk.frame_count(), 3);
+        assert_eq!(walk.index(5), 3);
+    }
+
+    #[test]
+    fn test_malformed_json_is_an_error() {
+        assert!(AtlasConfig::from_json(br#"{"name": "sprite_sheet"}"#).is_err());
     }
 }
@@ -1,2 +1,3 @@
+pub mod animation;
 pub mod config;
 pub mod sprite_mgr;
@@ -1,70 +1,221 @@
-use super::config::AtlasConfig;
-use bevy::prelude::*;
+use super::animation::SpriteAnimation;
+use super::config::{AnimationClip, AtlasConfig, AtlasConfigLoader, EntitySpriteConfig};
+use crate::core::move_animation::MoveAnimation;
+use bevy::{
+    asset::{AssetLoadFailedEvent, RecursiveDependencyLoadState},
+    prelude::*,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+/// 找不到精灵时显示的占位颜色，足够醒目
+const PLACEHOLDER_COLOR: Color = Color::srgb(1.0, 0.0, 1.0);
+/// 还没有美术资源的精灵用固定颜色的色块代替，不算缺失
+const FALLBACK_COLORS: &[(&str, Color)] = &[
+    ("fungus", Color::srgb(0.62, 0.5, 0.68)), // 真菌，淡紫色
+    ("corpse", Color::srgb(0.36, 0.27, 0.2)), // 尸体，深褐色
+];
+
+/// 精灵缺失时显示的颜色
+fn placeholder_color(name: &str) -> Color {
+    FALLBACK_COLORS
+        .iter()
+        .find(|(sprite, _)| *sprite == name)
+        .map_or(PLACEHOLDER_COLOR, |(_, color)| *color)
+}
+
+/// 精灵所在的图集以及它的配置
+#[derive(Debug, Clone)]
+struct SpriteEntry {
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+    config: EntitySpriteConfig,
+}
 
 #[derive(Resource)]
 pub struct SpriteManager {
-    pub texture: Handle<Image>,
-    pub texture_atlas_layouts: Handle<TextureAtlasLayout>,
-    pub config: AtlasConfig,
+    pub atlases: Vec<Handle<AtlasConfig>>,
     pub stomach_icon: Handle<Image>,
+    sprites: HashMap<String, SpriteEntry>, // 所有已加载图集中的精灵，按名称索引
+    indexed: HashSet<AssetId<AtlasConfig>>, // 已经建立索引的图集
+    missing: Mutex<HashSet<String>>,       // 已经警告过的缺失精灵，每个名称只警告一次
 }
 
-pub struct SpriteManagerPlugin;
+pub struct SpriteManagerPlugin {
+    pub atlases: Vec<String>, // 图集配置文件路径，相对于assets目录
+}
+
+impl Default for SpriteManagerPlugin {
+    fn default() -> Self {
+        Self {
+            atlases: vec!["textures/sprite_sheet.atlas".to_string()],
+        }
+    }
+}
 
 impl Plugin for SpriteManagerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_sprite_res);
+        app.init_asset::<AtlasConfig>()
+            .init_asset_loader::<AtlasConfigLoader>();
+
+        info!("SpriteManager Plugin Loaded");
+        let asset_server = app.world().resource::<AssetServer>().clone();
+        app.insert_resource(SpriteManager {
+            atlases: self
+                .atlases
+                .iter()
+                .map(|path| asset_server.load(path.clone()))
+                .collect(),
+            stomach_icon: asset_server.load("textures/stomach_icon.png"),
+            sprites: HashMap::new(),
+            indexed: HashSet::new(),
+            missing: Mutex::new(HashSet::new()),
+        })
+        .add_systems(
+            Update,
+            (
+                sync_atlas_system.run_if(on_event::<AssetEvent<AtlasConfig>>),
+                on_atlas_load_failed_event.run_if(on_event::<AssetLoadFailedEvent<AtlasConfig>>),
+            ),
+        );
     }
 }
 
-pub fn setup_sprite_res(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
+/// 图集加载完成或热重载后重建精灵索引，并刷新场景中已有的精灵
+pub fn sync_atlas_system(
+    mut events: EventReader<AssetEvent<AtlasConfig>>,
+    atlases: Res<Assets<AtlasConfig>>,
+    mut sprite_manager: ResMut<SpriteManager>,
+    mut query: Query<(&SpriteAnimation, &mut Sprite, Option<&mut MoveAnimation>)>,
 ) {
-    info!("SpriteManager Plugin Loaded");
-    let atlas_name = "sprite_sheet";
-    let image_path = format!("textures/{}.png", atlas_name);
-
-    let cfg_path = format!("assets/textures/{}.json", atlas_name);
+    let mut changed = false;
+    for event in events.read() {
+        if let AssetEvent::LoadedWithDependencies { id } | AssetEvent::Modified { id } = event
+            && let Some(atlas) = atlases.get(*id)
+        {
+            info!("SpriteManager: atlas {} loaded", atlas.name);
+            changed = true;
+        }
+    }
+    if !changed {
+        return;
+    }
 
-    let config = match std::fs::read_to_string(&cfg_path) {
-        Ok(cfg_str) => AtlasConfig::from(cfg_str.as_ref()),
-        Err(err) => {
-            error!("SpriteManager: {} {:?}", cfg_path, err);
-            AtlasConfig::default()
+    sprite_manager.rebuild(&atlases);
+    for (animation, mut sprite, move_animation) in query.iter_mut() {
+        sprite_manager.apply(&animation.sprite, &mut sprite);
+        if let Some(mut move_animation) = move_animation {
+            move_animation.faces_left = sprite_manager.faces_left(&animation.sprite);
         }
-    };
-
-    let texture = asset_server.load(image_path);
-    let layout = TextureAtlasLayout::from_grid(
-        UVec2::new(config.cell_width, config.cell_height),
-        config.columns,
-        config.rows,
-        None,
-        None,
-    );
-
-    commands.insert_resource(SpriteManager {
-        texture: texture,
-        texture_atlas_layouts: texture_atlas_layouts.add(layout),
-        config,
-        stomach_icon: asset_server.load("textures/stomach_icon.png"),
-    });
+    }
+}
+
+pub fn on_atlas_load_failed_event(mut events: EventReader<AssetLoadFailedEvent<AtlasConfig>>) {
+    for event in events.read() {
+        error!("SpriteManager: {} {}", event.path, event.error);
+    }
 }
 
 impl SpriteManager {
+    /// 按图集注册顺序重建索引，同名精灵以先注册的图集为准
+    fn rebuild(&mut self, atlases: &Assets<AtlasConfig>) {
+        self.sprites.clear();
+        self.indexed.clear();
+        for (id, atlas) in self
+            .atlases
+            .iter()
+            .filter_map(|handle| Some((handle.id(), atlases.get(handle)?)))
+        {
+            self.indexed.insert(id);
+            for cfg in atlas.sprites.iter() {
+                if self.sprites.contains_key(&cfg.name) {
+                    warn!(
+                        "SpriteManager: duplicated sprite {} in atlas {}",
+                        cfg.name, atlas.name
+                    );
+                    continue;
+                }
+                self.sprites.insert(
+                    cfg.name.clone(),
+                    SpriteEntry {
+                        texture: atlas.texture.clone(),
+                        layout: atlas.layout.clone(),
+                        config: cfg.clone(),
+                    },
+                );
+            }
+        }
+        // 重新加载后缺失的精灵可能已经补上，允许再次警告
+        self.missing.lock().unwrap().clear();
+    }
+
+    /// 用最新的图集数据更新sprite，保留尺寸和翻转等设置
+    fn apply(&self, name: &str, sprite: &mut Sprite) {
+        let Some(entry) = self.sprites.get(name) else {
+            self.warn_missing(name);
+            return;
+        };
+        if sprite.texture_atlas.is_none() {
+            // 之前显示的是占位图
+            sprite.color = Color::WHITE;
+        }
+        sprite.image = entry.texture.clone();
+        sprite.texture_atlas = Some(TextureAtlas {
+            layout: entry.layout.clone(),
+            index: entry.config.index,
+        });
+    }
+
+    fn warn_missing(&self, name: &str) {
+        let has_fallback = FALLBACK_COLORS.iter().any(|(sprite, _)| *sprite == name);
+        if !has_fallback && self.missing.lock().unwrap().insert(name.to_string()) {
+            warn!(
+                "SpriteManager: sprite {} not found, using placeholder",
+                name
+            );
+        }
+    }
+
+    /// 所有图集都已加载并建立索引，加载失败的图集不再等待，对应精灵显示占位图
+    pub fn is_ready(&self, asset_server: &AssetServer) -> bool {
+        self.atlases.iter().all(|handle| {
+            match asset_server.get_recursive_dependency_load_state(handle) {
+                Some(RecursiveDependencyLoadState::Loaded) => self.indexed.contains(&handle.id()),
+                Some(RecursiveDependencyLoadState::Failed(_)) => true,
+                _ => false,
+            }
+        })
+    }
+
+    pub fn clip(&self, sprite: &str, clip: &str) -> Option<&AnimationClip> {
+        self.sprites.get(sprite)?.config.clips.get(clip)
+    }
+
+    /// 原图中的精灵是否朝左，找不到精灵时按朝右处理
+    pub fn faces_left(&self, sprite: &str) -> bool {
+        self.sprites
+            .get(sprite)
+            .is_some_and(|entry| entry.config.faces_left)
+    }
+
     pub fn get_sprite_by_name(&self, name: &str) -> Sprite {
         return self.get_sprite_by_name_and_size(name, Vec2::new(64.0, 64.0));
     }
 
     pub fn get_sprite_by_name_and_size(&self, name: &str, size: Vec2) -> Sprite {
-        let cfg = self.config.sprites_map.get(name).unwrap();
+        let Some(entry) = self.sprites.get(name) else {
+            self.warn_missing(name);
+            return Sprite {
+                color: placeholder_color(name),
+                custom_size: Some(size),
+                ..Default::default()
+            };
+        };
         Sprite {
-            image: self.texture.clone(),
+            image: entry.texture.clone(),
             texture_atlas: Some(TextureAtlas {
-                layout: self.texture_atlas_layouts.clone(),
-                index: cfg.index,
+                layout: entry.layout.clone(),
+                index: entry.config.index,
             }),
             custom_size: Some(size),
             ..Default::default()
@@ -72,13 +223,30 @@ impl SpriteManager {
     }
 
     pub fn create_image_node_by_name(&self, name: &str) -> ImageNode {
-        let cfg = self.config.sprites_map.get(name).unwrap();
+        let Some(entry) = self.sprites.get(name) else {
+            self.warn_missing(name);
+            return ImageNode::solid_color(placeholder_color(name));
+        };
         ImageNode::from_atlas_image(
-            self.texture.clone(),
+            entry.texture.clone(),
             TextureAtlas {
-                layout: self.texture_atlas_layouts.clone(),
-                index: cfg.index,
+                layout: entry.layout.clone(),
+                index: entry.config.index,
             },
         )
     }
 }
+
+#[cfg(test)]
+impl SpriteManager {
+    /// 没有加载任何图集的精灵管理器，所有精灵都显示占位图
+    pub fn empty() -> Self {
+        Self {
+            atlases: Vec::new(),
+            stomach_icon: Handle::default(),
+            sprites: HashMap::new(),
+            indexed: HashSet::new(),
+            missing: Mutex::new(HashSet::new()),
+        }
+    }
+}
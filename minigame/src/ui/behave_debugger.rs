@@ -0,0 +1,383 @@
+//! 行为树调试面板
+//!
+//! 点击地图选中动物后，用egui显示其行为树的实时运行状态、AnimalActorBoard数据以及状态切换日志
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_behave::prelude::{Behave, BehaveCtx, BehaveStatusReport, Tree};
+use bevy_egui::{EguiContexts, EguiPrimaryContextPass, egui};
+
+use crate::ai::{ActorState, AnimalActorBoard, get_ai_behave_tree};
+use crate::core::hex_grid::HexMapPosition;
+use crate::core::interaction::SpecialMapCellHolder;
+use crate::core::{GameState, Metabolism};
+use crate::scenes::scene_selector::SceneSystemSet;
+
+/// 日志最多保留的条数
+const MAX_LOG_ENTRIES: usize = 200;
+
+/// 行为树节点在上一次tick后的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStatus {
+    None,
+    Running,
+    Success,
+    Failure,
+}
+
+/// 展开后的一个行为树节点
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeNodeLine {
+    pub depth: usize,
+    pub label: String,
+    pub status: NodeStatus,
+}
+
+#[derive(Debug, Clone)]
+pub struct BehaveLogEntry {
+    pub time: f32,
+    pub message: String,
+}
+
+/// 行为树调试器资源，记录当前观察的动物和状态切换日志
+#[derive(Resource, Default)]
+pub struct BehaveDebugger {
+    pub target: Option<Entity>,
+    pub selected_cell: Option<HexMapPosition>,
+    pub last_state: Option<ActorState>,
+    pub nodes: Vec<TreeNodeLine>,
+    pub log: VecDeque<BehaveLogEntry>,
+}
+
+impl BehaveDebugger {
+    pub fn set_target(&mut self, target: Option<Entity>) {
+        if self.target != target {
+            self.target = target;
+            self.last_state = None;
+            self.nodes.clear();
+            self.log.clear();
+        }
+    }
+
+    /// 更新与任务同名的节点状态
+    pub fn set_node_status(&mut self, name: &str, status: NodeStatus) {
+        for node in self.nodes.iter_mut().filter(|node| node.label == name) {
+            node.status = status;
+        }
+    }
+
+    pub fn push_log(&mut self, time: f32, message: String) {
+        if self.log.len() >= MAX_LOG_ENTRIES {
+            self.log.pop_front();
+        }
+        self.log.push_back(BehaveLogEntry { time, message });
+    }
+}
+
+pub struct BehaveDebuggerPlugin;
+
+impl Plugin for BehaveDebuggerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BehaveDebugger>()
+            .add_observer(on_behave_task_spawned)
+            .add_observer(on_behave_status_report)
+            .add_systems(
+                Update,
+                (
+                    select_debug_target_system.run_if(resource_changed::<SpecialMapCellHolder>),
+                    record_actor_state_system,
+                )
+                    .chain()
+                    .in_set(SceneSystemSet::GameSystems),
+            )
+            .add_systems(
+                EguiPrimaryContextPass,
+                behave_debugger_ui_system.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnExit(GameState::Playing), reset_debugger);
+    }
+}
+
+/// 按前序遍历展开行为树的结构，节点状态由任务的运行事件更新
+pub fn flatten_tree(tree: &Tree<Behave>) -> Vec<TreeNodeLine> {
+    tree.root()
+        .descendants()
+        .map(|node| TreeNodeLine {
+            depth: node.ancestors().count(),
+            label: node_label(node.value()),
+            status: NodeStatus::None,
+        })
+        .collect()
+}
+
+fn node_label(behave: &Behave) -> String {
+    match behave {
+        // 任务节点使用spawn_named时的名字，与任务实体的Name一致
+        Behave::DynamicEntity { name, .. } => name.to_string(),
+        Behave::Wait(secs) => format!("Wait({secs}s)"),
+        Behave::Sequence => "Sequence".to_string(),
+        Behave::Fallback => "Fallback".to_string(),
+        Behave::Forever => "Forever".to_string(),
+        Behave::While => "While".to_string(),
+        Behave::IfThen => "IfThen".to_string(),
+        Behave::Invert => "Invert".to_string(),
+        Behave::AlwaysSucceed => "AlwaysSucceed".to_string(),
+        Behave::AlwaysFail => "AlwaysFail".to_string(),
+        _ => "TriggerReq".to_string(),
+    }
+}
+
+// 子树中有节点在运行时，组合节点也显示为运行中
+fn display_status(nodes: &[TreeNodeLine], index: usize) -> NodeStatus {
+    let node = &nodes[index];
+    let running = nodes[index + 1..]
+        .iter()
+        .take_while(|child| child.depth > node.depth)
+        .any(|child| child.status == NodeStatus::Running);
+    if running {
+        NodeStatus::Running
+    } else {
+        node.status
+    }
+}
+
+// 选中地块发生变化时，将地块上的动物设置为调试对象
+fn select_debug_target_system(
+    holder: Res<SpecialMapCellHolder>,
+    mut debugger: ResMut<BehaveDebugger>,
+    actor_q: Query<(Entity, &AnimalActorBoard)>,
+) {
+    if debugger.selected_cell == holder.selected {
+        return;
+    }
+    debugger.selected_cell = holder.selected;
+
+    // 取消选中地块时保留当前的调试对象，方便继续观察
+    let Some(pos) = holder.selected else {
+        return;
+    };
+
+    if let Some((entity, board)) = actor_q.iter().find(|(_, board)| board.current_pos.eq(&pos))
+        && debugger.target != Some(entity)
+    {
+        debugger.set_target(Some(entity));
+        // 与生成动物时使用同一份行为树定义
+        debugger.nodes = flatten_tree(&get_ai_behave_tree(board.entity_type.clone()));
+    }
+}
+
+// 记录调试对象ActorState的切换
+fn record_actor_state_system(
+    mut debugger: ResMut<BehaveDebugger>,
+    actor_q: Query<&AnimalActorBoard>,
+    time: Res<Time>,
+) {
+    let Some(target) = debugger.target else {
+        return;
+    };
+
+    let Ok(board) = actor_q.get(target) else {
+        // 调试对象已经被销毁
+        debugger.push_log(time.elapsed_secs(), format!("{target} despawned"));
+        debugger.target = None;
+        debugger.last_state = None;
+        return;
+    };
+
+    if debugger.last_state.as_ref() != Some(&board.state) {
+        let message = match &debugger.last_state {
+            Some(prev) => format!("state {:?} -> {:?}", prev, board.state),
+            None => format!("state {:?}", board.state),
+        };
+        debugger.push_log(time.elapsed_secs(), message);
+        debugger.last_state = Some(board.state.clone());
+    }
+}
+
+fn on_behave_task_spawned(
+    trigger: Trigger<OnAdd, BehaveCtx>,
+    q: Query<(Option<&Name>, &BehaveCtx)>,
+    mut debugger: ResMut<BehaveDebugger>,
+    time: Res<Time>,
+) {
+    if let Ok((name, ctx)) = q.get(trigger.target())
+        && debugger.target == Some(ctx.target_entity())
+    {
+        let name = name.map_or("<unnamed>", |n| n.as_str());
+        debugger.set_node_status(name, NodeStatus::Running);
+        debugger.push_log(time.elapsed_secs(), format!("start {name}"));
+    }
+}
+
+fn on_behave_status_report(
+    trigger: Trigger<BehaveStatusReport>,
+    name_q: Query<&Name>,
+    mut debugger: ResMut<BehaveDebugger>,
+    time: Res<Time>,
+) {
+    let ctx = trigger.event().ctx();
+    if debugger.target != Some(ctx.target_entity()) {
+        return;
+    }
+
+    let name = ctx
+        .task_entity()
+        .and_then(|e| name_q.get(e).ok())
+        .map_or("<trigger>", |n| n.as_str());
+    let (status, result) = match trigger.event() {
+        BehaveStatusReport::Success(_) => (NodeStatus::Success, "success"),
+        BehaveStatusReport::Failure(_) => (NodeStatus::Failure, "failure"),
+    };
+    debugger.set_node_status(name, status);
+    debugger.push_log(time.elapsed_secs(), format!("{name} -> {result}"));
+}
+
+fn status_color(status: NodeStatus) -> egui::Color32 {
+    match status {
+        NodeStatus::None => egui::Color32::GRAY,
+        NodeStatus::Running => egui::Color32::from_rgb(240, 200, 60),
+        NodeStatus::Success => egui::Color32::from_rgb(80, 200, 100),
+        NodeStatus::Failure => egui::Color32::from_rgb(230, 80, 80),
+    }
+}
+
+fn behave_debugger_ui_system(
+    mut contexts: EguiContexts,
+    mut debugger: ResMut<BehaveDebugger>,
+    actor_q: Query<(&AnimalActorBoard, Option<&Metabolism>)>,
+) {
+    let Some(target) = debugger.target else {
+        return;
+    };
+    let Ok(ctx) = contexts.ctx_mut() else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new(format!("Behave Debugger - {target}"))
+        .open(&mut open)
+        .default_width(320.0)
+        .show(ctx, |ui| {
+            let Ok((board, metabolism)) = actor_q.get(target) else {
+                ui.label("target despawned");
+                return;
+            };
+
+            egui::CollapsingHeader::new("Behave Tree")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for (index, node) in debugger.nodes.iter().enumerate() {
+                        let status = display_status(&debugger.nodes, index);
+                        ui.horizontal(|ui| {
+                            ui.add_space(node.depth as f32 * 12.0);
+                            ui.colored_label(status_color(status), &node.label);
+                        });
+                    }
+                });
+
+            egui::CollapsingHeader::new("AnimalActorBoard")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::Grid::new("actor_board_grid")
+                        .num_columns(2)
+                        .striped(true)
+                        .show(ui, |ui| {
+                            let rows = [
+                                ("entity_type", format!("{:?}", board.entity_type)),
+                                ("state", format!("{:?}", board.state)),
+                                (
+                                    "current_pos",
+                                    format!("({}, {})", board.current_pos.x, board.current_pos.y),
+                                ),
+                                ("forage_target", format!("{:?}", board.forage_target)),
+                                (
+                                    "move_target",
+                                    format!("{:?}", board.move_target.map(|p| (p.x, p.y))),
+                                ),
+                                ("path_buffer", format!("{} steps", board.path_buffer.len())),
+                                (
+                                    "energy",
+                                    metabolism.map_or("-".to_string(), |m| {
+                                        format!("{:.1}/{:.0}", m.energy, m.max)
+                                    }),
+                                ),
+                                ("idle_counter", format!("{}", board.idle_counter)),
+                                (
+                                    "move_cd",
+                                    format!(
+                                        "{:.2}/{:.2}s",
+                                        board.move_cd_timer.elapsed_secs(),
+                                        board.move_cd_timer.duration().as_secs_f32()
+                                    ),
+                                ),
+                                ("path_cost", format!("{:.2}", board.path_cost)),
+                            ];
+                            for (key, value) in rows {
+                                ui.label(key);
+                                ui.label(value);
+                                ui.end_row();
+                            }
+                        });
+                });
+
+            egui::CollapsingHeader::new("Transitions")
+                .default_open(true)
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(200.0)
+                        .stick_to_bottom(true)
+                        .auto_shrink([false, true])
+                        .show(ui, |ui| {
+                            for entry in debugger.log.iter() {
+                                ui.monospace(format!("[{:>7.2}] {}", entry.time, entry.message));
+                            }
+                        });
+                });
+        });
+
+    if !open {
+        debugger.set_target(None);
+    }
+}
+
+fn reset_debugger(mut debugger: ResMut<BehaveDebugger>) {
+    debugger.set_target(None);
+    debugger.selected_cell = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::components::EntityType;
+
+    #[test]
+    fn test_flatten_tree() {
+        let mut nodes = flatten_tree(&get_ai_behave_tree(EntityType::Rabbit));
+        let layout: Vec<(usize, &str)> = nodes
+            .iter()
+            .map(|node| (node.depth, node.label.as_str()))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![
+                (0, "Forever"),
+                (1, "Fallback"),
+                (2, "Fallback"),
+                (3, "Flee Action"),
+                (2, "Fallback"),
+                (3, "Forage Action"),
+                (2, "Fallback"),
+                (3, "Idle Action"),
+            ]
+        );
+
+        // 运行中的任务使所有祖先节点显示为运行中，兄弟子树不受影响
+        nodes[5].status = NodeStatus::Running;
+        nodes[3].status = NodeStatus::Failure;
+        assert_eq!(display_status(&nodes, 0), NodeStatus::Running);
+        assert_eq!(display_status(&nodes, 4), NodeStatus::Running);
+        assert_eq!(display_status(&nodes, 2), NodeStatus::None);
+        assert_eq!(display_status(&nodes, 3), NodeStatus::Failure);
+    }
+}
@@ -1,12 +1,22 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::platform::collections::HashMap;
 use bevy::prelude::*;
-use bevy::ui::{PositionType, Val};
+use bevy::ui::{PositionType, RelativeCursorPosition, Val};
 
+use crate::core::HexGridConfig;
 use crate::core::components::EntityType;
-use crate::level::config::LevelConfigAsset;
+use crate::core::entities::SpeciesConfig;
+use crate::core::hex_grid::{
+    EntityWithCoord, HexMapPosition, SpatialPartition, hex_distance, hex_spiral,
+};
+use crate::core::interaction::GlobalMousePosition;
+use crate::core::player_action::{CardAction, CardTarget};
+use crate::level::config::{CardConfig, EntityFoodRelations, LevelConfigAsset};
 use crate::level::loader::LevelLoader;
-use crate::scenes::GameSceneUIRoot;
 use crate::scenes::scene_selector::SceneSystemSet;
+use crate::scenes::{GameSceneUIRoot, LevelGold};
 use crate::sprite::sprite_mgr::SpriteManager;
+use crate::ui::show_error_tips;
 
 #[derive(Component)]
 struct CardUIRoot;
@@ -15,9 +25,201 @@ struct CardUIRoot;
 #[derive(Component, Default)]
 pub struct EntityCardInfo {
     pub entity_type: EntityType,
+    pub action: CardAction,
     pub cost: u32,
+    pub pending: Option<EntityWithCoord>, // 迁移卡片已选中、等待选择目的地的实体
+    pub remaining: Option<u32>,           // 剩余可用次数，None表示无限制
+    pub placement_radius: Option<i32>,    // 投放范围，None表示不限制
+    pub cooldown: f32,                    // 每次投放后此卡片的冷却（秒）
+    cooldown_left: f32,
+    cooldown_total: f32,
 }
 
+impl EntityCardInfo {
+    pub fn new(card: &CardConfig) -> Self {
+        Self {
+            entity_type: card.entity_type.clone(),
+            action: card.action,
+            cost: card.cost,
+            remaining: (card.count_limit > 0).then_some(card.count_limit),
+            placement_radius: card.placement_radius,
+            cooldown: card.cooldown,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.cooldown_left <= 0.0
+    }
+
+    pub fn is_used_up(&self) -> bool {
+        self.remaining == Some(0)
+    }
+
+    /// 开始冷却，已有更长的冷却时保持不变
+    pub fn start_cooldown(&mut self, secs: f32) {
+        if secs > self.cooldown_left {
+            self.cooldown_left = secs;
+            self.cooldown_total = secs;
+        }
+    }
+
+    /// 卡片当前的状态，用完和金币不足的卡片不能选中
+    pub fn state(&self, gold: u32) -> CardState {
+        if self.is_used_up() {
+            CardState::UsedUp
+        } else if self.cost > gold {
+            CardState::Unaffordable
+        } else if !self.is_ready() {
+            CardState::CoolingDown
+        } else {
+            CardState::Ready
+        }
+    }
+
+    /// 剩余冷却占比，用于卡片上的冷却遮罩，用完的卡片始终遮住
+    pub fn cooldown_fraction(&self) -> f32 {
+        if self.is_used_up() {
+            1.0
+        } else if self.cooldown_total > 0.0 {
+            (self.cooldown_left / self.cooldown_total).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// 卡片的显示状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardState {
+    Ready,
+    CoolingDown,
+    Unaffordable,
+    UsedUp,
+}
+
+impl CardState {
+    pub fn is_selectable(&self) -> bool {
+        matches!(self, CardState::Ready | CardState::CoolingDown)
+    }
+}
+
+/// 卡片投放规则
+#[derive(Resource, Debug, Clone)]
+pub struct CardPlacementRules {
+    pub center: HexMapPosition,  // 投放范围的中心，即初始相机位置
+    pub operation_cooldown: f32, // 每次投放后所有卡片的公共冷却（秒）
+}
+
+impl CardPlacementRules {
+    pub fn in_range(&self, card: &EntityCardInfo, pos: &HexMapPosition) -> bool {
+        card.placement_radius
+            .is_none_or(|radius| hex_distance(&self.center, pos) <= radius)
+    }
+
+    /// 检查卡片能否作用于指定地块，返回作用的实体，不能作用时返回提示信息
+    pub fn check(
+        &self,
+        card: &EntityCardInfo,
+        pos: &HexMapPosition,
+        partition: &SpatialPartition,
+        gold: u32,
+    ) -> Result<Option<EntityWithCoord>, &'static str> {
+        if card.is_used_up() {
+            return Err("卡片已用完!");
+        }
+        if !card.is_ready() {
+            return Err("卡片冷却中!");
+        }
+        if !self.in_range(card, pos) {
+            return Err("超出投放范围!");
+        }
+        if !partition.is_revealed(pos) {
+            return Err("区域未探明!");
+        }
+        if card.cost > gold {
+            return Err("金币不足!");
+        }
+        // 迁移卡片选中实体后，下一次点击的是目的地
+        let target = match card.pending {
+            Some(_) => CardTarget::EmptyCell,
+            None => card.action.target(&card.entity_type),
+        };
+        if target == CardTarget::EmptyCell {
+            if partition.is_obstacle(pos) {
+                return Err("地形阻挡!");
+            }
+            if !partition.check_entity_conflict_by_pos(card.entity_type.clone(), pos) {
+                return Err("位置冲突!");
+            }
+            return Ok(None);
+        }
+        match partition.entity_of_type_at(pos, &card.entity_type) {
+            Some(found) => Ok(Some(found)),
+            None => Err("没有可作用的目标!"),
+        }
+    }
+
+    /// 卡片可以作用的地块，不考虑位置冲突和地块上的实体
+    pub fn allowed_cells(
+        &self,
+        card: &EntityCardInfo,
+        partition: &SpatialPartition,
+    ) -> Vec<HexMapPosition> {
+        let cells = match card.placement_radius {
+            Some(radius) => hex_spiral(self.center, radius),
+            None => {
+                let config = &partition.config;
+                (0..config.height as i32)
+                    .flat_map(|y| (0..config.width as i32).map(move |x| HexMapPosition::new(x, y)))
+                    .collect()
+            }
+        };
+        cells
+            .into_iter()
+            .filter(|pos| partition.is_valid_position(pos) && partition.is_revealed(pos))
+            .collect()
+    }
+
+    /// 投放成功后扣除使用次数并开始卡片自身的冷却
+    pub fn consume(&self, card: &mut EntityCardInfo) {
+        if let Some(remaining) = card.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(1);
+        }
+        card.start_cooldown(card.cooldown);
+    }
+}
+
+// 卡片上的冷却遮罩，高度随剩余冷却缩短
+#[derive(Component)]
+struct CardCooldownMask;
+
+// 卡片右上角的剩余次数
+#[derive(Component)]
+struct CardUsesText;
+
+// 不可用时变灰的卡片底图和图标
+#[derive(Component)]
+struct CardGreyOut;
+
+// 金币不足时变红的费用
+#[derive(Component)]
+struct CardCostText;
+
+// 悬停卡片时显示的说明文字
+#[derive(Component)]
+struct CardTooltip(String);
+
+#[derive(Component)]
+struct CardTooltipNode;
+
+// 点击不可用的卡片时左右抖动
+#[derive(Component)]
+struct CardShake(Timer);
+
+const CARD_COST_COLOR: Color = Color::srgb(1.00, 0.76, 0.76);
+const CARD_SCROLL_LINE: f32 = 40.0;
+
 // #[derive(AsBindGroup, Asset, TypePath, Debug, Clone)]
 // pub struct FlowUIMaterial {
 //     // Uniform bindings must implement `ShaderType`, which will be used to convert the value to
@@ -56,7 +258,15 @@ impl Plugin for EntityCardsPlugin {
             .add_systems(Startup, load_card_assets)
             .add_systems(
                 Update,
-                (handle_card_onclick)
+                (
+                    handle_card_onclick,
+                    auto_deselect_card_system,
+                    tick_card_cooldown_system,
+                    update_card_ui_system,
+                    shake_card_system,
+                    scroll_cards_system,
+                    card_tooltip_system,
+                )
                     .in_set(SceneSystemSet::GameSystems)
                     .chain(),
             ); // 确保按顺序执行
@@ -64,12 +274,15 @@ impl Plugin for EntityCardsPlugin {
 }
 
 const SELECTED_BOARDER_COLOR: Color = Color::srgba(0.33, 1.00, 0.50, 0.83);
+/// 选中卡片后无操作自动取消选中的时间（秒）
+const CARD_IDLE_TIMEOUT: f32 = 5.0;
 
 pub fn spawn_card_ui(
     mut commands: Commands,
     card_assets: Res<CardAssets>,
     level_loader: ResMut<LevelLoader>,
     level_data: Res<Assets<LevelConfigAsset>>,
+    grid_config: Res<HexGridConfig>,
     ui_root: Query<Entity, With<GameSceneUIRoot>>,
     sprite_manager: Res<SpriteManager>,
     // mut materials: ResMut<Assets<CustomMaterial>>,
@@ -78,6 +291,14 @@ pub fn spawn_card_ui(
     let parent = ui_root.single().unwrap();
 
     let cards = level_config.useable_cards.clone();
+    // 和生成实体使用同一份物种属性，卡片提示与投放出的动物一致
+    let species = SpeciesConfig::from_level(level_config);
+    commands.insert_resource(CardPlacementRules {
+        center: HexMapPosition::from(
+            grid_config.level_to_grid(level_config.startup_camera_pos.unwrap_or_default()),
+        ),
+        operation_cooldown: level_config.operation_cooldown,
+    });
 
     commands.entity(parent).with_children(|parent| {
         parent
@@ -92,99 +313,214 @@ pub fn spawn_card_ui(
                     height: Val::Px(120.0),
                     flex_direction: FlexDirection::Row,
                     align_items: AlignItems::Center,
-                    justify_content: JustifyContent::Center,
-                    align_content: AlignContent::Center,
-                    column_gap: Val::Px(10.0),
+                    // 卡片较多时横向滚动
+                    overflow: Overflow::scroll_x(),
                     ..Default::default()
                 },
+                ScrollPosition::default(),
+                RelativeCursorPosition::default(),
                 BackgroundColor(Color::srgba(0.45, 0.45, 0.45, 0.45).into()),
             ))
             .with_children(|root| {
-                for card in cards {
-                    let sprite_name = match card.entity_type {
-                        EntityType::Grass => "grass_normal",
-                        EntityType::Rabbit => "rabbit",
-                        EntityType::Fox => "fox",
-                        _ => panic!("unsupported entity type: {:?}", card.entity_type),
-                    };
-
-                    root.spawn((
-                        Name::new("Card"),
-                        EntityCardInfo {
-                            entity_type: EntityType::Grass,
-                            cost: card.cost,
-                        },
-                        Interaction::default(),
-                        Node {
-                            width: Val::Px(68.0),
-                            height: Val::Px(100.0),
-                            flex_direction: FlexDirection::Column,
-                            overflow: Overflow::clip(),
-                            ..Default::default()
-                        },
-                        BorderRadius::all(Val::Px(5.5)),
-                        Outline {
-                            width: Val::Px(5.),
-                            offset: Val::ZERO,
-                            color: Color::NONE,
-                        },
-                        children![(
-                            ImageNode::new(card_assets.card_bg.clone()),
+                // 左右外边距自动，卡片放得下时居中，放不下时从左侧开始排列
+                root.spawn((
+                    Name::new("Cards Row"),
+                    Node {
+                        flex_direction: FlexDirection::Row,
+                        align_items: AlignItems::Center,
+                        column_gap: Val::Px(10.0),
+                        margin: UiRect::horizontal(Val::Auto),
+                        padding: UiRect::horizontal(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                ))
+                .with_children(|root| {
+                    for card in cards {
+                        let sprite_name = match card.entity_type {
+                            EntityType::Grass => "grass_normal",
+                            EntityType::Rabbit => "rabbit",
+                            EntityType::Fox => "fox",
+                            EntityType::Fungus => "fungus",
+                            _ => panic!("unsupported entity type: {:?}", card.entity_type),
+                        };
+
+                        let tooltip =
+                            CardTooltip(card_tooltip(&card, &level_config.food_chains, &species));
+                        let info = EntityCardInfo::new(&card);
+                        let uses = info
+                            .remaining
+                            .map(|n| format!("x{}", n))
+                            .unwrap_or_default();
+                        // 非投放卡片在左上角标出操作
+                        let label = match info.action {
+                            CardAction::Spawn => String::new(),
+                            action => action.label().to_string(),
+                        };
+                        root.spawn((
+                            Name::new("Card"),
+                            info,
+                            tooltip,
+                            Interaction::default(),
                             Node {
-                                width: Val::Percent(100.),
-                                height: Val::Percent(100.),
+                                width: Val::Px(68.0),
+                                height: Val::Px(100.0),
+                                flex_shrink: 0.0,
                                 flex_direction: FlexDirection::Column,
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                ..default()
+                                overflow: Overflow::clip(),
+                                ..Default::default()
+                            },
+                            BorderRadius::all(Val::Px(5.5)),
+                            Outline {
+                                width: Val::Px(5.),
+                                offset: Val::ZERO,
+                                color: Color::NONE,
                             },
                             children![
                                 (
-                                    sprite_manager.create_image_node_by_name(sprite_name),
-                                    Node {
-                                        width: Val::Px(68.),
-                                        height: Val::Px(68.),
-                                        ..Default::default()
-                                    }
-                                ),
-                                (
+                                    CardGreyOut,
+                                    ImageNode::new(card_assets.card_bg.clone()),
                                     Node {
                                         width: Val::Percent(100.),
-                                        height: Val::Px(40.),
-                                        align_content: AlignContent::Center,
+                                        height: Val::Percent(100.),
+                                        flex_direction: FlexDirection::Column,
                                         align_items: AlignItems::Center,
                                         justify_content: JustifyContent::Center,
-                                        flex_direction: FlexDirection::Row,
-                                        row_gap: Val::Px(2.0),
-                                        ..Default::default()
+                                        ..default()
                                     },
-                                    BackgroundColor(Color::srgba(0.184, 0.145, 0.741, 0.781)),
-                                    BorderRadius::bottom(Val::Px(5.0)),
                                     children![
                                         (
-                                            Text::new(format!("{}", card.cost)),
-                                            TextFont {
-                                                font: card_assets.font.clone(),
-                                                font_size: 20.0,
+                                            CardGreyOut,
+                                            sprite_manager.create_image_node_by_name(sprite_name),
+                                            Node {
+                                                width: Val::Px(68.),
+                                                height: Val::Px(68.),
                                                 ..Default::default()
-                                            },
-                                            TextColor(Color::srgb(1.00, 0.76, 0.76).into()),
+                                            }
                                         ),
                                         (
-                                            ImageNode::new(card_assets.gold_icon.clone()),
                                             Node {
-                                                width: Val::Px(20.0),
-                                                height: Val::Px(20.0),
+                                                width: Val::Percent(100.),
+                                                height: Val::Px(40.),
+                                                align_content: AlignContent::Center,
+                                                align_items: AlignItems::Center,
+                                                justify_content: JustifyContent::Center,
+                                                flex_direction: FlexDirection::Row,
+                                                row_gap: Val::Px(2.0),
                                                 ..Default::default()
-                                            }
-                                        ),
-                                    ]
+                                            },
+                                            BackgroundColor(Color::srgba(
+                                                0.184, 0.145, 0.741, 0.781
+                                            )),
+                                            BorderRadius::bottom(Val::Px(5.0)),
+                                            children![
+                                                (
+                                                    CardCostText,
+                                                    Text::new(format!("{}", card.cost)),
+                                                    TextFont {
+                                                        font: card_assets.font.clone(),
+                                                        font_size: 20.0,
+                                                        ..Default::default()
+                                                    },
+                                                    TextColor(CARD_COST_COLOR),
+                                                ),
+                                                (
+                                                    ImageNode::new(card_assets.gold_icon.clone()),
+                                                    Node {
+                                                        width: Val::Px(20.0),
+                                                        height: Val::Px(20.0),
+                                                        ..Default::default()
+                                                    }
+                                                ),
+                                            ]
+                                        )
+                                    ],
+                                ),
+                                (
+                                    CardCooldownMask,
+                                    Node {
+                                        position_type: PositionType::Absolute,
+                                        top: Val::Px(0.0),
+                                        width: Val::Percent(100.),
+                                        height: Val::Percent(0.),
+                                        ..default()
+                                    },
+                                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.6)),
+                                ),
+                                (
+                                    CardUsesText,
+                                    Text::new(uses),
+                                    TextFont {
+                                        font: card_assets.font.clone(),
+                                        font_size: 16.0,
+                                        ..Default::default()
+                                    },
+                                    TextColor(Color::WHITE),
+                                    Node {
+                                        position_type: PositionType::Absolute,
+                                        top: Val::Px(2.0),
+                                        right: Val::Px(4.0),
+                                        ..default()
+                                    },
+                                ),
+                                (
+                                    Text::new(label),
+                                    TextFont {
+                                        font: card_assets.font.clone(),
+                                        font_size: 16.0,
+                                        ..Default::default()
+                                    },
+                                    TextColor(Color::srgb(1.00, 0.85, 0.30)),
+                                    Node {
+                                        position_type: PositionType::Absolute,
+                                        top: Val::Px(2.0),
+                                        left: Val::Px(4.0),
+                                        ..default()
+                                    },
+                                ),
+                                // 实体名称压在图标下沿
+                                (
+                                    Text::new(card.entity_type.display_name()),
+                                    TextFont {
+                                        font: card_assets.font.clone(),
+                                        font_size: 13.0,
+                                        ..Default::default()
+                                    },
+                                    TextColor(Color::WHITE),
+                                    TextLayout::new_with_justify(JustifyText::Center),
+                                    BackgroundColor(Color::srgba(0.0, 0.0, 0.0, 0.35)),
+                                    Node {
+                                        position_type: PositionType::Absolute,
+                                        top: Val::Px(44.0),
+                                        width: Val::Percent(100.),
+                                        ..default()
+                                    },
                                 )
                             ],
-                        )],
-                    ));
-                }
+                        ));
+                    }
+                });
             });
+
+        // 悬停卡片时显示的物种说明
+        parent.spawn((
+            CardTooltipNode,
+            Node {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(128.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                ..Default::default()
+            },
+            BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.8)),
+            BorderRadius::all(Val::Px(5.0)),
+            Text::new(""),
+            TextFont {
+                font: card_assets.font.clone(),
+                font_size: 16.0,
+                ..Default::default()
+            },
+            TextColor(Color::WHITE),
+            Visibility::Hidden,
+        ));
     });
 }
 
@@ -201,33 +537,361 @@ fn handle_card_onclick(
         (Entity, Ref<Interaction>, Has<CardSelectedMarker>),
         (With<EntityCardInfo>, Changed<Interaction>),
     >,
-    mut outline_query: Query<&mut Outline, With<EntityCardInfo>>,
+    mut outline_query: Query<(&mut Outline, &mut EntityCardInfo)>,
+    gold: Res<LevelGold>,
 ) {
     for (card_entity, interaction, is_selected) in card_query {
         if interaction.eq(&Interaction::Pressed) {
+            // 不可用的卡片抖动提示，不能选中
+            let state = outline_query
+                .get(card_entity)
+                .map_or(CardState::Ready, |(_, info)| info.state(gold.0));
+            if !state.is_selectable() {
+                commands
+                    .entity(card_entity)
+                    .insert(CardShake(Timer::from_seconds(0.4, TimerMode::Once)));
+                show_error_tips(
+                    &mut commands,
+                    match state {
+                        CardState::UsedUp => "卡片已用完!",
+                        _ => "金币不足!",
+                    },
+                );
+                continue;
+            }
+            // 本来就选中了则只去除选中效果，否则先取消之前选中的卡片
+            deselect_card(&mut commands, &mut selected_card, &mut outline_query);
             if is_selected {
-                // 本来就选中了，去除选中效果
-                commands.entity(card_entity).remove::<CardSelectedMarker>();
-                if let Ok(mut outline) = outline_query.get_mut(card_entity) {
-                    outline.color = Color::NONE;
-                }
-                selected_card.0 = None;
                 return;
             }
 
-            if selected_card.0.is_some() {
-                let entity = selected_card.0.unwrap();
-                commands.entity(entity).remove::<CardSelectedMarker>();
-                if let Ok(mut outline) = outline_query.get_mut(entity) {
-                    outline.color = Color::NONE;
-                }
-            }
-
             selected_card.0 = Some(card_entity);
-            if let Ok(mut ol) = outline_query.get_mut(card_entity) {
+            if let Ok((mut ol, _)) = outline_query.get_mut(card_entity) {
                 ol.color = SELECTED_BOARDER_COLOR;
             }
             commands.entity(card_entity).insert(CardSelectedMarker);
         }
     }
 }
+
+// 去除当前选中卡片的选中效果
+fn deselect_card(
+    commands: &mut Commands,
+    selected_card: &mut SelectedCardHolder,
+    outline_query: &mut Query<(&mut Outline, &mut EntityCardInfo)>,
+) {
+    let Some(entity) = selected_card.0.take() else {
+        return;
+    };
+    commands.entity(entity).remove::<CardSelectedMarker>();
+    if let Ok((mut outline, mut info)) = outline_query.get_mut(entity) {
+        outline.color = Color::NONE;
+        info.pending = None;
+    }
+}
+
+// 选中卡片后一段时间没有操作（移动或点击鼠标）则自动取消选中
+fn auto_deselect_card_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mouse_position: Res<GlobalMousePosition>,
+    mut selected_card: ResMut<SelectedCardHolder>,
+    mut outline_query: Query<(&mut Outline, &mut EntityCardInfo)>,
+    mut idle: Local<(f32, Vec2)>,
+) {
+    let (idle_secs, last_pos) = &mut *idle;
+    if selected_card.0.is_none()
+        || selected_card.is_changed()
+        || mouse.get_just_pressed().next().is_some()
+        || mouse_position.pos != *last_pos
+    {
+        *idle_secs = 0.0;
+        *last_pos = mouse_position.pos;
+        return;
+    }
+    *idle_secs += time.delta_secs();
+    if *idle_secs >= CARD_IDLE_TIMEOUT {
+        deselect_card(&mut commands, &mut selected_card, &mut outline_query);
+    }
+}
+
+fn tick_card_cooldown_system(time: Res<Time>, mut query: Query<&mut EntityCardInfo>) {
+    for mut card in query.iter_mut() {
+        if !card.is_ready() {
+            card.cooldown_left = (card.cooldown_left - time.delta_secs()).max(0.0);
+        }
+    }
+}
+
+// 卡片状态或者金币变化时更新冷却遮罩、剩余次数和可用状态
+fn update_card_ui_system(
+    card_query: Query<(Entity, Ref<EntityCardInfo>)>,
+    gold: Res<LevelGold>,
+    children_query: Query<&Children>,
+    mut mask_query: Query<&mut Node, With<CardCooldownMask>>,
+    mut text_query: Query<&mut Text, With<CardUsesText>>,
+    mut cost_query: Query<&mut TextColor, With<CardCostText>>,
+    mut image_query: Query<&mut ImageNode, With<CardGreyOut>>,
+) {
+    for (entity, card) in card_query.iter() {
+        if !card.is_changed() && !gold.is_changed() {
+            continue;
+        }
+        let state = card.state(gold.0);
+        let tint = match state {
+            CardState::Unaffordable | CardState::UsedUp => Color::srgb(0.45, 0.45, 0.45),
+            _ => Color::WHITE,
+        };
+        let cost_color = match state {
+            CardState::Unaffordable => Color::srgb(1.00, 0.25, 0.25),
+            _ => CARD_COST_COLOR,
+        };
+        for child in children_query.iter_descendants(entity) {
+            if let Ok(mut node) = mask_query.get_mut(child) {
+                node.height = Val::Percent(card.cooldown_fraction() * 100.0);
+            }
+            if let Ok(mut text) = text_query.get_mut(child) {
+                text.0 = card
+                    .remaining
+                    .map(|n| format!("x{}", n))
+                    .unwrap_or_default();
+            }
+            if let Ok(mut color) = cost_query.get_mut(child) {
+                color.set_if_neq(TextColor(cost_color));
+            }
+            if let Ok(mut image) = image_query.get_mut(child)
+                && image.color != tint
+            {
+                image.color = tint;
+            }
+        }
+    }
+}
+
+// 抖动幅度随时间衰减，结束后回到原位
+fn shake_card_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut CardShake, &mut Node)>,
+) {
+    for (entity, mut shake, mut node) in query.iter_mut() {
+        shake.0.tick(time.delta());
+        if shake.0.finished() {
+            node.left = Val::Auto;
+            commands.entity(entity).remove::<CardShake>();
+            continue;
+        }
+        let t = shake.0.elapsed_secs();
+        let offset = (t * 60.0).sin() * 6.0 * (1.0 - shake.0.fraction());
+        node.left = Val::Px(offset);
+    }
+}
+
+// 鼠标在卡片栏上时，滚轮横向滚动卡片
+fn scroll_cards_system(
+    mut wheel: EventReader<MouseWheel>,
+    mut root_query: Query<(&mut ScrollPosition, &RelativeCursorPosition), With<CardUIRoot>>,
+) {
+    let Ok((mut scroll, cursor)) = root_query.single_mut() else {
+        wheel.clear();
+        return;
+    };
+    for event in wheel.read() {
+        if !cursor.mouse_over() {
+            continue;
+        }
+        let delta = match event.unit {
+            MouseScrollUnit::Line => (event.x + event.y) * CARD_SCROLL_LINE,
+            MouseScrollUnit::Pixel => event.x + event.y,
+        };
+        scroll.offset_x = (scroll.offset_x - delta).max(0.0);
+    }
+}
+
+// 悬停卡片时在卡片栏上方显示说明
+fn card_tooltip_system(
+    card_query: Query<(&Interaction, &CardTooltip)>,
+    mut tooltip_query: Query<(&mut Node, &mut Text, &mut Visibility), With<CardTooltipNode>>,
+    mouse_position: Res<GlobalMousePosition>,
+) {
+    let Ok((mut node, mut text, mut visibility)) = tooltip_query.single_mut() else {
+        return;
+    };
+    let hovered = card_query
+        .iter()
+        .find(|(interaction, _)| **interaction != Interaction::None);
+    let Some((_, tooltip)) = hovered else {
+        visibility.set_if_neq(Visibility::Hidden);
+        return;
+    };
+    if text.0 != tooltip.0 {
+        text.0 = tooltip.0.clone();
+    }
+    node.left = Val::Px(mouse_position.pos.x);
+    visibility.set_if_neq(Visibility::Inherited);
+}
+
+// 卡片说明：名称、属性和食物链中的捕食关系
+fn card_tooltip(
+    card: &CardConfig,
+    food_chains: &HashMap<EntityType, EntityFoodRelations>,
+    species: &SpeciesConfig,
+) -> String {
+    let name = card.entity_type.display_name();
+    let mut lines = vec![match card.action {
+        CardAction::Spawn => name.to_string(),
+        action => format!("{}{}", action.label(), name),
+    }];
+    if let Some(stats) = species.stats(&card.entity_type) {
+        lines.push(format!(
+            "速度: {}格/秒  视野: {}格",
+            stats.move_speed, stats.vision_radius
+        ));
+        lines.push(format!(
+            "能量: {}  基础代谢: {}/秒",
+            stats.max_energy, stats.basal
+        ));
+    }
+    let names = |types: &mut dyn Iterator<Item = &EntityType>| {
+        let mut names: Vec<_> = types.map(|t| t.display_name()).collect();
+        names.sort();
+        names.join("、")
+    };
+    if let Some(relations) = food_chains.get(&card.entity_type) {
+        if !relations.preys_on.is_empty() {
+            lines.push(format!("食物: {}", names(&mut relations.preys_on.iter())));
+        }
+        if !relations.predators_of.is_empty() {
+            lines.push(format!(
+                "天敌: {}",
+                names(&mut relations.predators_of.iter())
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(count_limit: u32, placement_radius: Option<i32>) -> EntityCardInfo {
+        EntityCardInfo::new(&CardConfig {
+            entity_type: EntityType::Rabbit,
+            action: CardAction::Spawn,
+            cost: 2,
+            count_limit,
+            placement_radius,
+            cooldown: 3.0,
+        })
+    }
+
+    #[test]
+    fn test_card_placement_rules() {
+        let partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        let rules = CardPlacementRules {
+            center: HexMapPosition::new(5, 5),
+            operation_cooldown: 1.0,
+        };
+        let near = HexMapPosition::new(6, 5);
+        let far = HexMapPosition::new(9, 5);
+
+        let mut limited = card(1, Some(2));
+        assert_eq!(
+            rules.check(&limited, &far, &partition, 10),
+            Err("超出投放范围!")
+        );
+        assert_eq!(
+            rules.check(&limited, &near, &partition, 1),
+            Err("金币不足!")
+        );
+        assert_eq!(rules.check(&limited, &near, &partition, 10), Ok(None));
+        assert_eq!(rules.allowed_cells(&limited, &partition).len(), 19);
+
+        // 自身冷却比公共冷却长，以较长的为准
+        rules.consume(&mut limited);
+        limited.start_cooldown(rules.operation_cooldown);
+        assert_eq!(limited.cooldown_left, 3.0);
+        assert_eq!(limited.cooldown_fraction(), 1.0);
+        assert_eq!(
+            rules.check(&limited, &near, &partition, 10),
+            Err("卡片已用完!")
+        );
+
+        let mut unlimited = card(0, None);
+        rules.consume(&mut unlimited);
+        assert_eq!(unlimited.remaining, None);
+        assert_eq!(
+            rules.check(&unlimited, &far, &partition, 10),
+            Err("卡片冷却中!")
+        );
+        unlimited.cooldown_left = 0.0;
+        assert_eq!(rules.check(&unlimited, &far, &partition, 10), Ok(None));
+    }
+
+    #[test]
+    fn test_card_action_targets() {
+        let mut partition = SpatialPartition::new(HexGridConfig::new(1.0, 10, 10, 1.0));
+        let rules = CardPlacementRules {
+            center: HexMapPosition::new(5, 5),
+            operation_cooldown: 1.0,
+        };
+        let pos = HexMapPosition::new(5, 5);
+        let rabbit = Entity::from_raw(1);
+        let mut relocate = card(0, None);
+        relocate.action = CardAction::Relocate;
+        assert_eq!(
+            rules.check(&relocate, &pos, &partition, 10),
+            Err("没有可作用的目标!")
+        );
+
+        // 先选中动物，再选择没有动物的目的地
+        partition.insert_cache_entity(rabbit, &pos, EntityType::Rabbit);
+        let target = rules.check(&relocate, &pos, &partition, 10).unwrap();
+        assert_eq!(target.map(|t| t.entity), Some(rabbit));
+        relocate.pending = target;
+        assert_eq!(
+            rules.check(&relocate, &pos, &partition, 10),
+            Err("位置冲突!")
+        );
+        let dest = HexMapPosition::new(6, 5);
+        assert_eq!(rules.check(&relocate, &dest, &partition, 10), Ok(None));
+        // 绝育只对植物有效
+        assert!(CardAction::Sterilise.applies_to(&EntityType::Grass));
+        assert!(!CardAction::Sterilise.applies_to(&EntityType::Rabbit));
+        partition.set_obstacle(&dest, true);
+        assert_eq!(
+            rules.check(&relocate, &dest, &partition, 10),
+            Err("地形阻挡!")
+        );
+    }
+
+    #[test]
+    fn test_card_state_and_tooltip() {
+        let mut rabbit = card(1, None);
+        assert_eq!(rabbit.state(1), CardState::Unaffordable);
+        assert_eq!(rabbit.state(10), CardState::Ready);
+        rabbit.start_cooldown(1.0);
+        assert!(rabbit.state(10).is_selectable());
+        rabbit.remaining = Some(0);
+        assert_eq!(rabbit.state(10), CardState::UsedUp);
+
+        let mut relations = EntityFoodRelations::default();
+        relations.preys_on.insert(EntityType::Grass);
+        relations.predators_of.insert(EntityType::Fox);
+        let mut food_chains = HashMap::new();
+        food_chains.insert(EntityType::Rabbit, relations);
+        let config = CardConfig {
+            entity_type: EntityType::Rabbit,
+            action: CardAction::Feed,
+            cost: 0,
+            count_limit: 0,
+            placement_radius: None,
+            cooldown: 0.0,
+        };
+        let tooltip = card_tooltip(&config, &food_chains, &SpeciesConfig::default());
+        assert!(tooltip.starts_with("喂食兔子\n"));
+        assert!(tooltip.ends_with("食物: 青草\n天敌: 狐狸"));
+    }
+}
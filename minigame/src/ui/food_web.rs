@@ -0,0 +1,569 @@
+//! 食物网面板
+//!
+//! 按关卡的food_chains把物种画成节点连线图：生产者在最下层，按捕食关系逐层往上，
+//! 节点颜色区分生产者、消费者和顶级消费者，并显示实时的种群数量。
+//! 关卡引用的食物链模板中定义了营养级、坐标或连线样式时，以模板为准。
+//! 发生捕食时，沿着猎物到捕食者的连线播放一个能量流动的光点。
+
+use bevy::platform::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use bevy::ui::{PositionType, Val};
+
+use crate::core::GameState;
+use crate::core::components::EntityType;
+use crate::core::economy::EcologyEvent;
+use crate::core::hex_grid::SpatialPartition;
+use crate::level::config::{EntityFoodRelations, LevelConfigAsset};
+use crate::level::food_chain::FoodChain;
+use crate::level::loader::LevelLoader;
+use crate::scenes::scene_selector::SceneSystemSet;
+use crate::scenes::{GameSceneUIRoot, setup_game_scene};
+
+const PANEL_SIZE: Vec2 = Vec2::new(360.0, 280.0);
+const NODE_RADIUS: f32 = 24.0;
+const EDGE_DOT_SIZE: f32 = 3.0;
+const EDGE_DOT_SPACING: f32 = 10.0;
+const PULSE_SIZE: f32 = 8.0;
+const PULSE_DURATION: f32 = 0.8; // 光点从猎物移动到捕食者的时间（秒）
+const MAX_PULSES: usize = 32;
+
+const PRODUCER_COLOR: Color = Color::srgb(0.30, 0.69, 0.31);
+const CONSUMER_COLOR: Color = Color::srgb(1.00, 0.60, 0.00);
+const APEX_COLOR: Color = Color::srgb(0.90, 0.25, 0.25);
+const EDGE_COLOR: Color = Color::srgba(0.85, 0.85, 0.85, 0.5);
+const PULSE_COLOR: Color = Color::srgb(1.0, 0.95, 0.4);
+
+/// 物种在食物网中的角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrophicRole {
+    Producer, // 不捕食其它物种
+    Consumer, // 既捕食也被捕食
+    Apex,     // 没有天敌
+}
+
+impl TrophicRole {
+    fn color(&self) -> Color {
+        match self {
+            TrophicRole::Producer => PRODUCER_COLOR,
+            TrophicRole::Consumer => CONSUMER_COLOR,
+            TrophicRole::Apex => APEX_COLOR,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FoodWebNode {
+    pub entity_type: EntityType,
+    pub level: u32, // 营养级，生产者为1
+    pub role: TrophicRole,
+    pub pos: Vec2, // 在面板中的相对位置(0-1)，y轴向下
+}
+
+/// 能量从猎物流向捕食者的连线
+#[derive(Debug, Clone, PartialEq)]
+pub struct FoodWebEdge {
+    pub prey: usize,
+    pub predator: usize,
+    pub color: Color,
+    pub thickness: f32, // 连线光点的大小(px)
+}
+
+/// 由捕食关系计算出的食物网布局
+#[derive(Debug, Clone, Default)]
+pub struct FoodWeb {
+    pub nodes: Vec<FoodWebNode>,
+    pub edges: Vec<FoodWebEdge>,
+}
+
+impl FoodWeb {
+    pub fn from_level(level: &LevelConfigAsset) -> Self {
+        Self::build(&level.food_chains, &level.food_chain_docs)
+    }
+
+    #[cfg(test)]
+    pub fn from_relations(food_chains: &HashMap<EntityType, EntityFoodRelations>) -> Self {
+        Self::build(food_chains, &[])
+    }
+
+    /// 多条模板定义了同一物种或关系时，以先引用的模板为准
+    fn build(
+        food_chains: &HashMap<EntityType, EntityFoodRelations>,
+        templates: &[FoodChain],
+    ) -> Self {
+        // preys_on和predators_of是同一关系的两个方向，合并后去重
+        let mut links = HashSet::new();
+        for (entity_type, relations) in food_chains.iter() {
+            for prey in relations.preys_on.iter() {
+                links.insert((prey.clone(), entity_type.clone()));
+            }
+            for predator in relations.predators_of.iter() {
+                links.insert((entity_type.clone(), predator.clone()));
+            }
+        }
+        let mut species: Vec<EntityType> = food_chains
+            .keys()
+            .chain(links.iter().flat_map(|(prey, predator)| [prey, predator]))
+            .filter(|t| **t != EntityType::Cell)
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        species.sort_by_key(|t| t.to_string());
+        let index_of = |t: &EntityType| species.iter().position(|s| s == t);
+        let mut edges: Vec<(usize, usize)> = links
+            .iter()
+            .filter_map(|(prey, predator)| Some((index_of(prey)?, index_of(predator)?)))
+            .collect();
+        edges.sort();
+
+        // 模板中的营养级和坐标覆盖计算结果，坐标范围为0-100
+        let mut levels = vec![1u32; species.len()];
+        let mut positions: Vec<Option<Vec2>> = vec![None; species.len()];
+        let mut overridden = HashSet::new();
+        for node in templates.iter().flat_map(|chain| chain.nodes.iter()) {
+            if let Some(i) = index_of(&node.entity)
+                && overridden.insert(i)
+            {
+                levels[i] = node.level.max(1);
+                positions[i] = node
+                    .position
+                    .map(|pos| (pos / 100.0).clamp(Vec2::ZERO, Vec2::ONE));
+            }
+        }
+
+        // 其余物种的营养级比所有猎物中最高的再高一级，最多迭代物种数次以防环路
+        let cap = levels.iter().copied().max().unwrap_or(1) + species.len() as u32;
+        for _ in 0..species.len() {
+            let mut changed = false;
+            for &(prey, predator) in edges.iter() {
+                let level = (levels[prey] + 1).min(cap);
+                if !overridden.contains(&predator) && levels[predator] < level {
+                    levels[predator] = level;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let max_level = levels.iter().copied().max().unwrap_or(1);
+        let mut rows: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, level) in levels.iter().enumerate() {
+            rows.entry(*level).or_default().push(i);
+        }
+        let nodes = species
+            .iter()
+            .enumerate()
+            .map(|(i, entity_type)| {
+                let has_prey = edges.iter().any(|(_, predator)| *predator == i);
+                let has_predator = edges.iter().any(|(prey, _)| *prey == i);
+                let role = match (has_prey, has_predator) {
+                    (false, _) => TrophicRole::Producer,
+                    (true, true) => TrophicRole::Consumer,
+                    (true, false) => TrophicRole::Apex,
+                };
+                // 同一营养级的物种在一行中均匀排开，生产者在最下面
+                let row = &rows[&levels[i]];
+                let column = row.iter().position(|j| *j == i).unwrap_or_default();
+                let pos = positions[i].unwrap_or(Vec2::new(
+                    (column as f32 + 0.5) / row.len() as f32,
+                    1.0 - (levels[i] as f32 - 0.5) / max_level as f32,
+                ));
+                FoodWebNode {
+                    entity_type: entity_type.clone(),
+                    level: levels[i],
+                    role,
+                    pos,
+                }
+            })
+            .collect();
+
+        let edges = edges
+            .into_iter()
+            .map(|(prey, predator)| {
+                let (color, thickness) = templates
+                    .iter()
+                    .filter(|chain| {
+                        chain
+                            .links()
+                            .any(|(a, b, _)| *a == species[prey] && *b == species[predator])
+                    })
+                    .find_map(|chain| chain.visual.as_ref())
+                    .map_or((EDGE_COLOR, EDGE_DOT_SIZE), |visual| {
+                        let color = Srgba::hex(&visual.color).map_or_else(
+                            |_| {
+                                warn!("food chain edge color {} is not a hex color", visual.color);
+                                EDGE_COLOR
+                            },
+                            Color::from,
+                        );
+                        (color, visual.thickness.max(1.0))
+                    });
+                FoodWebEdge {
+                    prey,
+                    predator,
+                    color,
+                    thickness,
+                }
+            })
+            .collect();
+
+        Self { nodes, edges }
+    }
+
+    fn position_of(&self, entity_type: &EntityType) -> Option<Vec2> {
+        self.nodes
+            .iter()
+            .find(|node| node.entity_type == *entity_type)
+            .map(|node| node.pos * PANEL_SIZE)
+    }
+}
+
+/// 食物网面板，保存布局供光点动画使用
+#[derive(Component)]
+pub struct FoodWebPanel(FoodWeb);
+
+/// 显示或隐藏面板的按钮
+#[derive(Component)]
+pub struct FoodWebToggle;
+
+/// 节点中的种群数量文本
+#[derive(Component)]
+struct FoodWebPopulation(EntityType);
+
+/// 沿连线移动的能量光点
+#[derive(Component)]
+struct FoodWebPulse {
+    from: Vec2,
+    to: Vec2,
+    elapsed: f32,
+}
+
+pub struct FoodWebPlugin;
+
+impl Plugin for FoodWebPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(GameState::Playing),
+            spawn_food_web_panel.after(setup_game_scene),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_food_web_system,
+                update_population_system,
+                (spawn_predation_pulse_system, animate_pulse_system).chain(),
+            )
+                .in_set(SceneSystemSet::GameSystems),
+        );
+    }
+}
+
+fn spawn_food_web_panel(
+    mut commands: Commands,
+    level_loader: Res<LevelLoader>,
+    level_data: Res<Assets<LevelConfigAsset>>,
+    ui_root: Query<Entity, With<GameSceneUIRoot>>,
+    asset_server: Res<AssetServer>,
+) {
+    let (Some(level_config), Ok(parent)) =
+        (level_data.get(&level_loader.level_data), ui_root.single())
+    else {
+        return;
+    };
+    let web = FoodWeb::from_level(level_config);
+    let font = asset_server.load("fonts/msyh.ttc");
+    let text_font = |size: f32| TextFont {
+        font: font.clone(),
+        font_size: size,
+        ..Default::default()
+    };
+
+    // 按钮和面板放在HUD下方右侧
+    let container = commands
+        .spawn((
+            Name::new("Food Web"),
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.0),
+                top: Val::Percent(16.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::End,
+                row_gap: Val::Px(6.0),
+                ..Default::default()
+            },
+            ChildOf(parent),
+        ))
+        .id();
+    commands.spawn((
+        FoodWebToggle,
+        Button,
+        Node {
+            padding: UiRect::axes(Val::Px(10.0), Val::Px(4.0)),
+            ..Default::default()
+        },
+        BorderRadius::all(Val::Px(6.0)),
+        BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.7)),
+        children![(Text::new("食物网 (F)"), text_font(16.0))],
+        ChildOf(container),
+    ));
+
+    let mut edge_dots = Vec::new();
+    for edge in web.edges.iter() {
+        let from = web.nodes[edge.prey].pos * PANEL_SIZE;
+        let to = web.nodes[edge.predator].pos * PANEL_SIZE;
+        // 连线只画在两个节点的圆之间
+        let length = from.distance(to) - NODE_RADIUS * 2.0;
+        let count = (length / EDGE_DOT_SPACING).max(0.0) as usize;
+        let dir = (to - from).normalize_or_zero();
+        for i in 0..=count {
+            let dot = from + dir * (NODE_RADIUS + i as f32 * EDGE_DOT_SPACING);
+            edge_dots.push((dot, edge.color, edge.thickness));
+        }
+    }
+
+    let nodes = web.nodes.clone();
+    let panel = commands
+        .spawn((
+            FoodWebPanel(web),
+            Node {
+                width: Val::Px(PANEL_SIZE.x),
+                height: Val::Px(PANEL_SIZE.y),
+                ..Default::default()
+            },
+            BorderRadius::all(Val::Px(8.0)),
+            BackgroundColor(Color::srgba(0.1, 0.1, 0.1, 0.8)),
+            Visibility::Hidden,
+            ChildOf(container),
+        ))
+        .id();
+    // 先生成连线，节点显示在连线上面
+    for (dot, color, size) in edge_dots {
+        commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(dot.x - size / 2.0),
+                top: Val::Px(dot.y - size / 2.0),
+                width: Val::Px(size),
+                height: Val::Px(size),
+                ..Default::default()
+            },
+            BackgroundColor(color),
+            ChildOf(panel),
+        ));
+    }
+    for node in nodes {
+        let center = node.pos * PANEL_SIZE;
+        commands.spawn((
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(center.x - NODE_RADIUS),
+                top: Val::Px(center.y - NODE_RADIUS),
+                width: Val::Px(NODE_RADIUS * 2.0),
+                height: Val::Px(NODE_RADIUS * 2.0),
+                flex_direction: FlexDirection::Column,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            BorderRadius::MAX,
+            BackgroundColor(node.role.color()),
+            children![
+                // 节点名称后标出营养级
+                (
+                    Text::new(format!(
+                        "{} {}级",
+                        node.entity_type.display_name(),
+                        node.level
+                    )),
+                    text_font(12.0),
+                ),
+                (
+                    FoodWebPopulation(node.entity_type.clone()),
+                    Text::new("0"),
+                    text_font(12.0),
+                )
+            ],
+            ChildOf(panel),
+        ));
+    }
+}
+
+// 点击按钮或按F键切换面板
+fn toggle_food_web_system(
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<FoodWebToggle>)>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut panel_q: Query<&mut Visibility, With<FoodWebPanel>>,
+) {
+    let pressed = interaction_q.iter().any(|i| *i == Interaction::Pressed);
+    if !pressed && !keyboard.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    for mut visibility in panel_q.iter_mut() {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn update_population_system(
+    partition: Res<SpatialPartition>,
+    mut text_q: Query<(&mut Text, &FoodWebPopulation)>,
+) {
+    for (mut text, population) in text_q.iter_mut() {
+        let count = partition.count_by_type(&population.0).to_string();
+        if text.0 != count {
+            text.0 = count;
+        }
+    }
+}
+
+// 面板可见时，每次捕食沿对应连线发出一个光点
+fn spawn_predation_pulse_system(
+    mut commands: Commands,
+    mut events: EventReader<EcologyEvent>,
+    panel_q: Query<(Entity, &FoodWebPanel, &Visibility)>,
+    pulse_q: Query<(), With<FoodWebPulse>>,
+) {
+    let Ok((panel, web, visibility)) = panel_q.single() else {
+        events.clear();
+        return;
+    };
+    let mut pulses = pulse_q.iter().count();
+    for event in events.read() {
+        let EcologyEvent::Predation { predator, prey } = event else {
+            continue;
+        };
+        if *visibility == Visibility::Hidden || pulses >= MAX_PULSES {
+            continue;
+        }
+        let (Some(from), Some(to)) = (web.0.position_of(prey), web.0.position_of(predator)) else {
+            continue;
+        };
+        commands.spawn((
+            FoodWebPulse {
+                from,
+                to,
+                elapsed: 0.0,
+            },
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Px(PULSE_SIZE),
+                height: Val::Px(PULSE_SIZE),
+                ..Default::default()
+            },
+            BorderRadius::MAX,
+            BackgroundColor(PULSE_COLOR),
+            ChildOf(panel),
+        ));
+        pulses += 1;
+    }
+}
+
+fn animate_pulse_system(
+    mut commands: Commands,
+    mut pulse_q: Query<(Entity, &mut FoodWebPulse, &mut Node)>,
+    time: Res<Time>,
+) {
+    for (entity, mut pulse, mut node) in pulse_q.iter_mut() {
+        pulse.elapsed += time.delta_secs();
+        if pulse.elapsed >= PULSE_DURATION {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let pos = pulse.from.lerp(pulse.to, pulse.elapsed / PULSE_DURATION);
+        node.left = Val::Px(pos.x - PULSE_SIZE / 2.0);
+        node.top = Val::Px(pos.y - PULSE_SIZE / 2.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_food_web_layout() {
+        let mut food_chains = HashMap::new();
+        let mut relations = EntityFoodRelations::default();
+        relations.preys_on.insert(EntityType::Grass);
+        relations.predators_of.insert(EntityType::Fox);
+        food_chains.insert(EntityType::Rabbit, relations);
+        // 同一关系从两个方向重复配置时只算一条边
+        let mut relations = EntityFoodRelations::default();
+        relations.preys_on.insert(EntityType::Rabbit);
+        food_chains.insert(EntityType::Fox, relations);
+
+        let web = FoodWeb::from_relations(&food_chains);
+        assert_eq!(web.nodes.len(), 3);
+        assert_eq!(web.edges.len(), 2);
+
+        let node = |t: EntityType| web.nodes.iter().find(|n| n.entity_type == t).unwrap();
+        let (grass, rabbit, fox) = (
+            node(EntityType::Grass),
+            node(EntityType::Rabbit),
+            node(EntityType::Fox),
+        );
+        assert_eq!(
+            (grass.level, rabbit.level, fox.level),
+            (1, 2, 3),
+            "营养级按捕食关系逐层递增"
+        );
+        assert_eq!(grass.role, TrophicRole::Producer);
+        assert_eq!(rabbit.role, TrophicRole::Consumer);
+        assert_eq!(fox.role, TrophicRole::Apex);
+        // 生产者在最下面
+        assert!(grass.pos.y > rabbit.pos.y && rabbit.pos.y > fox.pos.y);
+        assert!(web.edges.iter().all(|e| e.color == EDGE_COLOR));
+    }
+
+    #[test]
+    fn test_food_web_template_layout() {
+        let chain = FoodChain::from_ron(
+            br##"(
+                id: "chain_1",
+                version: "2.0",
+                nodes: [
+                    (id: "n1", entity: (type: grass), level: 1, position: Some((30.0, 80.0))),
+                    (id: "n2", entity: (type: rabbit), level: 3),
+                ],
+                edges: [(source: "n1", target: "n2", energy: 0.3)],
+                visual: Some((color: "#4CAF50", thickness: 4.0)),
+            )"##,
+        )
+        .unwrap();
+        let mut level = LevelConfigAsset::default();
+        level.merge_food_chain(chain);
+        level
+            .food_chains
+            .entry(EntityType::Fox)
+            .or_default()
+            .preys_on
+            .insert(EntityType::Rabbit);
+
+        let web = FoodWeb::from_level(&level);
+        let index = |t: EntityType| web.nodes.iter().position(|n| n.entity_type == t).unwrap();
+        let (grass, rabbit, fox) = (
+            index(EntityType::Grass),
+            index(EntityType::Rabbit),
+            index(EntityType::Fox),
+        );
+        assert_eq!(web.nodes[grass].pos, Vec2::new(0.3, 0.8));
+        assert_eq!(web.nodes[rabbit].level, 3);
+        assert_eq!(web.nodes[fox].level, 4);
+        let edge = |prey, predator| {
+            web.edges
+                .iter()
+                .find(|e| e.prey == prey && e.predator == predator)
+                .unwrap()
+        };
+        // 模板中的连线使用模板的样式，其它连线使用默认样式
+        assert_eq!(
+            edge(grass, rabbit).color,
+            Color::from(Srgba::hex("4CAF50").unwrap())
+        );
+        assert_eq!(edge(grass, rabbit).thickness, 4.0);
+        assert_eq!(edge(rabbit, fox).color, EDGE_COLOR);
+    }
+}
@@ -3,6 +3,10 @@ use bevy::prelude::*;
 use bevy::ui::{FlexDirection, PositionType, UiRect, Val};
 
 use crate::core::GameState;
+use crate::core::economy::Economy;
+use crate::core::environment::Environment;
+use crate::core::fog::FogOfWar;
+use crate::core::interaction::map_cell_click_system;
 use crate::scenes::scene_selector::SceneSystemSet;
 use crate::scenes::{LevelGold, setup_game_scene};
 
@@ -21,6 +25,22 @@ pub struct TimeText;
 #[derive(Component)]
 pub struct GoldLable;
 
+/// 金币收入速率文本
+#[derive(Component)]
+pub struct IncomeRateLabel;
+
+/// 季节、昼夜和天气文本，开启环境系统的关卡才有
+#[derive(Component)]
+pub struct EnvironmentLabel;
+
+/// 侦察按钮，开启战争迷雾的关卡才有
+#[derive(Component)]
+pub struct ScoutButton;
+
+const SCOUTING_BORDER_COLOR: Color = Color::srgb(1.0, 0.85, 0.2);
+const INCOME_COLOR: Color = Color::srgb(0.45, 0.9, 0.45);
+const LOSS_COLOR: Color = Color::srgb(1.0, 0.45, 0.45);
+
 /// HUD资源
 #[derive(Default, Resource)]
 pub struct HudAssets {
@@ -46,6 +66,12 @@ impl Plugin for HudPlugin {
                     update_time_text,
                     update_gold_label_text
                         .run_if(resource_exists::<LevelGold>.and(resource_changed::<LevelGold>)),
+                    update_income_rate_text.run_if(resource_exists::<Economy>),
+                    update_environment_text.run_if(resource_exists::<Environment>),
+                    // 在地图点击之后处理，避免点击按钮的同一帧就执行侦察
+                    handle_scout_button
+                        .after(map_cell_click_system)
+                        .run_if(resource_exists::<FogOfWar>),
                 )
                     .in_set(SceneSystemSet::GameSystems),
             );
@@ -56,6 +82,8 @@ fn setup_hud(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     mut hud_assets: ResMut<HudAssets>,
+    fog: Option<Res<FogOfWar>>,
+    environment: Option<Res<Environment>>,
 ) {
     // 加载字体资源
     hud_assets.font = asset_server.load("fonts/msyhbd.ttc");
@@ -121,10 +149,62 @@ fn setup_hud(
                         },
                         TextColor(WHITE.into()),
                         GoldLable,
+                    ),
+                    (
+                        Text::new(""),
+                        TextFont {
+                            font: hud_assets.font.clone(),
+                            font_size: 18.0,
+                            ..Default::default()
+                        },
+                        TextColor(INCOME_COLOR),
+                        Node {
+                            margin: UiRect::left(Val::Px(8.0)),
+                            ..Default::default()
+                        },
+                        IncomeRateLabel,
                     )
                 ],
             ));
 
+            if environment.is_some() {
+                parent.spawn((
+                    EnvironmentLabel,
+                    Text::new(""),
+                    TextFont {
+                        font: hud_assets.font.clone(),
+                        font_size: 20.0,
+                        ..Default::default()
+                    },
+                    TextColor(WHITE.into()),
+                ));
+            }
+
+            // 侦察按钮，点击后再点击地图执行侦察
+            if let Some(fog) = fog {
+                parent.spawn((
+                    ScoutButton,
+                    Button,
+                    Node {
+                        padding: UiRect::axes(Val::Px(12.0), Val::Px(6.0)),
+                        border: UiRect::all(Val::Px(3.0)),
+                        ..Default::default()
+                    },
+                    BorderColor(Color::BLACK),
+                    BorderRadius::all(Val::Px(8.0)),
+                    BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
+                    children![(
+                        Text::new(format!("侦察 {}", fog.config.scout_cost)),
+                        TextFont {
+                            font: hud_assets.font.clone(),
+                            font_size: 24.0,
+                            ..Default::default()
+                        },
+                        TextColor(WHITE.into()),
+                    )],
+                ));
+            }
+
             // 时间文本
             parent.spawn((
                 TimeText,
@@ -168,6 +248,76 @@ fn update_gold_label_text(mut query: Query<&mut Text, With<GoldLable>>, gold: Re
     }
 }
 
+// 收入速率每帧都可能变化，只在显示的文本不同时才修改
+fn update_income_rate_text(
+    mut query: Query<(&mut Text, &mut TextColor), With<IncomeRateLabel>>,
+    economy: Res<Economy>,
+) {
+    let rate = economy.rate;
+    let label = if rate.abs() < 0.05 {
+        String::new()
+    } else {
+        format!("{:+.1}/秒", rate)
+    };
+    for (mut text, mut color) in &mut query {
+        if text.0 != label {
+            text.0 = label.clone();
+            color.0 = if rate < 0.0 { LOSS_COLOR } else { INCOME_COLOR };
+        }
+    }
+}
+
+// 天气显示剩余时间，文本不同时才修改
+fn update_environment_text(
+    mut query: Query<&mut Text, With<EnvironmentLabel>>,
+    environment: Res<Environment>,
+) {
+    let mut parts = Vec::new();
+    if let Some(season) = environment.season() {
+        parts.push(season.season.display_name().to_string());
+    }
+    if environment.config.day_length > 0.0 {
+        let day = if environment.is_night() { "夜" } else { "昼" };
+        parts.push(day.to_string());
+    }
+    if let Some(weather) = environment.weather.as_ref() {
+        parts.push(format!(
+            "{} {:.0}秒",
+            weather.weather.display_name(),
+            weather.remaining.max(0.0).ceil()
+        ));
+    }
+    let label = parts.join(" · ");
+    for mut text in &mut query {
+        if text.0 != label {
+            text.0 = label.clone();
+        }
+    }
+}
+
+// 点击侦察按钮切换侦察状态，高亮边框表示下一次点击地图将执行侦察
+fn handle_scout_button(
+    interaction_q: Query<&Interaction, (Changed<Interaction>, With<ScoutButton>)>,
+    mut border_q: Query<&mut BorderColor, With<ScoutButton>>,
+    mut fog: ResMut<FogOfWar>,
+) {
+    for interaction in interaction_q.iter() {
+        if *interaction == Interaction::Pressed {
+            fog.scouting = !fog.scouting;
+        }
+    }
+    // 侦察执行后由点击系统取消侦察状态，这里同步按钮
+    if fog.is_changed() {
+        for mut border in border_q.iter_mut() {
+            border.0 = if fog.scouting {
+                SCOUTING_BORDER_COLOR
+            } else {
+                Color::BLACK
+            };
+        }
+    }
+}
+
 fn cleanup_hud(mut commands: Commands, query: Query<Entity, With<HudRoot>>) {
     let _ = query.single().map(|entity| {
         commands.entity(entity).despawn();
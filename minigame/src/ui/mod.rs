@@ -3,15 +3,19 @@
 //! 负责游戏界面的渲染和交互
 
 mod animal_state_ui;
+mod behave_debugger;
 pub mod cards;
 mod error_tips;
+mod food_web;
 pub mod hud;
 mod progress_bar;
 mod progress_bar_material;
 
 pub use animal_state_ui::AnimalStateUIPanel;
+pub use behave_debugger::BehaveDebuggerPlugin;
 pub use cards::*;
 pub use error_tips::ErrorTipsPlugin;
 pub use error_tips::show_error_tips;
+pub use food_web::FoodWebPlugin;
 pub use progress_bar::*;
 pub use progress_bar_material::ProgressBarMaterial;